@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN "admin" BOOL NOT NULL DEFAULT false;
+UPDATE users SET "admin" = true WHERE "role" = 'admin';
+ALTER TABLE users DROP COLUMN "role";
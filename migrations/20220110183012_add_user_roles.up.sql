@@ -0,0 +1,6 @@
+-- `admin` is replaced by a role so we can have more than two kinds of users
+ALTER TABLE users
+    ADD COLUMN "role" VARCHAR NOT NULL DEFAULT 'user'
+        CONSTRAINT valid_role CHECK ( "role" IN ('admin', 'user', 'viewer') );
+UPDATE users SET "role" = 'admin' WHERE "admin";
+ALTER TABLE users DROP COLUMN "admin";
@@ -0,0 +1,5 @@
+ALTER TABLE tags DROP COLUMN IF EXISTS household_id;
+ALTER TABLE accounts DROP COLUMN IF EXISTS household_id;
+DROP TABLE IF EXISTS household_invites;
+DROP TABLE IF EXISTS household_members;
+DROP TABLE IF EXISTS households;
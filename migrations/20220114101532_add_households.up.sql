@@ -0,0 +1,34 @@
+-- A household is a group of users that can share accounts and tags
+CREATE TABLE IF NOT EXISTS households
+(
+    id         SERIAL PRIMARY KEY,
+    name       TEXT    NOT NULL,
+    created_by uuid    NOT NULL REFERENCES users (id)
+);
+
+CREATE TABLE IF NOT EXISTS household_members
+(
+    household_id INTEGER NOT NULL REFERENCES households (id) ON DELETE CASCADE,
+    user_id      uuid    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- owner: can manage members, editor: can change shared data, viewer: can only see shared data
+    "role"       VARCHAR NOT NULL DEFAULT 'editor'
+        CONSTRAINT valid_member_role CHECK ( "role" IN ('owner', 'editor', 'viewer') ),
+
+    PRIMARY KEY (household_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS household_invites
+(
+    id           SERIAL PRIMARY KEY,
+    household_id INTEGER NOT NULL REFERENCES households (id) ON DELETE CASCADE,
+    user_id      uuid    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    invited_by   uuid    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    "role"       VARCHAR NOT NULL DEFAULT 'editor'
+        CONSTRAINT valid_invite_role CHECK ( "role" IN ('owner', 'editor', 'viewer') ),
+
+    UNIQUE (household_id, user_id)
+);
+
+-- Accounts and tags that belong to a household are visible to all of its members
+ALTER TABLE accounts ADD COLUMN household_id INTEGER REFERENCES households (id) ON DELETE SET NULL;
+ALTER TABLE tags ADD COLUMN household_id INTEGER REFERENCES households (id) ON DELETE SET NULL;
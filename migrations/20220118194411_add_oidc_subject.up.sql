@@ -0,0 +1,2 @@
+-- The `sub` claim of the user's OpenID Connect identity, if they have linked one
+ALTER TABLE users ADD COLUMN oidc_subject VARCHAR UNIQUE;
@@ -0,0 +1,18 @@
+-- Every change to the data and every login attempt
+CREATE TABLE IF NOT EXISTS audit_log
+(
+    id          BIGSERIAL PRIMARY KEY,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- Who did it, null for failed logins of unknown users
+    user_id     uuid REFERENCES users (id) ON DELETE SET NULL,
+    action      VARCHAR     NOT NULL,
+    entity_type VARCHAR     NOT NULL,
+    entity_id   VARCHAR,
+    before      JSONB,
+    after       JSONB,
+    ip          VARCHAR,
+    user_agent  TEXT
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_user_id_idx ON audit_log (user_id, created_at);
+CREATE INDEX IF NOT EXISTS audit_log_entity_idx ON audit_log (entity_type, entity_id);
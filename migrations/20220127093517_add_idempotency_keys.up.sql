@@ -0,0 +1,16 @@
+-- Responses to requests with an `Idempotency-Key` header, replayed when the request is retried
+CREATE TABLE IF NOT EXISTS idempotency_keys
+(
+    user_id      uuid        NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    key          VARCHAR     NOT NULL,
+    -- SHA-256 of the method, path and body, a retry must be the same request
+    request_hash BYTEA       NOT NULL,
+    -- Null while the first request is still running
+    status       SMALLINT,
+    headers      JSONB,
+    body         BYTEA,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, key)
+);
+
+CREATE INDEX IF NOT EXISTS idempotency_keys_created_at_idx ON idempotency_keys (created_at);
@@ -0,0 +1,3 @@
+-- Bumped on every update, sent as the `ETag` so concurrent updates can be detected with `If-Match`
+ALTER TABLE accounts ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE tags ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,30 @@
+-- Money moving from one account to another, third party accounts are used for the outside world
+CREATE TABLE IF NOT EXISTS transactions
+(
+    id             SERIAL PRIMARY KEY,
+    -- Deleting an account deletes its transactions, the balance of the other account is left as it is
+    source_id      INTEGER     NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    destination_id INTEGER     NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    amount         NUMERIC     NOT NULL,
+    description    TEXT,
+    happened_on    DATE        NOT NULL DEFAULT CURRENT_DATE,
+    -- Who entered it
+    user_id        uuid        NOT NULL REFERENCES users (id),
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    CONSTRAINT positive_amount CHECK ( amount > 0 ),
+    CONSTRAINT different_accounts CHECK ( source_id <> destination_id )
+);
+
+CREATE INDEX IF NOT EXISTS transactions_source_id_idx ON transactions (source_id, happened_on);
+CREATE INDEX IF NOT EXISTS transactions_destination_id_idx ON transactions (destination_id, happened_on);
+
+CREATE TABLE IF NOT EXISTS transaction_tags
+(
+    transaction_id INTEGER NOT NULL REFERENCES transactions (id) ON DELETE CASCADE,
+    tag_id         INTEGER NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+
+    PRIMARY KEY (transaction_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS transaction_tags_tag_id_idx ON transaction_tags (tag_id);
@@ -0,0 +1,18 @@
+-- How a user wants to see their data, a user without a row has the defaults
+CREATE TABLE IF NOT EXISTS user_settings
+(
+    user_id            uuid     NOT NULL PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    -- ISO 4217 code, amounts are shown without a currency when there is none
+    currency           VARCHAR(3),
+    -- Language of the pages, the one of the browser is used when there is none
+    locale             VARCHAR,
+    first_day_of_week  VARCHAR  NOT NULL DEFAULT 'monday',
+    -- Budget periods start on this day of the month, the last day of shorter months is used
+    period_start_day   SMALLINT NOT NULL DEFAULT 1,
+    -- Where new transactions go when no account is picked
+    default_account_id INTEGER  REFERENCES accounts (id) ON DELETE SET NULL,
+    -- light, dark or system
+    theme              VARCHAR  NOT NULL DEFAULT 'light',
+
+    CONSTRAINT valid_period_start_day CHECK ( period_start_day BETWEEN 1 AND 31 )
+);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS transactions_user_id_client_id_idx;
+
+ALTER TABLE transactions
+    DROP COLUMN IF EXISTS client_id;
@@ -0,0 +1,7 @@
+-- Made up by the browser when a transaction is entered, so an entry that is sent twice,
+-- like one that was queued while offline, is only saved once
+ALTER TABLE transactions
+    ADD COLUMN IF NOT EXISTS client_id uuid;
+
+CREATE UNIQUE INDEX IF NOT EXISTS transactions_user_id_client_id_idx
+    ON transactions (user_id, client_id) WHERE client_id IS NOT NULL;
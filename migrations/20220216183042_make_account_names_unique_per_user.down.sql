@@ -0,0 +1,2 @@
+ALTER TABLE accounts DROP CONSTRAINT IF EXISTS accounts_user_id_name_key;
+ALTER TABLE accounts ADD CONSTRAINT accounts_name_key UNIQUE (name);
@@ -0,0 +1,3 @@
+-- Every user names their accounts the way they want, only their own names have to be different
+ALTER TABLE accounts DROP CONSTRAINT IF EXISTS accounts_name_key;
+ALTER TABLE accounts ADD CONSTRAINT accounts_user_id_name_key UNIQUE (user_id, name);
@@ -0,0 +1,6 @@
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS transactions_source_id_fkey;
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS transactions_destination_id_fkey;
+ALTER TABLE transactions
+    ADD CONSTRAINT transactions_source_id_fkey FOREIGN KEY (source_id) REFERENCES accounts (id) ON DELETE CASCADE;
+ALTER TABLE transactions
+    ADD CONSTRAINT transactions_destination_id_fkey FOREIGN KEY (destination_id) REFERENCES accounts (id) ON DELETE CASCADE;
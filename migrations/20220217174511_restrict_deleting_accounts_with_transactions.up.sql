@@ -0,0 +1,7 @@
+-- An account with transactions can't be deleted, that would leave the balances of the other accounts and the tags wrong
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS transactions_source_id_fkey;
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS transactions_destination_id_fkey;
+ALTER TABLE transactions
+    ADD CONSTRAINT transactions_source_id_fkey FOREIGN KEY (source_id) REFERENCES accounts (id) ON DELETE RESTRICT;
+ALTER TABLE transactions
+    ADD CONSTRAINT transactions_destination_id_fkey FOREIGN KEY (destination_id) REFERENCES accounts (id) ON DELETE RESTRICT;
@@ -0,0 +1,2 @@
+-- Webhooks subscribed to account.balance_below get it when the available money of an account goes below this
+ALTER TABLE webhooks ADD COLUMN IF NOT EXISTS balance_threshold NUMERIC;
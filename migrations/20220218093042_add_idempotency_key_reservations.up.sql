@@ -0,0 +1,4 @@
+-- Tells the request that reserved a key apart from a retry that reserved it again after the lease ran out
+ALTER TABLE idempotency_keys ADD COLUMN IF NOT EXISTS reservation uuid NOT NULL DEFAULT gen_random_uuid();
+-- The request that reserved the key keeps moving this forward while it runs, the lease is counted from it
+ALTER TABLE idempotency_keys ADD COLUMN IF NOT EXISTS refreshed_at TIMESTAMPTZ NOT NULL DEFAULT now();
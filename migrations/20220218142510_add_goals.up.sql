@@ -0,0 +1,19 @@
+-- Money set aside in a normal account to save up for something.
+-- It's still part of the total money of the account, but not of its available money.
+CREATE TABLE IF NOT EXISTS goals
+(
+    id          SERIAL PRIMARY KEY NOT NULL,
+    name        TEXT COLLATE "ignore_case" NOT NULL,
+    description TEXT,
+    -- The goal is reached once its balance gets there
+    target      NUMERIC NOT NULL,
+    balance     NUMERIC NOT NULL DEFAULT '0.0'::numeric,
+    account_id  INTEGER NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    user_id     uuid    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    version     INTEGER NOT NULL DEFAULT 1,
+    CONSTRAINT goals_user_id_name_key UNIQUE (user_id, name),
+    CONSTRAINT positive_target CHECK ( target > 0 ),
+    CONSTRAINT not_negative_balance CHECK ( balance >= 0 )
+);
+
+CREATE INDEX IF NOT EXISTS goals_account_id_idx ON goals (account_id);
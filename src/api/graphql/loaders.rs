@@ -0,0 +1,225 @@
+//! Batched loading of the relations between objects, every key asked for while resolving a level of the query is
+//! loaded with a single query
+
+use std::collections::HashMap;
+
+use {
+    async_graphql::dataloader::Loader, bigdecimal::BigDecimal, chrono::Utc, sqlx::PgPool,
+    uuid::Uuid,
+};
+
+use super::gql_error;
+use crate::{
+    crud,
+    models::{
+        account::AccountRow,
+        household::{HouseholdMemberRow, HouseholdRow},
+        tag::TagRow,
+        transaction::TransactionDetails,
+    },
+};
+
+/// Loads everything for a single request, only what the user can see
+pub(crate) struct Loaders {
+    pub(crate) db: PgPool,
+    pub(crate) user_id: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct HouseholdId(pub(crate) i32);
+
+/// The members of a household
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct MembersOf(pub(crate) i32);
+
+/// The accounts shared with a household
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct AccountsOf(pub(crate) i32);
+
+/// The tags shared with a household
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct TagsOf(pub(crate) i32);
+
+/// The tags on the transactions of an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct TagsUsedOn(pub(crate) i32);
+
+/// The latest transactions of an account, as many as `limit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct TransactionsOf {
+    pub(crate) account_id: i32,
+    pub(crate) limit: u64,
+}
+
+/// What was spent with a tag in the budget period of the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct SpentOn(pub(crate) i32);
+
+impl Loader<HouseholdId> for Loaders {
+    type Value = HouseholdRow;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[HouseholdId],
+    ) -> Result<HashMap<HouseholdId, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let households = crud::households::fetch_households_by_id(&self.db, self.user_id, &ids)
+            .await
+            .map_err(gql_error)?;
+
+        Ok(households
+            .into_iter()
+            .map(|household| (HouseholdId(household.id), household))
+            .collect())
+    }
+}
+
+impl Loader<MembersOf> for Loaders {
+    type Value = Vec<HouseholdMemberRow>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[MembersOf],
+    ) -> Result<HashMap<MembersOf, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let memberships = crud::households::fetch_memberships(&self.db, self.user_id, &ids)
+            .await
+            .map_err(gql_error)?;
+
+        let mut members: HashMap<_, Vec<_>> = HashMap::new();
+        for membership in memberships {
+            members
+                .entry(MembersOf(membership.household_id))
+                .or_default()
+                .push(membership.into());
+        }
+        Ok(members)
+    }
+}
+
+impl Loader<AccountsOf> for Loaders {
+    type Value = Vec<AccountRow>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[AccountsOf],
+    ) -> Result<HashMap<AccountsOf, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let accounts = crud::accounts::fetch_household_accounts(&self.db, self.user_id, &ids)
+            .await
+            .map_err(gql_error)?;
+
+        let mut by_household: HashMap<_, Vec<_>> = HashMap::new();
+        for account in accounts {
+            if let Some(household_id) = account.household_id {
+                by_household
+                    .entry(AccountsOf(household_id))
+                    .or_default()
+                    .push(account);
+            }
+        }
+        Ok(by_household)
+    }
+}
+
+impl Loader<TagsOf> for Loaders {
+    type Value = Vec<TagRow>;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[TagsOf]) -> Result<HashMap<TagsOf, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let tags = crud::tags::fetch_household_tags(&self.db, self.user_id, &ids)
+            .await
+            .map_err(gql_error)?;
+
+        let mut by_household: HashMap<_, Vec<_>> = HashMap::new();
+        for tag in tags {
+            if let Some(household_id) = tag.household_id {
+                by_household
+                    .entry(TagsOf(household_id))
+                    .or_default()
+                    .push(tag);
+            }
+        }
+        Ok(by_household)
+    }
+}
+
+impl Loader<TagsUsedOn> for Loaders {
+    type Value = Vec<TagRow>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[TagsUsedOn],
+    ) -> Result<HashMap<TagsUsedOn, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let tags = crud::tags::fetch_account_tags(&self.db, self.user_id, &ids)
+            .await
+            .map_err(gql_error)?;
+
+        Ok(tags
+            .into_iter()
+            .map(|(account_id, tags)| (TagsUsedOn(account_id), tags))
+            .collect())
+    }
+}
+
+impl Loader<TransactionsOf> for Loaders {
+    type Value = Vec<TransactionDetails>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[TransactionsOf],
+    ) -> Result<HashMap<TransactionsOf, Self::Value>, Self::Error> {
+        // One query for every limit that was asked for, usually there's only one
+        let mut by_limit: HashMap<u64, Vec<i32>> = HashMap::new();
+        for key in keys {
+            by_limit.entry(key.limit).or_default().push(key.account_id);
+        }
+
+        let mut transactions = HashMap::new();
+        for (limit, ids) in by_limit {
+            let found = crud::transactions::fetch_accounts_transactions(
+                &self.db,
+                self.user_id,
+                &ids,
+                limit,
+            )
+            .await
+            .map_err(gql_error)?;
+            transactions.extend(
+                found
+                    .into_iter()
+                    .map(|(account_id, found)| (TransactionsOf { account_id, limit }, found)),
+            );
+        }
+        Ok(transactions)
+    }
+}
+
+impl Loader<SpentOn> for Loaders {
+    type Value = BigDecimal;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[SpentOn]) -> Result<HashMap<SpentOn, Self::Value>, Self::Error> {
+        let ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let settings = crud::settings::fetch_settings(&self.db, self.user_id)
+            .await
+            .map_err(gql_error)?;
+        let since = settings.period_start(Utc::today().naive_utc());
+        let spent = crud::transactions::fetch_tag_spent_since(&self.db, &ids, since)
+            .await
+            .map_err(gql_error)?;
+
+        // Tags without transactions in the period have spent nothing
+        Ok(ids
+            .into_iter()
+            .map(|id| (SpentOn(id), spent.get(&id).cloned().unwrap_or_default()))
+            .collect())
+    }
+}
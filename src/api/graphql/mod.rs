@@ -0,0 +1,136 @@
+//! A read-only GraphQL api, for clients that want the accounts and tags together with their households in a single
+//! request
+
+mod loaders;
+mod types;
+
+use {
+    async_graphql::{
+        dataloader::DataLoader, Context, EmptyMutation, EmptySubscription, ErrorExtensions, Object,
+        Request, Response, Result, Schema,
+    },
+    axum::extract::Extension,
+    sqlx::PgPool,
+    uuid::Uuid,
+};
+
+use self::{
+    loaders::Loaders,
+    types::{Account, Household, Tag},
+};
+use crate::{crud, error::Problem, extract::Json, models::user::UserClaims, CommonError};
+
+pub(crate) type BudgetmanSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Deepest a query can go, `households { accounts { household { ... } } }` can go on forever otherwise
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 500;
+
+pub(crate) fn schema() -> BudgetmanSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// Post /api/graphql
+#[utoipa::path(
+    post,
+    path = "/api/graphql",
+    tag = "graphql",
+    request_body(content = Object, description = "A GraphQL request, with its `query` and the `variables` it uses"),
+    responses((status = 200, description = "The `data` of the query, with the `errors` it ran into", body = Object)),
+)]
+pub(crate) async fn handle_graphql(
+    Extension(schema): Extension<BudgetmanSchema>,
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    Json(req): Json<Request>,
+) -> Json<Response> {
+    let loaders = Loaders {
+        db: db.clone(),
+        user_id: user.id,
+    };
+    let req = req
+        .data(db)
+        .data(CurrentUser(user.id))
+        .data(DataLoader::new(loaders, tokio::spawn));
+
+    Json(schema.execute(req).await)
+}
+
+/// The id of the user making the request
+struct CurrentUser(Uuid);
+
+/// The message of the error, with the same `code` the REST api would have returned in its extensions
+fn gql_error(err: CommonError) -> async_graphql::Error {
+    let problem = Problem::from(err);
+    let code = problem.code;
+    async_graphql::Error::new(problem.detail).extend_with(|_, ext| ext.set("code", code.as_ref()))
+}
+
+/// Turns `NotFound` into null, for fields that get a single object by its id
+fn optional<T>(res: Result<T, CommonError>) -> Result<Option<T>> {
+    match res {
+        Ok(value) => Ok(Some(value)),
+        Err(CommonError::NotFound) => Ok(None),
+        Err(err) => Err(gql_error(err)),
+    }
+}
+
+fn context<'a>(ctx: &Context<'a>) -> (&'a PgPool, Uuid) {
+    (
+        ctx.data_unchecked::<PgPool>(),
+        ctx.data_unchecked::<CurrentUser>().0,
+    )
+}
+
+pub(crate) struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The accounts the user owns or that are shared with them
+    async fn accounts(&self, ctx: &Context<'_>) -> Result<Vec<Account>> {
+        let (db, user_id) = context(ctx);
+        let accounts = crud::accounts::fetch_accounts(db, &user_id)
+            .await
+            .map_err(gql_error)?;
+        Ok(accounts.into_iter().map(Account).collect())
+    }
+
+    async fn account(&self, ctx: &Context<'_>, id: i32) -> Result<Option<Account>> {
+        let (db, user_id) = context(ctx);
+        let account = optional(crud::accounts::fetch_account(db, &user_id, id).await)?;
+        Ok(account.map(Account))
+    }
+
+    /// The tags the user owns or that are shared with them
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        let (db, user_id) = context(ctx);
+        let tags = crud::tags::fetch_tags(db, user_id)
+            .await
+            .map_err(gql_error)?;
+        Ok(tags.into_iter().map(Tag).collect())
+    }
+
+    async fn tag(&self, ctx: &Context<'_>, id: i32) -> Result<Option<Tag>> {
+        let (db, user_id) = context(ctx);
+        let tag = optional(crud::tags::fetch_tag(db, user_id, id).await)?;
+        Ok(tag.map(Tag))
+    }
+
+    /// The households the user is a member of
+    async fn households(&self, ctx: &Context<'_>) -> Result<Vec<Household>> {
+        let (db, user_id) = context(ctx);
+        let households = crud::households::fetch_households(db, user_id)
+            .await
+            .map_err(gql_error)?;
+        Ok(households.into_iter().map(Household).collect())
+    }
+
+    async fn household(&self, ctx: &Context<'_>, id: i32) -> Result<Option<Household>> {
+        let (db, user_id) = context(ctx);
+        let household = optional(crud::households::fetch_household(db, user_id, id).await)?;
+        Ok(household.map(|details| Household(details.household)))
+    }
+}
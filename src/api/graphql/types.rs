@@ -0,0 +1,270 @@
+//! The objects of the schema, wrappers around the rows the REST api returns
+
+use {
+    async_graphql::{dataloader::DataLoader, Context, Enum, Object, Result, ID},
+    bigdecimal::BigDecimal,
+};
+
+use super::loaders::{
+    AccountsOf, HouseholdId, Loaders, MembersOf, SpentOn, TagsOf, TagsUsedOn, TransactionsOf,
+};
+use crate::models::{
+    account::AccountRow,
+    household::{HouseholdMemberRow, HouseholdRow, MemberRole},
+    tag::TagRow,
+    transaction::TransactionDetails,
+};
+
+pub(crate) struct Account(pub(crate) AccountRow);
+
+/// Money is a decimal number as a string, so it's never rounded
+#[Object]
+impl Account {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    /// Null for adhoc accounts
+    async fn available_money(&self) -> Option<String> {
+        self.0.available_money.as_ref().map(ToString::to_string)
+    }
+
+    /// Null for adhoc accounts
+    async fn total_money(&self) -> Option<String> {
+        self.0.total_money.as_ref().map(ToString::to_string)
+    }
+
+    /// Id of the owner
+    async fn user_id(&self) -> ID {
+        ID(self.0.user_id.to_string())
+    }
+
+    async fn is_adhoc(&self) -> bool {
+        self.0.is_adhoc
+    }
+
+    /// Same as the `ETag` of the account in the REST api
+    async fn version(&self) -> i32 {
+        self.0.version
+    }
+
+    /// The household it's shared with
+    async fn household(&self, ctx: &Context<'_>) -> Result<Option<Household>> {
+        household(ctx, self.0.household_id).await
+    }
+
+    /// The tags on its transactions
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        let loader = ctx.data_unchecked::<DataLoader<Loaders>>();
+        let tags = loader.load_one(TagsUsedOn(self.0.id)).await?;
+        Ok(tags.unwrap_or_default().into_iter().map(Tag).collect())
+    }
+
+    /// The latest transactions from or to it, newest first
+    #[graphql(complexity = "limit as usize * child_complexity")]
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 10, validator(minimum = 1, maximum = 50))] limit: u64,
+    ) -> Result<Vec<Transaction>> {
+        let loader = ctx.data_unchecked::<DataLoader<Loaders>>();
+        let key = TransactionsOf {
+            account_id: self.0.id,
+            limit,
+        };
+        let transactions = loader.load_one(key).await?;
+        Ok(transactions
+            .unwrap_or_default()
+            .into_iter()
+            .map(Transaction)
+            .collect())
+    }
+}
+
+pub(crate) struct Transaction(pub(crate) TransactionDetails);
+
+#[Object]
+impl Transaction {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    /// The account the money came from
+    async fn source_id(&self) -> i32 {
+        self.0.source_id
+    }
+
+    async fn source_name(&self) -> &str {
+        &self.0.source_name
+    }
+
+    /// The account the money went to
+    async fn destination_id(&self) -> i32 {
+        self.0.destination_id
+    }
+
+    async fn destination_name(&self) -> &str {
+        &self.0.destination_name
+    }
+
+    async fn amount(&self) -> String {
+        self.0.amount.to_string()
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    /// `YYYY-MM-DD`
+    async fn happened_on(&self) -> String {
+        self.0.happened_on.to_string()
+    }
+
+    /// Names of its tags
+    async fn tags(&self) -> &[String] {
+        &self.0.tags
+    }
+}
+
+pub(crate) struct Tag(pub(crate) TagRow);
+
+#[Object]
+impl Tag {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    async fn limit(&self) -> Option<String> {
+        self.0.limit.as_ref().map(ToString::to_string)
+    }
+
+    async fn balance(&self) -> String {
+        self.0.balance.to_string()
+    }
+
+    /// Spent since the start of the budget period of the user
+    async fn spent(&self, ctx: &Context<'_>) -> Result<String> {
+        Ok(spent(ctx, self.0.id).await?.to_string())
+    }
+
+    /// Whether more than the limit was spent this budget period
+    async fn is_over_limit(&self, ctx: &Context<'_>) -> Result<bool> {
+        Ok(self.0.is_over_limit(&spent(ctx, self.0.id).await?))
+    }
+
+    /// Id of the owner
+    async fn user_id(&self) -> ID {
+        ID(self.0.user_id.to_string())
+    }
+
+    /// Same as the `ETag` of the tag in the REST api
+    async fn version(&self) -> i32 {
+        self.0.version
+    }
+
+    /// The household it's shared with
+    async fn household(&self, ctx: &Context<'_>) -> Result<Option<Household>> {
+        household(ctx, self.0.household_id).await
+    }
+}
+
+pub(crate) struct Household(pub(crate) HouseholdRow);
+
+#[Object]
+impl Household {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn created_by(&self) -> ID {
+        ID(self.0.created_by.to_string())
+    }
+
+    async fn members(&self, ctx: &Context<'_>) -> Result<Vec<Member>> {
+        let loader = ctx.data_unchecked::<DataLoader<Loaders>>();
+        let members = loader.load_one(MembersOf(self.0.id)).await?;
+        Ok(members
+            .unwrap_or_default()
+            .into_iter()
+            .map(Member)
+            .collect())
+    }
+
+    /// The accounts shared with the household
+    async fn accounts(&self, ctx: &Context<'_>) -> Result<Vec<Account>> {
+        let loader = ctx.data_unchecked::<DataLoader<Loaders>>();
+        let accounts = loader.load_one(AccountsOf(self.0.id)).await?;
+        Ok(accounts
+            .unwrap_or_default()
+            .into_iter()
+            .map(Account)
+            .collect())
+    }
+
+    /// The tags shared with the household
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        let loader = ctx.data_unchecked::<DataLoader<Loaders>>();
+        let tags = loader.load_one(TagsOf(self.0.id)).await?;
+        Ok(tags.unwrap_or_default().into_iter().map(Tag).collect())
+    }
+}
+
+pub(crate) struct Member(pub(crate) HouseholdMemberRow);
+
+#[Object]
+impl Member {
+    async fn user_id(&self) -> ID {
+        ID(self.0.user_id.to_string())
+    }
+
+    async fn username(&self) -> &str {
+        &self.0.username
+    }
+
+    async fn role(&self) -> Role {
+        self.0.role.into()
+    }
+}
+
+/// What a member is allowed to do with the data of a household
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "MemberRole")]
+pub(crate) enum Role {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+async fn household(ctx: &Context<'_>, id: Option<i32>) -> Result<Option<Household>> {
+    let id = match id {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+    let loader = ctx.data_unchecked::<DataLoader<Loaders>>();
+    Ok(loader.load_one(HouseholdId(id)).await?.map(Household))
+}
+
+async fn spent(ctx: &Context<'_>, tag_id: i32) -> Result<BigDecimal> {
+    let loader = ctx.data_unchecked::<DataLoader<Loaders>>();
+    Ok(loader.load_one(SpentOn(tag_id)).await?.unwrap_or_default())
+}
@@ -1,53 +1,93 @@
 use {
     axum::{
-        extract::{Extension, Path},
+        extract::Extension,
         http::StatusCode,
+        response::sse::{Event, KeepAlive, Sse},
     },
-    serde_json::{json, Value},
+    futures_util::stream::{self, Stream},
     sqlx::PgPool,
+    tokio::sync::broadcast::error::RecvError,
+    uuid::Uuid,
 };
 
 use crate::{
     crud,
-    extract::{Json, Query},
-    models::{account::*, tag::TagRow, user::UserClaims},
+    extract::{IfMatch, Json, Path, Query, ValidatedJson},
+    models::{
+        account::*,
+        audit::{AuditLogPage, AuditLogQuery, ClientInfo},
+        goal::GoalRow,
+        household::{HouseholdDetails, HouseholdInviteRow, HouseholdRow},
+        settings::UserSettings,
+        tag::TagRow,
+        transaction::SavedTransaction,
+        user::{AdminUser, RequirePermission, UserClaims, UserRow, Write},
+        webhook::{WebhookDeliveryRow, WebhookRow},
+    },
     requests::*,
+    responses::*,
+    utils::events,
     Error,
 };
 
 /// Post /api/v1/login
+#[utoipa::path(
+    post,
+    path = "/api/v1/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 400, description = "Wrong credentials", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The username or password is missing", body = Problem, content_type = "application/problem+json"),
+    ),
+    security(()),
+)]
 pub(crate) async fn handle_login(
-    Json(req): Json<crate::requests::LoginRequest>,
+    ValidatedJson(req): ValidatedJson<LoginRequest>,
     Extension(db): Extension<PgPool>,
-) -> Result<Json<Value>, Error> {
-    let jwt = crud::login(req, &db).await.map_err(Error::ApiError)?;
-    Ok(Json(json!({ "access_token": jwt })))
+    client: ClientInfo,
+) -> Result<Json<LoginResponse>, Error> {
+    let access_token = crud::login(req, &db, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(LoginResponse { access_token }))
 }
 
 /// Get /api/v1/accounts
+#[utoipa::path(
+    get,
+    path = "/api/v1/accounts",
+    tag = "accounts",
+    params(AccountTypeQuery),
+    responses(
+        (status = 200, body = AccountList),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
 pub(crate) async fn get_accounts(
     Extension(db): Extension<PgPool>,
     user: UserClaims,
     Query(type_q): Query<AccountTypeQuery>,
-) -> Result<Json<Value>, Error> {
+) -> Result<Json<AccountList>, Error> {
     let accounts = match type_q.account_type {
         AccountType::Any => {
             let accounts = crud::accounts::fetch_accounts(&db, &user.id)
                 .await
                 .map_err(Error::ApiError)?;
-            json!(accounts)
+            AccountList::Any(accounts)
         }
         AccountType::Adhoc => {
             let accounts = crud::accounts::fetch_adhoc_accounts(&db, &user.id)
                 .await
                 .map_err(Error::ApiError)?;
-            json!(accounts)
+            AccountList::Adhoc(accounts)
         }
         AccountType::Normal => {
             let accounts = crud::accounts::fetch_normal_accounts(&db, &user.id)
                 .await
                 .map_err(Error::ApiError)?;
-            json!(accounts)
+            AccountList::Normal(accounts)
         }
     };
 
@@ -55,38 +95,66 @@ pub(crate) async fn get_accounts(
 }
 
 /// Get /api/v1/accounts/:id
+#[utoipa::path(
+    get,
+    path = "/api/v1/accounts/{id}",
+    tag = "accounts",
+    params(("id" = i32, Path, description = "Id of the account")),
+    responses(
+        (status = 200, body = AccountRow, headers(("ETag" = String, description = "Changes every time the account is updated"))),
+        (status = 304, description = "The account didn't change since the `ETag` in `If-None-Match`"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
 pub(crate) async fn get_specific_account(
     Extension(db): Extension<PgPool>,
     user: UserClaims,
     Path(id): Path<i32>,
-) -> Result<Json<AccountRow>, Error> {
+) -> Result<Tagged<Json<AccountRow>>, Error> {
     let account = crud::accounts::fetch_account(&db, &user.id, id)
         .await
         .map_err(Error::ApiError)?;
-    Ok(Json(account))
+    Ok(Tagged {
+        version: account.version,
+        body: Json(account),
+    })
 }
 
 /// Post /api/v1/accounts
+#[utoipa::path(
+    post,
+    path = "/api/v1/accounts",
+    tag = "accounts",
+    request_body = AccountCreateRequest,
+    responses(
+        (status = 200, body = Created),
+        (status = 422, description = "The account is not valid, like a normal account without `starting_money`", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Viewers can't create accounts", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is an account with that name", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
 pub(crate) async fn create_account(
     Extension(db): Extension<PgPool>,
-    user: UserClaims,
-    Json(account): Json<AccountCreateRequest>,
-) -> Result<Json<Value>, Error> {
-    if !account.is_adhoc && account.starting_money.is_none() {
-        let err = (
-            StatusCode::BAD_REQUEST,
-            "Normal account needs initial money",
-        )
-            .into();
-        return Err(Error::ApiError(err));
-    }
-    let id = crud::accounts::create_account(&db, user.id, account)
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(account): ValidatedJson<AccountCreateRequest>,
+) -> Result<Json<Created>, Error> {
+    let account = crud::accounts::create_account(&db, user.id, account, &client)
         .await
         .map_err(Error::ApiError)?;
-    Ok(Json(json!({ "id": id })))
+    Ok(Json(Created { id: account.id }))
 }
 
 /// Get /api/v1/tags
+#[utoipa::path(
+    get,
+    path = "/api/v1/tags",
+    tag = "tags",
+    responses(
+        (status = 200, body = [TagRow]),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
 pub(crate) async fn get_tags(
     Extension(db): Extension<PgPool>,
     user: UserClaims,
@@ -97,26 +165,1016 @@ pub(crate) async fn get_tags(
     Ok(Json(tags))
 }
 
-/// Get /api/v1/tags
+/// Get /api/v1/tags/:id
+#[utoipa::path(
+    get,
+    path = "/api/v1/tags/{id}",
+    tag = "tags",
+    params(("id" = i32, Path, description = "Id of the tag")),
+    responses(
+        (status = 200, body = TagRow, headers(("ETag" = String, description = "Changes every time the tag is updated"))),
+        (status = 304, description = "The tag didn't change since the `ETag` in `If-None-Match`"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
 pub(crate) async fn get_specific_tag(
     Extension(db): Extension<PgPool>,
     user: UserClaims,
     Path(id): Path<i32>,
-) -> Result<Json<TagRow>, Error> {
-    let tags = crud::tags::fetch_tag(&db, user.id, id)
+) -> Result<Tagged<Json<TagRow>>, Error> {
+    let tag = crud::tags::fetch_tag(&db, user.id, id)
         .await
         .map_err(Error::ApiError)?;
-    Ok(Json(tags))
+    Ok(Tagged {
+        version: tag.version,
+        body: Json(tag),
+    })
 }
 
-/// Get /api/v1/tags
+/// Post /api/v1/tags
+#[utoipa::path(
+    post,
+    path = "/api/v1/tags",
+    tag = "tags",
+    request_body = TagCreate,
+    responses(
+        (status = 200, body = Created),
+        (status = 403, description = "Viewers can't create tags", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is a tag with that name", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The tag is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
 pub(crate) async fn create_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(to_create): ValidatedJson<TagCreate>,
+) -> Result<Json<Created>, Error> {
+    let tag = crud::tags::create_tag(&db, user.id, to_create, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(Created { id: tag.id }))
+}
+
+/// Put /api/v1/accounts/:id/household
+#[utoipa::path(
+    put,
+    path = "/api/v1/accounts/{id}/household",
+    tag = "accounts",
+    params(
+        ("id" = i32, Path, description = "Id of the account"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being changed"),
+    ),
+    request_body = ShareRequest,
+    responses(
+        (status = 204, description = "The account is shared with the household, or not shared anymore", headers(("ETag" = String))),
+        (status = 412, description = "The account was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Not allowed to write to the household", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn share_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(req): ValidatedJson<ShareRequest>,
+) -> Result<Tagged<StatusCode>, Error> {
+    let account = crud::accounts::set_account_household(
+        &db,
+        user.id,
+        id,
+        req.household_id,
+        &if_match,
+        &client,
+    )
+    .await
+    .map_err(Error::ApiError)?;
+    Ok(Tagged {
+        version: account.version,
+        body: StatusCode::NO_CONTENT,
+    })
+}
+
+/// Put /api/v1/accounts/:id
+#[utoipa::path(
+    put,
+    path = "/api/v1/accounts/{id}",
+    tag = "accounts",
+    params(
+        ("id" = i32, Path, description = "Id of the account"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being changed"),
+    ),
+    request_body = AccountUpdate,
+    responses(
+        (status = 200, body = AccountRow, headers(("ETag" = String))),
+        (status = 403, description = "Not allowed to write to the household", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is a account with that name", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The account was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The account is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn update_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(update): ValidatedJson<AccountUpdate>,
+) -> Result<Tagged<Json<AccountRow>>, Error> {
+    let account = crud::accounts::update_account(&db, user.id, id, update, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Tagged {
+        version: account.version,
+        body: Json(account),
+    })
+}
+
+/// Delete /api/v1/accounts/:id
+#[utoipa::path(
+    delete,
+    path = "/api/v1/accounts/{id}",
+    tag = "accounts",
+    params(
+        ("id" = i32, Path, description = "Id of the account"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being deleted"),
+    ),
+    responses(
+        (status = 204, description = "The account was deleted"),
+        (status = 404, description = "There is no account with that id that the user owns", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The account has transactions, they have to be deleted first", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The account was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn delete_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+) -> Result<StatusCode, Error> {
+    crud::accounts::delete_account(&db, user.id, id, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Put /api/v1/tags/:id/household
+#[utoipa::path(
+    put,
+    path = "/api/v1/tags/{id}/household",
+    tag = "tags",
+    params(
+        ("id" = i32, Path, description = "Id of the tag"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being changed"),
+    ),
+    request_body = ShareRequest,
+    responses(
+        (status = 204, description = "The tag is shared with the household, or not shared anymore", headers(("ETag" = String))),
+        (status = 412, description = "The tag was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Not allowed to write to the household", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn share_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(req): ValidatedJson<ShareRequest>,
+) -> Result<Tagged<StatusCode>, Error> {
+    let tag = crud::tags::set_tag_household(&db, user.id, id, req.household_id, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Tagged {
+        version: tag.version,
+        body: StatusCode::NO_CONTENT,
+    })
+}
+
+/// Put /api/v1/tags/:id
+#[utoipa::path(
+    put,
+    path = "/api/v1/tags/{id}",
+    tag = "tags",
+    params(
+        ("id" = i32, Path, description = "Id of the tag"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being changed"),
+    ),
+    request_body = TagUpdate,
+    responses(
+        (status = 200, body = TagRow, headers(("ETag" = String))),
+        (status = 403, description = "Not allowed to write to the household", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is a tag with that name", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The tag was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The tag is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn update_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(update): ValidatedJson<TagUpdate>,
+) -> Result<Tagged<Json<TagRow>>, Error> {
+    let tag = crud::tags::update_tag(&db, user.id, id, update, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Tagged {
+        version: tag.version,
+        body: Json(tag),
+    })
+}
+
+/// Delete /api/v1/tags/:id
+#[utoipa::path(
+    delete,
+    path = "/api/v1/tags/{id}",
+    tag = "tags",
+    params(
+        ("id" = i32, Path, description = "Id of the tag"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being deleted"),
+    ),
+    responses(
+        (status = 204, description = "The tag was deleted"),
+        (status = 404, description = "There is no tag with that id that the user owns", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The tag was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn delete_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+) -> Result<StatusCode, Error> {
+    crud::tags::delete_tag(&db, user.id, id, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get /api/v1/goals
+#[utoipa::path(
+    get,
+    path = "/api/v1/goals",
+    tag = "goals",
+    responses(
+        (status = 200, body = [GoalRow]),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_goals(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+) -> Result<Json<Vec<GoalRow>>, Error> {
+    let goals = crud::goals::fetch_goals(&db, user.id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(goals))
+}
+
+/// Get /api/v1/goals/:id
+#[utoipa::path(
+    get,
+    path = "/api/v1/goals/{id}",
+    tag = "goals",
+    params(("id" = i32, Path, description = "Id of the goal")),
+    responses(
+        (status = 200, body = GoalRow, headers(("ETag" = String, description = "Changes every time the goal is updated"))),
+        (status = 304, description = "The goal didn't change since the `ETag` in `If-None-Match`"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_specific_goal(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    Path(id): Path<i32>,
+) -> Result<Tagged<Json<GoalRow>>, Error> {
+    let goal = crud::goals::fetch_goal(&db, user.id, id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Tagged {
+        version: goal.version,
+        body: Json(goal),
+    })
+}
+
+/// Post /api/v1/goals
+#[utoipa::path(
+    post,
+    path = "/api/v1/goals",
+    tag = "goals",
+    request_body = GoalCreate,
+    responses(
+        (status = 200, body = Created),
+        (status = 403, description = "Viewers can't create goals", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is a goal with that name", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The goal is not valid or the account is not one of the user's normal accounts", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn create_goal(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(to_create): ValidatedJson<GoalCreate>,
+) -> Result<Json<Created>, Error> {
+    let goal = crud::goals::create_goal(&db, user.id, to_create, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(Created { id: goal.id }))
+}
+
+/// Put /api/v1/goals/:id
+#[utoipa::path(
+    put,
+    path = "/api/v1/goals/{id}",
+    tag = "goals",
+    params(
+        ("id" = i32, Path, description = "Id of the goal"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being changed"),
+    ),
+    request_body = GoalUpdate,
+    responses(
+        (status = 200, body = GoalRow, headers(("ETag" = String))),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is a goal with that name", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The goal was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The goal is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn update_goal(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(update): ValidatedJson<GoalUpdate>,
+) -> Result<Tagged<Json<GoalRow>>, Error> {
+    let goal = crud::goals::update_goal(&db, user.id, id, update, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Tagged {
+        version: goal.version,
+        body: Json(goal),
+    })
+}
+
+/// Delete /api/v1/goals/:id
+#[utoipa::path(
+    delete,
+    path = "/api/v1/goals/{id}",
+    tag = "goals",
+    params(
+        ("id" = i32, Path, description = "Id of the goal"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being deleted"),
+    ),
+    responses(
+        (status = 204, description = "The goal was deleted, what was set aside for it is available in its account again"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The goal was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn delete_goal(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+) -> Result<StatusCode, Error> {
+    crud::goals::delete_goal(&db, user.id, id, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Post /api/v1/goals/:id/transfers
+#[utoipa::path(
+    post,
+    path = "/api/v1/goals/{id}/transfers",
+    tag = "goals",
+    params(("id" = i32, Path, description = "Id of the goal")),
+    request_body = GoalTransfer,
+    responses(
+        (status = 200, body = GoalRow, headers(("ETag" = String))),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The amount is 0, more than the account has available or more than the goal has", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn transfer_to_goal(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    ValidatedJson(transfer): ValidatedJson<GoalTransfer>,
+) -> Result<Tagged<Json<GoalRow>>, Error> {
+    let goal = crud::goals::transfer(&db, user.id, id, transfer, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Tagged {
+        version: goal.version,
+        body: Json(goal),
+    })
+}
+
+/// Post /api/v1/batch
+#[utoipa::path(
+    post,
+    path = "/api/v1/batch",
+    tag = "batch",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "All the operations were applied", body = BatchResponse),
+        (status = 422, description = "Some operations failed and none were applied, `items` says why each one failed", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn run_batch(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(req): ValidatedJson<BatchRequest>,
+) -> Result<Json<BatchResponse>, Error> {
+    let results = crud::batch::run_batch(&db, user.id, req.operations, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(BatchResponse { results }))
+}
+
+/// Post /api/v1/transactions/sync
+#[utoipa::path(
+    post,
+    path = "/api/v1/transactions/sync",
+    tag = "transactions",
+    request_body = TransactionSyncRequest,
+    responses(
+        (status = 200, description = "Every entry was looked at, `results` says which ones were saved and which ones conflict", body = TransactionSyncResponse),
+        (status = 422, description = "There are no entries or too many of them", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn sync_transactions(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(req): ValidatedJson<TransactionSyncRequest>,
+) -> Result<Json<TransactionSyncResponse>, Error> {
+    let results = crud::transactions::sync_entries(&db, user.id, req.entries, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(TransactionSyncResponse { results }))
+}
+
+/// Post /api/v1/transactions/parse
+#[utoipa::path(
+    post,
+    path = "/api/v1/transactions/parse",
+    tag = "transactions",
+    request_body = QuickAddRequest,
+    responses(
+        (status = 200, description = "What the line would save, it can only be saved if there are no `ambiguities`", body = QuickAddPreview),
+        (status = 422, description = "The line is too long", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn parse_transaction(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    ValidatedJson(req): ValidatedJson<QuickAddRequest>,
+) -> Result<Json<QuickAddPreview>, Error> {
+    let preview = crud::quick_add::preview(&db, user.id, &req.line)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(preview))
+}
+
+/// Post /api/v1/transactions/quick-add
+#[utoipa::path(
+    post,
+    path = "/api/v1/transactions/quick-add",
+    tag = "transactions",
+    request_body = QuickAddRequest,
+    responses(
+        (status = 200, description = "The transaction was saved, or had been saved before with the same `client_id`", body = Created),
+        (status = 403, description = "Viewers can't add transactions", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The line has ambiguities or the transaction is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn quick_add_transaction(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(req): ValidatedJson<QuickAddRequest>,
+) -> Result<Json<Created>, Error> {
+    let (_, saved) = crud::quick_add::save(&db, user.id, &req.line, req.client_id, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    let (SavedTransaction::Created(transaction) | SavedTransaction::Duplicate(transaction)) = saved;
+    Ok(Json(Created { id: transaction.id }))
+}
+
+/// Delete /api/v1/transactions/:id
+#[utoipa::path(
+    delete,
+    path = "/api/v1/transactions/{id}",
+    tag = "transactions",
+    params(("id" = i32, Path, description = "Id of the transaction")),
+    responses(
+        (status = 204, description = "The transaction was deleted and its amount taken back out of the accounts and tags"),
+        (status = 403, description = "Not allowed to change one of its accounts or tags", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "There is no transaction with that id between accounts the user can see", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn delete_transaction(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> Result<StatusCode, Error> {
+    crud::transactions::delete_transaction(&db, user.id, id, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get /api/v1/households
+#[utoipa::path(
+    get,
+    path = "/api/v1/households",
+    tag = "households",
+    responses(
+        (status = 200, body = [HouseholdRow]),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_households(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+) -> Result<Json<Vec<HouseholdRow>>, Error> {
+    let households = crud::households::fetch_households(&db, user.id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(households))
+}
+
+/// Get /api/v1/households/:id
+#[utoipa::path(
+    get,
+    path = "/api/v1/households/{id}",
+    tag = "households",
+    params(("id" = i32, Path, description = "Id of the household")),
+    responses(
+        (status = 200, body = HouseholdDetails),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_specific_household(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    Path(id): Path<i32>,
+) -> Result<Json<HouseholdDetails>, Error> {
+    let household = crud::households::fetch_household(&db, user.id, id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(household))
+}
+
+/// Post /api/v1/households
+#[utoipa::path(
+    post,
+    path = "/api/v1/households",
+    tag = "households",
+    request_body = HouseholdCreate,
+    responses(
+        (status = 200, body = Created),
+        (status = 403, description = "Viewers can't create households", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The household is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn create_household(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(to_create): ValidatedJson<HouseholdCreate>,
+) -> Result<Json<Created>, Error> {
+    let id = crud::households::create_household(&db, user.id, to_create, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(Created { id }))
+}
+
+/// Post /api/v1/households/:id/invites
+#[utoipa::path(
+    post,
+    path = "/api/v1/households/{id}/invites",
+    tag = "households",
+    params(("id" = i32, Path, description = "Id of the household")),
+    request_body = InviteCreate,
+    responses(
+        (status = 200, body = Created),
+        (status = 403, description = "Only owners can invite", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The user is already a member or invited", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The invite is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn create_invite(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    ValidatedJson(invite): ValidatedJson<InviteCreate>,
+) -> Result<Json<Created>, Error> {
+    let id = crud::households::create_invite(&db, user.id, id, invite, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(Created { id }))
+}
+
+/// Delete /api/v1/households/:id/members/:user_id
+#[utoipa::path(
+    delete,
+    path = "/api/v1/households/{id}/members/{user_id}",
+    tag = "households",
+    params(
+        ("id" = i32, Path, description = "Id of the household"),
+        ("user_id" = Uuid, Path, description = "Id of the member, can be yourself to leave"),
+    ),
+    responses(
+        (status = 204, description = "The member was removed"),
+        (status = 403, description = "Only owners can remove other members", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The member is the last owner of the household", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn remove_member(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    client: ClientInfo,
+    Path((id, member_id)): Path<(i32, Uuid)>,
+) -> Result<StatusCode, Error> {
+    crud::households::remove_member(&db, user.id, id, member_id, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get /api/v1/invites
+#[utoipa::path(
+    get,
+    path = "/api/v1/invites",
+    tag = "invites",
+    responses(
+        (status = 200, body = [HouseholdInviteRow]),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_invites(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+) -> Result<Json<Vec<HouseholdInviteRow>>, Error> {
+    let invites = crud::households::fetch_invites(&db, user.id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(invites))
+}
+
+/// Post /api/v1/invites/:id/accept
+#[utoipa::path(
+    post,
+    path = "/api/v1/invites/{id}/accept",
+    tag = "invites",
+    params(("id" = i32, Path, description = "Id of the invite")),
+    responses(
+        (status = 204, description = "Joined the household"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn accept_invite(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> Result<StatusCode, Error> {
+    crud::households::accept_invite(&db, user.id, id, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Delete /api/v1/invites/:id
+#[utoipa::path(
+    delete,
+    path = "/api/v1/invites/{id}",
+    tag = "invites",
+    params(("id" = i32, Path, description = "Id of the invite")),
+    responses(
+        (status = 204, description = "The invite was declined"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn decline_invite(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> Result<StatusCode, Error> {
+    crud::households::delete_invite(&db, user.id, id, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get /api/v1/webhooks
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, body = [WebhookRow]),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_webhooks(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+) -> Result<Json<Vec<WebhookRow>>, Error> {
+    let webhooks = crud::webhooks::fetch_webhooks(&db, user.id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(webhooks))
+}
+
+/// Get /api/v1/webhooks/:id
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Id of the webhook")),
+    responses(
+        (status = 200, body = WebhookRow),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_specific_webhook(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    Path(id): Path<i32>,
+) -> Result<Json<WebhookRow>, Error> {
+    let webhook = crud::webhooks::fetch_webhook(&db, user.id, id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(webhook))
+}
+
+/// Post /api/v1/webhooks
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    request_body = WebhookCreate,
+    responses(
+        (status = 200, description = "The webhook was registered, the secret is not shown again", body = CreatedWebhook),
+        (status = 422, description = "The url or the events are not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn create_webhook(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(webhook): ValidatedJson<WebhookCreate>,
+) -> Result<Json<CreatedWebhook>, Error> {
+    let (webhook, secret) = crud::webhooks::create_webhook(&db, user.id, webhook, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(CreatedWebhook {
+        id: webhook.id,
+        secret,
+    }))
+}
+
+/// Delete /api/v1/webhooks/:id
+#[utoipa::path(
+    delete,
+    path = "/api/v1/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Id of the webhook")),
+    responses(
+        (status = 204, description = "The webhook and its deliveries were deleted"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn delete_webhook(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> Result<StatusCode, Error> {
+    crud::webhooks::delete_webhook(&db, user.id, id, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get /api/v1/webhooks/:id/deliveries
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(("id" = i32, Path, description = "Id of the webhook")),
+    responses(
+        (status = 200, description = "The newest 100 deliveries, newest first", body = [WebhookDeliveryRow]),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_webhook_deliveries(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<WebhookDeliveryRow>>, Error> {
+    let deliveries = crud::webhooks::fetch_deliveries(&db, user.id, id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(deliveries))
+}
+
+/// Get /api/v1/events
+#[utoipa::path(
+    get,
+    path = "/api/v1/events",
+    tag = "events",
+    responses(
+        (status = 200, description = "A stream of server-sent events, one for every change to the accounts, tags and goals the user can see. \
+            The name of the event is the same as for webhooks and the data is the entity as JSON. \
+            A `resync` event means some changes were missed and everything has to be fetched again.", body = String, content_type = "text/event-stream"),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn stream_events(
+    user: UserClaims,
+) -> Sse<impl Stream<Item = Result<Event, serde_json::Error>>> {
+    let user_id = user.id;
+    let changes = stream::unfold(events::subscribe(), move |mut changes| async move {
+        loop {
+            let event = match changes.recv().await {
+                Ok(change) if change.users.contains(&user_id) => Event::default()
+                    .event(change.event.as_ref())
+                    .json_data(&change.data),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(_)) => Ok(Event::default().event("resync").data("")),
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((event, changes));
+        }
+    });
+
+    Sse::new(changes).keep_alive(KeepAlive::default())
+}
+
+/// Get /api/v1/admin/users
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/users",
+    tag = "admin",
+    responses(
+        (status = 200, body = [UserRow]),
+        (status = 403, description = "Not an admin", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_users(
+    Extension(db): Extension<PgPool>,
+    _: AdminUser,
+) -> Result<Json<Vec<UserRow>>, Error> {
+    let users = crud::fetch_users(&db).await.map_err(Error::ApiError)?;
+    Ok(Json(users))
+}
+
+/// Post /api/v1/admin/users
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/users",
+    tag = "admin",
+    request_body = UserCreateRequest,
+    responses(
+        (status = 200, body = CreatedUser),
+        (status = 409, description = "The username is taken", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Not an admin", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The user is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn create_user(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user: admin, .. }: AdminUser,
+    client: ClientInfo,
+    ValidatedJson(user): ValidatedJson<UserCreateRequest>,
+) -> Result<Json<CreatedUser>, Error> {
+    let id = crud::create_user(&db, Some(admin.id), user, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(CreatedUser { id }))
+}
+
+/// Put /api/v1/admin/users/:id/role
+#[utoipa::path(
+    put,
+    path = "/api/v1/admin/users/{id}/role",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "Id of the user")),
+    request_body = RoleUpdateRequest,
+    responses(
+        (status = 204, description = "The role was changed"),
+        (status = 403, description = "Not an admin", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The user is the last admin", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn update_user_role(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user: admin, .. }: AdminUser,
+    client: ClientInfo,
+    Path(id): Path<Uuid>,
+    ValidatedJson(req): ValidatedJson<RoleUpdateRequest>,
+) -> Result<StatusCode, Error> {
+    crud::update_user_role(&db, admin.id, id, req.role, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get /api/v1/settings
+#[utoipa::path(
+    get,
+    path = "/api/v1/settings",
+    tag = "settings",
+    responses(
+        (status = 200, description = "The settings of the user, the defaults if they never changed them", body = UserSettings),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_settings(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+) -> Result<Json<UserSettings>, Error> {
+    let settings = crud::settings::fetch_settings(&db, user.id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(settings))
+}
+
+/// Put /api/v1/settings
+#[utoipa::path(
+    put,
+    path = "/api/v1/settings",
+    tag = "settings",
+    request_body = SettingsUpdate,
+    responses(
+        (status = 200, body = UserSettings),
+        (status = 422, description = "The settings are not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn update_settings(
+    Extension(db): Extension<PgPool>,
+    // Viewers can change them too, they only change how the data is shown to them
+    user: UserClaims,
+    client: ClientInfo,
+    ValidatedJson(update): ValidatedJson<SettingsUpdate>,
+) -> Result<Json<UserSettings>, Error> {
+    let settings = crud::settings::update_settings(&db, user.id, update, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(settings))
+}
+
+/// Get /api/v1/audit
+#[utoipa::path(
+    get,
+    path = "/api/v1/audit",
+    tag = "audit",
+    params(AuditLogQuery),
+    responses(
+        (status = 200, body = AuditLogPage),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The page is too far for the database to skip to", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_audit_log(
     Extension(db): Extension<PgPool>,
     user: UserClaims,
-    Json(to_create): Json<TagCreate>,
-) -> Result<Json<Value>, Error> {
-    let id = crud::tags::create_tag(&db, user.id, to_create)
+    Query(q): Query<AuditLogQuery>,
+) -> Result<Json<AuditLogPage>, Error> {
+    let page = crud::audit::fetch_audit_log(&db, &user, q)
         .await
         .map_err(Error::ApiError)?;
-    Ok(Json(json!({ "id": id })))
+    Ok(Json(page))
 }
@@ -1,22 +1,281 @@
+mod graphql;
 mod handlers;
+mod openapi;
+mod v2;
 
 use axum::{
-    routing::{get, post},
-    Router,
+    handler::Handler,
+    http::Method,
+    routing::{MethodFilter, MethodRouter},
+    AddExtensionLayer, Router,
 };
 
+use crate::middleware::{ConditionalGetLayer, IdempotencyLayer};
+
 pub(crate) fn routes() -> Router {
-    // Might want to play with GraphQL later or simply do breaking changes to the api, so we use `v1` path
-    let api_v1_routes = Router::new()
+    let api_routes = Router::new()
+        .nest("/v1", rest_routes(v1_routes()))
+        .nest("/v2", rest_routes(v2::routes()))
+        .merge(
+            graphql_routes()
+                .into_router()
+                .layer(AddExtensionLayer::new(graphql::schema())),
+        );
+    Router::new().nest("/api", api_routes)
+}
+
+fn rest_routes(routes: ApiRouter) -> Router {
+    routes
+        .into_router()
+        .layer(IdempotencyLayer)
+        .layer(ConditionalGetLayer)
+}
+
+// Might want to do breaking changes to the api later, so we use `v1` path
+fn v1_routes() -> ApiRouter {
+    ApiRouter::new("/api/v1")
+        .route("/openapi.json", get(openapi::serve_openapi))
         .route("/login", post(handlers::handle_login))
         .route(
             "/accounts",
             get(handlers::get_accounts).post(handlers::create_account),
         )
-        .route("/accounts/:id", get(handlers::get_specific_account))
+        .route(
+            "/accounts/:id",
+            get(handlers::get_specific_account)
+                .put(handlers::update_account)
+                .delete(handlers::delete_account),
+        )
+        .route("/accounts/:id/household", put(handlers::share_account))
         .route("/tags", get(handlers::get_tags).post(handlers::create_tag))
-        .route("/tags/:id", get(handlers::get_specific_tag));
+        .route(
+            "/tags/:id",
+            get(handlers::get_specific_tag)
+                .put(handlers::update_tag)
+                .delete(handlers::delete_tag),
+        )
+        .route("/tags/:id/household", put(handlers::share_tag))
+        .route(
+            "/goals",
+            get(handlers::get_goals).post(handlers::create_goal),
+        )
+        .route(
+            "/goals/:id",
+            get(handlers::get_specific_goal)
+                .put(handlers::update_goal)
+                .delete(handlers::delete_goal),
+        )
+        .route("/goals/:id/transfers", post(handlers::transfer_to_goal))
+        .route("/batch", post(handlers::run_batch))
+        .route("/transactions/sync", post(handlers::sync_transactions))
+        .route("/transactions/parse", post(handlers::parse_transaction))
+        .route(
+            "/transactions/quick-add",
+            post(handlers::quick_add_transaction),
+        )
+        .route("/transactions/:id", delete(handlers::delete_transaction))
+        .route(
+            "/households",
+            get(handlers::get_households).post(handlers::create_household),
+        )
+        .route("/households/:id", get(handlers::get_specific_household))
+        .route("/households/:id/invites", post(handlers::create_invite))
+        .route(
+            "/households/:id/members/:user_id",
+            delete(handlers::remove_member),
+        )
+        .route("/invites", get(handlers::get_invites))
+        .route("/invites/:id", delete(handlers::decline_invite))
+        .route("/invites/:id/accept", post(handlers::accept_invite))
+        .route(
+            "/webhooks",
+            get(handlers::get_webhooks).post(handlers::create_webhook),
+        )
+        .route(
+            "/webhooks/:id",
+            get(handlers::get_specific_webhook).delete(handlers::delete_webhook),
+        )
+        .route(
+            "/webhooks/:id/deliveries",
+            get(handlers::get_webhook_deliveries),
+        )
+        .route("/events", get(handlers::stream_events))
+        .route(
+            "/settings",
+            get(handlers::get_settings).put(handlers::update_settings),
+        )
+        .route("/audit", get(handlers::get_audit_log))
+        .nest("/admin", admin_routes)
+}
+
+fn graphql_routes() -> ApiRouter {
+    ApiRouter::new("/api").route("/graphql", post(graphql::handle_graphql))
+}
+
+/// Routes that can only be used by admins, every handler here must take an `AdminUser`
+fn admin_routes(router: ApiRouter) -> ApiRouter {
+    router
+        .route(
+            "/users",
+            get(handlers::get_users).post(handlers::create_user),
+        )
+        .route("/users/:id/role", put(handlers::update_user_role))
+}
+
+/// A `Router` that remembers the method and full path of every route,
+/// so a test can check that all of them are in the OpenAPI document
+struct ApiRouter {
+    router: Router,
+    prefix: String,
+    routes: Vec<(Method, String)>,
+}
+
+impl ApiRouter {
+    fn new(prefix: &str) -> Self {
+        Self {
+            router: Router::new(),
+            prefix: prefix.to_owned(),
+            routes: Vec::new(),
+        }
+    }
+
+    fn route(mut self, path: &str, methods: Methods) -> Self {
+        let full_path = format!("{}{}", self.prefix, path);
+        self.routes.extend(
+            methods
+                .methods
+                .into_iter()
+                .map(|method| (method, full_path.clone())),
+        );
+        self.router = self.router.route(path, methods.router);
+        self
+    }
+
+    fn nest(mut self, path: &str, routes: fn(ApiRouter) -> ApiRouter) -> Self {
+        let nested = routes(ApiRouter::new(&format!("{}{}", self.prefix, path)));
+        self.routes.extend(nested.routes);
+        self.router = self.router.nest(path, nested.router);
+        self
+    }
+
+    fn into_router(self) -> Router {
+        self.router
+    }
+}
+
+/// A `MethodRouter` that remembers its methods, made with the `get`, `post`, `put` and `delete` of this module
+#[derive(Default)]
+struct Methods {
+    methods: Vec<Method>,
+    router: MethodRouter,
+}
+
+impl Methods {
+    fn on<H, T>(mut self, method: Method, filter: MethodFilter, handler: H) -> Self
+    where
+        H: Handler<T>,
+        T: 'static,
+    {
+        self.methods.push(method);
+        self.router = self.router.on(filter, handler);
+        self
+    }
+
+    fn get<H: Handler<T>, T: 'static>(self, handler: H) -> Self {
+        self.on(Method::GET, MethodFilter::GET, handler)
+    }
+
+    fn post<H: Handler<T>, T: 'static>(self, handler: H) -> Self {
+        self.on(Method::POST, MethodFilter::POST, handler)
+    }
+
+    fn put<H: Handler<T>, T: 'static>(self, handler: H) -> Self {
+        self.on(Method::PUT, MethodFilter::PUT, handler)
+    }
+
+    fn delete<H: Handler<T>, T: 'static>(self, handler: H) -> Self {
+        self.on(Method::DELETE, MethodFilter::DELETE, handler)
+    }
+}
+
+fn get<H: Handler<T>, T: 'static>(handler: H) -> Methods {
+    Methods::default().get(handler)
+}
+
+fn post<H: Handler<T>, T: 'static>(handler: H) -> Methods {
+    Methods::default().post(handler)
+}
+
+fn put<H: Handler<T>, T: 'static>(handler: H) -> Methods {
+    Methods::default().put(handler)
+}
+
+fn delete<H: Handler<T>, T: 'static>(handler: H) -> Methods {
+    Methods::default().delete(handler)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        axum::http::Method,
+        utoipa::{
+            openapi::{path::PathItemType, OpenApi as OpenApiDoc},
+            OpenApi,
+        },
+    };
+
+    use super::{graphql_routes, openapi, v1_routes, v2, ApiRouter};
+
+    /// The routes of `router` that don't have an operation in `doc`, `:param` segments are matched with `{param}`
+    fn undocumented(router: ApiRouter, doc: &OpenApiDoc) -> Vec<String> {
+        router
+            .routes
+            .into_iter()
+            .filter(|(method, route)| {
+                let path = route
+                    .split('/')
+                    .map(|segment| match segment.strip_prefix(':') {
+                        Some(param) => format!("{{{}}}", param),
+                        None => segment.to_owned(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let method = match *method {
+                    Method::GET => PathItemType::Get,
+                    Method::POST => PathItemType::Post,
+                    Method::PUT => PathItemType::Put,
+                    Method::DELETE => PathItemType::Delete,
+                    _ => unreachable!("Routes only have the methods of `Methods`"),
+                };
+                let operations = doc.paths.paths.get(&path).map(|item| &item.operations);
+                !operations.is_some_and(|operations| operations.contains_key(&method))
+            })
+            .map(|(method, route)| format!("{} {}", method, route))
+            .collect()
+    }
+
+    #[test]
+    fn every_route_is_in_the_openapi_document() {
+        let v1_doc = openapi::ApiDoc::openapi();
+        let mut missing = undocumented(v1_routes(), &v1_doc);
+        missing.extend(undocumented(graphql_routes(), &v1_doc));
+        missing.extend(undocumented(v2::routes(), &v2::openapi::ApiDoc::openapi()));
+        assert!(
+            missing.is_empty(),
+            "Routes missing from the OpenAPI document: {}",
+            missing.join(", ")
+        );
+    }
 
-    let api_v1_nest = Router::new().nest("/v1", api_v1_routes);
-    Router::new().nest("/api", api_v1_nest)
+    #[test]
+    fn routes_are_matched_by_method_and_path() {
+        let doc = openapi::ApiDoc::openapi();
+        let router = ApiRouter::new("/api/v1")
+            .route("/accounts/:id", super::get(|| async {}).post(|| async {}))
+            .route("/nowhere", super::get(|| async {}));
+        assert_eq!(
+            undocumented(router, &doc),
+            ["POST /api/v1/accounts/:id", "GET /api/v1/nowhere",]
+        );
+    }
 }
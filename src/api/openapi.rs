@@ -0,0 +1,220 @@
+use {
+    axum::Json,
+    utoipa::{
+        openapi::{
+            path::{ParameterBuilder, ParameterIn, PathItemType},
+            security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+            ObjectBuilder, OpenApi as OpenApiDoc, Required, SchemaType,
+        },
+        Modify, OpenApi,
+    },
+};
+
+use super::{graphql, handlers};
+use crate::{
+    error::{ErrorCode, FieldError, ItemError, Problem},
+    i18n::Locale,
+    models::{
+        account::*,
+        audit::{AuditAction, AuditEntity, AuditLogPage, AuditLogRow},
+        goal::GoalRow,
+        household::{
+            HouseholdDetails, HouseholdInviteRow, HouseholdMemberRow, HouseholdRow, MemberRole,
+        },
+        settings::{Theme, UserSettings, Weekday},
+        tag::TagRow,
+        user::{Role, UserRow},
+        webhook::{DeliveryStatus, WebhookDeliveryRow, WebhookEvent, WebhookRow},
+    },
+    requests::*,
+    responses::*,
+    utils::cookies,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "budgetman"),
+    paths(
+        serve_openapi,
+        handlers::handle_login,
+        handlers::get_accounts,
+        handlers::get_specific_account,
+        handlers::create_account,
+        handlers::update_account,
+        handlers::delete_account,
+        handlers::share_account,
+        handlers::get_tags,
+        handlers::get_specific_tag,
+        handlers::create_tag,
+        handlers::update_tag,
+        handlers::delete_tag,
+        handlers::share_tag,
+        handlers::get_goals,
+        handlers::get_specific_goal,
+        handlers::create_goal,
+        handlers::update_goal,
+        handlers::delete_goal,
+        handlers::transfer_to_goal,
+        handlers::run_batch,
+        handlers::sync_transactions,
+        handlers::parse_transaction,
+        handlers::quick_add_transaction,
+        handlers::delete_transaction,
+        handlers::get_households,
+        handlers::get_specific_household,
+        handlers::create_household,
+        handlers::create_invite,
+        handlers::remove_member,
+        handlers::get_invites,
+        handlers::accept_invite,
+        handlers::decline_invite,
+        handlers::get_webhooks,
+        handlers::get_specific_webhook,
+        handlers::create_webhook,
+        handlers::delete_webhook,
+        handlers::get_webhook_deliveries,
+        handlers::stream_events,
+        handlers::get_users,
+        handlers::create_user,
+        handlers::update_user_role,
+        handlers::get_settings,
+        handlers::update_settings,
+        handlers::get_audit_log,
+        graphql::handle_graphql,
+    ),
+    components(schemas(
+        Problem,
+        ErrorCode,
+        FieldError,
+        ItemError,
+        LoginRequest,
+        LoginResponse,
+        Created,
+        CreatedUser,
+        AccountCreateRequest,
+        AccountUpdate,
+        AccountType,
+        AccountList,
+        AccountRow,
+        AdhocAccountRow,
+        NormalAccountRow,
+        TagCreate,
+        TagUpdate,
+        TagRow,
+        GoalCreate,
+        GoalUpdate,
+        GoalTransfer,
+        GoalRow,
+        ShareRequest,
+        BatchRequest,
+        BatchOperation,
+        BatchResponse,
+        BatchItemResult,
+        TransactionSyncRequest,
+        QueuedTransaction,
+        TransactionSyncResponse,
+        SyncedEntry,
+        SyncStatus,
+        QuickAddRequest,
+        QuickAddPreview,
+        NamedRef,
+        Ambiguity,
+        QuickAddField,
+        AmbiguityKind,
+        HouseholdCreate,
+        HouseholdRow,
+        HouseholdDetails,
+        HouseholdMemberRow,
+        HouseholdInviteRow,
+        InviteCreate,
+        MemberRole,
+        UserCreateRequest,
+        RoleUpdateRequest,
+        UserRow,
+        Role,
+        WebhookCreate,
+        CreatedWebhook,
+        WebhookRow,
+        WebhookEvent,
+        WebhookDeliveryRow,
+        DeliveryStatus,
+        SettingsUpdate,
+        UserSettings,
+        Weekday,
+        Theme,
+        Locale,
+        AuditLogPage,
+        AuditLogRow,
+        AuditAction,
+        AuditEntity,
+    )),
+    modifiers(&Security, &IdempotencyKey),
+    security(("bearer" = []), ("cookie" = [])),
+)]
+pub(crate) struct ApiDoc;
+
+/// Adds the two ways to send the access token, every route needs one of them unless it says otherwise
+pub(super) struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut OpenApiDoc) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(cookies::ACCESS_TOKEN))),
+        );
+    }
+}
+
+/// Adds the optional `Idempotency-Key` header to every POST route, see [`crate::middleware::IdempotencyLayer`]
+pub(super) struct IdempotencyKey;
+
+impl Modify for IdempotencyKey {
+    fn modify(&self, openapi: &mut OpenApiDoc) {
+        let operations = openapi
+            .paths
+            .paths
+            .values_mut()
+            .filter_map(|item| item.operations.get_mut(&PathItemType::Post));
+        for operation in operations {
+            let header = ParameterBuilder::new()
+                .name("Idempotency-Key")
+                .parameter_in(ParameterIn::Header)
+                .required(Required::False)
+                .description(Some(
+                    "Retries with the same key get the first response again instead of repeating the request",
+                ))
+                .schema(Some(
+                    ObjectBuilder::new()
+                        .schema_type(SchemaType::String)
+                        .max_length(Some(255)),
+                ))
+                .build();
+            operation
+                .parameters
+                .get_or_insert_with(Vec::new)
+                .push(header);
+        }
+    }
+}
+
+/// Get /api/v1/openapi.json
+#[utoipa::path(
+    get,
+    path = "/api/v1/openapi.json",
+    tag = "docs",
+    responses((status = 200, description = "This document")),
+    security(()),
+)]
+pub(crate) async fn serve_openapi() -> Json<OpenApiDoc> {
+    Json(ApiDoc::openapi())
+}
@@ -0,0 +1,393 @@
+use {
+    axum::{extract::Extension, http::StatusCode},
+    sqlx::PgPool,
+};
+
+use crate::{
+    crud,
+    extract::{IfMatch, Json, Path, Query, ValidatedJson},
+    models::{
+        account::{AccountType, AccountTypeQuery},
+        audit::ClientInfo,
+        tag::TagRow,
+        user::{RequirePermission, UserClaims, Write},
+    },
+    requests::*,
+    responses::*,
+    Error,
+};
+
+/// Get /api/v2/accounts
+#[utoipa::path(
+    get,
+    path = "/api/v2/accounts",
+    tag = "accounts",
+    params(AccountTypeQuery),
+    responses(
+        (status = 200, body = AccountListEnvelope),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_accounts(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    Query(type_q): Query<AccountTypeQuery>,
+) -> Result<Json<Envelope<Vec<AccountResource>>>, Error> {
+    let accounts = crud::accounts::fetch_accounts(&db, &user.id)
+        .await
+        .map_err(Error::ApiError)?;
+    let data = accounts
+        .into_iter()
+        .filter(|account| match type_q.account_type {
+            AccountType::Any => true,
+            AccountType::Adhoc => account.is_adhoc,
+            AccountType::Normal => !account.is_adhoc,
+        })
+        .map(AccountResource::from)
+        .collect();
+    Ok(Json(Envelope { data }))
+}
+
+/// Get /api/v2/accounts/:id
+#[utoipa::path(
+    get,
+    path = "/api/v2/accounts/{id}",
+    tag = "accounts",
+    params(("id" = i32, Path, description = "Id of the account")),
+    responses(
+        (status = 200, body = AccountEnvelope, headers(("ETag" = String, description = "Changes every time the account is updated"))),
+        (status = 304, description = "The account didn't change since the `ETag` in `If-None-Match`"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_specific_account(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    Path(id): Path<i32>,
+) -> Result<Tagged<Json<Envelope<AccountResource>>>, Error> {
+    let account = crud::accounts::fetch_account(&db, &user.id, id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(account_response(account.into()))
+}
+
+/// Post /api/v2/accounts
+#[utoipa::path(
+    post,
+    path = "/api/v2/accounts",
+    tag = "accounts",
+    request_body = TypedAccountCreate,
+    responses(
+        (status = 201, body = AccountEnvelope, headers(
+            ("Location" = String, description = "Where the account can be found"),
+            ("ETag" = String),
+        )),
+        (status = 403, description = "Viewers can't create accounts", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is an account with that name", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The account is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn create_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(account): ValidatedJson<TypedAccountCreate>,
+) -> Result<Tagged<CreatedAt<Json<Envelope<AccountResource>>>>, Error> {
+    let account = crud::accounts::create_account(&db, user.id, account.into(), &client)
+        .await
+        .map_err(Error::ApiError)?;
+    let location = format!("/api/v2/accounts/{}", account.id);
+    let Tagged { version, body } = account_response(account.into());
+    Ok(Tagged {
+        version,
+        body: CreatedAt { location, body },
+    })
+}
+
+/// Put /api/v2/accounts/:id
+#[utoipa::path(
+    put,
+    path = "/api/v2/accounts/{id}",
+    tag = "accounts",
+    params(
+        ("id" = i32, Path, description = "Id of the account"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being changed"),
+    ),
+    request_body = AccountUpdate,
+    responses(
+        (status = 200, body = AccountEnvelope, headers(("ETag" = String))),
+        (status = 403, description = "Not allowed to write to the household", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is an account with that name", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The account was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The account is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn update_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(update): ValidatedJson<AccountUpdate>,
+) -> Result<Tagged<Json<Envelope<AccountResource>>>, Error> {
+    let account = crud::accounts::update_account(&db, user.id, id, update, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(account_response(account.into()))
+}
+
+/// Delete /api/v2/accounts/:id
+#[utoipa::path(
+    delete,
+    path = "/api/v2/accounts/{id}",
+    tag = "accounts",
+    params(
+        ("id" = i32, Path, description = "Id of the account"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being deleted"),
+    ),
+    responses(
+        (status = 204, description = "The account was deleted"),
+        (status = 404, description = "There is no account with that id that the user owns", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The account has transactions", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The account was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn delete_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+) -> Result<StatusCode, Error> {
+    crud::accounts::delete_account(&db, user.id, id, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Put /api/v2/accounts/:id/household
+#[utoipa::path(
+    put,
+    path = "/api/v2/accounts/{id}/household",
+    tag = "accounts",
+    params(
+        ("id" = i32, Path, description = "Id of the account"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being changed"),
+    ),
+    request_body = ShareRequest,
+    responses(
+        (status = 200, description = "The account, shared with the household or not shared anymore", body = AccountEnvelope, headers(("ETag" = String))),
+        (status = 403, description = "Not allowed to write to the household", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The account was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn share_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(req): ValidatedJson<ShareRequest>,
+) -> Result<Tagged<Json<Envelope<AccountResource>>>, Error> {
+    let account = crud::accounts::set_account_household(
+        &db,
+        user.id,
+        id,
+        req.household_id,
+        &if_match,
+        &client,
+    )
+    .await
+    .map_err(Error::ApiError)?;
+    Ok(account_response(account.into()))
+}
+
+/// Get /api/v2/tags
+#[utoipa::path(
+    get,
+    path = "/api/v2/tags",
+    tag = "tags",
+    responses(
+        (status = 200, body = TagListEnvelope),
+        (status = 400, description = "Not logged in", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_tags(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+) -> Result<Json<Envelope<Vec<TagRow>>>, Error> {
+    let data = crud::tags::fetch_tags(&db, user.id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(Json(Envelope { data }))
+}
+
+/// Get /api/v2/tags/:id
+#[utoipa::path(
+    get,
+    path = "/api/v2/tags/{id}",
+    tag = "tags",
+    params(("id" = i32, Path, description = "Id of the tag")),
+    responses(
+        (status = 200, body = TagEnvelope, headers(("ETag" = String, description = "Changes every time the tag is updated"))),
+        (status = 304, description = "The tag didn't change since the `ETag` in `If-None-Match`"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn get_specific_tag(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    Path(id): Path<i32>,
+) -> Result<Tagged<Json<Envelope<TagRow>>>, Error> {
+    let tag = crud::tags::fetch_tag(&db, user.id, id)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(tag_response(tag))
+}
+
+/// Post /api/v2/tags
+#[utoipa::path(
+    post,
+    path = "/api/v2/tags",
+    tag = "tags",
+    request_body = TagCreate,
+    responses(
+        (status = 201, body = TagEnvelope, headers(
+            ("Location" = String, description = "Where the tag can be found"),
+            ("ETag" = String),
+        )),
+        (status = 403, description = "Viewers can't create tags", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is a tag with that name", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The tag is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn create_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    ValidatedJson(to_create): ValidatedJson<TagCreate>,
+) -> Result<Tagged<CreatedAt<Json<Envelope<TagRow>>>>, Error> {
+    let tag = crud::tags::create_tag(&db, user.id, to_create, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    let location = format!("/api/v2/tags/{}", tag.id);
+    let Tagged { version, body } = tag_response(tag);
+    Ok(Tagged {
+        version,
+        body: CreatedAt { location, body },
+    })
+}
+
+/// Put /api/v2/tags/:id
+#[utoipa::path(
+    put,
+    path = "/api/v2/tags/{id}",
+    tag = "tags",
+    params(
+        ("id" = i32, Path, description = "Id of the tag"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being changed"),
+    ),
+    request_body = TagUpdate,
+    responses(
+        (status = 200, body = TagEnvelope, headers(("ETag" = String))),
+        (status = 403, description = "Not allowed to write to the household", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "There already is a tag with that name", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The tag was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "The tag is not valid", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn update_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(update): ValidatedJson<TagUpdate>,
+) -> Result<Tagged<Json<Envelope<TagRow>>>, Error> {
+    let tag = crud::tags::update_tag(&db, user.id, id, update, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(tag_response(tag))
+}
+
+/// Delete /api/v2/tags/:id
+#[utoipa::path(
+    delete,
+    path = "/api/v2/tags/{id}",
+    tag = "tags",
+    params(
+        ("id" = i32, Path, description = "Id of the tag"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being deleted"),
+    ),
+    responses(
+        (status = 204, description = "The tag was deleted"),
+        (status = 404, description = "There is no tag with that id that the user owns", body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The tag was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn delete_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+) -> Result<StatusCode, Error> {
+    crud::tags::delete_tag(&db, user.id, id, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Put /api/v2/tags/:id/household
+#[utoipa::path(
+    put,
+    path = "/api/v2/tags/{id}/household",
+    tag = "tags",
+    params(
+        ("id" = i32, Path, description = "Id of the tag"),
+        ("If-Match" = String, Header, description = "`ETag` of the version that is being changed"),
+    ),
+    request_body = ShareRequest,
+    responses(
+        (status = 200, description = "The tag, shared with the household or not shared anymore", body = TagEnvelope, headers(("ETag" = String))),
+        (status = 403, description = "Not allowed to write to the household", body = Problem, content_type = "application/problem+json"),
+        (status = 404, body = Problem, content_type = "application/problem+json"),
+        (status = 412, description = "The tag was changed since the `ETag` in `If-Match`", body = Problem, content_type = "application/problem+json"),
+        (status = 428, description = "There is no `If-Match` header", body = Problem, content_type = "application/problem+json"),
+    ),
+)]
+pub(crate) async fn share_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+    IfMatch(if_match): IfMatch,
+    ValidatedJson(req): ValidatedJson<ShareRequest>,
+) -> Result<Tagged<Json<Envelope<TagRow>>>, Error> {
+    let tag = crud::tags::set_tag_household(&db, user.id, id, req.household_id, &if_match, &client)
+        .await
+        .map_err(Error::ApiError)?;
+    Ok(tag_response(tag))
+}
+
+fn account_response(account: AccountResource) -> Tagged<Json<Envelope<AccountResource>>> {
+    Tagged {
+        version: account.version(),
+        body: Json(Envelope { data: account }),
+    }
+}
+
+fn tag_response(tag: TagRow) -> Tagged<Json<Envelope<TagRow>>> {
+    Tagged {
+        version: tag.version,
+        body: Json(Envelope { data: tag }),
+    }
+}
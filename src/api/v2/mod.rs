@@ -0,0 +1,31 @@
+//! Version 2 of the api: every resource is wrapped in `{ "data": ... }`, creating something returns it with
+//! `201 Created` and its `Location`, and accounts have a `type` instead of a different shape for each kind
+
+mod handlers;
+pub(super) mod openapi;
+
+use super::{get, put, ApiRouter};
+
+pub(super) fn routes() -> ApiRouter {
+    ApiRouter::new("/api/v2")
+        .route("/openapi.json", get(openapi::serve_openapi))
+        .route(
+            "/accounts",
+            get(handlers::get_accounts).post(handlers::create_account),
+        )
+        .route(
+            "/accounts/:id",
+            get(handlers::get_specific_account)
+                .put(handlers::update_account)
+                .delete(handlers::delete_account),
+        )
+        .route("/accounts/:id/household", put(handlers::share_account))
+        .route("/tags", get(handlers::get_tags).post(handlers::create_tag))
+        .route(
+            "/tags/:id",
+            get(handlers::get_specific_tag)
+                .put(handlers::update_tag)
+                .delete(handlers::delete_tag),
+        )
+        .route("/tags/:id/household", put(handlers::share_tag))
+}
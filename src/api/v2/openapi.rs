@@ -0,0 +1,69 @@
+use {
+    axum::Json,
+    utoipa::{openapi::OpenApi as OpenApiDoc, OpenApi},
+};
+
+use super::{
+    super::openapi::{IdempotencyKey, Security},
+    handlers,
+};
+use crate::{
+    error::{ErrorCode, FieldError, Problem},
+    models::{account::AccountType, tag::TagRow},
+    requests::{AccountUpdate, ShareRequest, TagCreate, TagUpdate, TypedAccountCreate},
+    responses::{
+        AccountEnvelope, AccountListEnvelope, AccountResource, TagEnvelope, TagListEnvelope,
+    },
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "budgetman", version = "2"),
+    paths(
+        serve_openapi,
+        handlers::get_accounts,
+        handlers::get_specific_account,
+        handlers::create_account,
+        handlers::update_account,
+        handlers::delete_account,
+        handlers::share_account,
+        handlers::get_tags,
+        handlers::get_specific_tag,
+        handlers::create_tag,
+        handlers::update_tag,
+        handlers::delete_tag,
+        handlers::share_tag,
+    ),
+    components(schemas(
+        Problem,
+        ErrorCode,
+        FieldError,
+        AccountType,
+        AccountResource,
+        AccountEnvelope,
+        AccountListEnvelope,
+        TypedAccountCreate,
+        AccountUpdate,
+        TagRow,
+        TagEnvelope,
+        TagListEnvelope,
+        TagCreate,
+        TagUpdate,
+        ShareRequest,
+    )),
+    modifiers(&Security, &IdempotencyKey),
+    security(("bearer" = []), ("cookie" = [])),
+)]
+pub(in crate::api) struct ApiDoc;
+
+/// Get /api/v2/openapi.json
+#[utoipa::path(
+    get,
+    path = "/api/v2/openapi.json",
+    tag = "docs",
+    responses((status = 200, description = "This document")),
+    security(()),
+)]
+pub(crate) async fn serve_openapi() -> Json<OpenApiDoc> {
+    Json(ApiDoc::openapi())
+}
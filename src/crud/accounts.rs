@@ -1,15 +1,31 @@
 use std::borrow::Cow;
 
 use {
-    sea_query::{bind_params_sqlx_postgres, Expr, PostgresQueryBuilder, Query, Value},
-    sqlx::PgPool,
+    sea_query::{
+        bind_params_sqlx_postgres, Cond, Expr, LockType, PostgresQueryBuilder, Query, Value,
+    },
+    serde_json::json,
+    sqlx::{Acquire, PgConnection, PgPool, Postgres},
     strum::IntoEnumIterator,
     uuid::Uuid,
 };
 
-use crate::{models::account::*, requests::AccountCreateRequest, utils, CommonError};
+use super::{audit, events, households};
+use crate::{
+    error::ErrorCode,
+    models::{
+        account::*,
+        audit::{AuditAction, AuditEntity, AuditEntry, ClientInfo},
+        household::MemberRole,
+        transaction::TransactionTable,
+        webhook::WebhookEvent,
+    },
+    requests::{AccountCreateRequest, AccountUpdate},
+    utils::{self, etag::EntityTags},
+    CommonError,
+};
 
-/// Get accounts related to the given `user_id`
+/// Get accounts related to the given `user_id`, either their own or shared with them through a household
 pub(crate) async fn fetch_accounts(
     db: &PgPool,
     user_id: &Uuid,
@@ -23,16 +39,43 @@ pub(crate) async fn fetch_accounts(
             AccountTable::TotalMoney,
             AccountTable::UserId,
             AccountTable::IsAdhoc,
+            AccountTable::HouseholdId,
+            AccountTable::Version,
         ])
         .from(AccountTable::Table)
-        .and_where(Expr::col(AccountTable::UserId).eq(user_id.to_owned()))
+        .and_where(households::visible_to(
+            *user_id,
+            AccountTable::UserId,
+            AccountTable::HouseholdId,
+        ))
         .build(PostgresQueryBuilder);
     let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
 
-    Ok(query.fetch_all(db).await.map_err(|e| CommonError::Db {
+    query.fetch_all(db).await.map_err(|e| CommonError::Db {
         msg: Some("Failed to fetch accounts from db".into()),
         source: e,
-    })?)
+    })
+}
+
+/// Get the accounts shared with the given households, leaving out the households the user is not a member of
+pub(crate) async fn fetch_household_accounts(
+    db: &PgPool,
+    user_id: Uuid,
+    household_ids: &[i32],
+) -> Result<Vec<AccountRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(AccountTable::iter().skip(1))
+        .from(AccountTable::Table)
+        .and_where(Expr::col(AccountTable::HouseholdId).is_in(household_ids.iter().copied()))
+        .and_where(households::visible_to(
+            user_id,
+            AccountTable::UserId,
+            AccountTable::HouseholdId,
+        ))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    Ok(query.fetch_all(db).await?)
 }
 
 pub(crate) async fn fetch_adhoc_accounts(
@@ -42,15 +85,19 @@ pub(crate) async fn fetch_adhoc_accounts(
     let (sql, values) = Query::select()
         .columns([AccountTable::Id, AccountTable::Name, AccountTable::UserId])
         .from(AccountTable::Table)
-        .and_where(Expr::col(AccountTable::UserId).eq(user_id.to_owned()))
+        .and_where(households::visible_to(
+            *user_id,
+            AccountTable::UserId,
+            AccountTable::HouseholdId,
+        ))
         .and_where(Expr::col(AccountTable::IsAdhoc).eq(true))
         .build(PostgresQueryBuilder);
     let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
 
-    Ok(query.fetch_all(db).await.map_err(|e| CommonError::Db {
+    query.fetch_all(db).await.map_err(|e| CommonError::Db {
         msg: Some("Failed to fetch adhoc accounts from db".into()),
         source: e,
-    })?)
+    })
 }
 
 pub(crate) async fn fetch_account(
@@ -62,13 +109,38 @@ pub(crate) async fn fetch_account(
         .columns(AccountTable::iter().skip(1))
         .from(AccountTable::Table)
         .and_where(Expr::col(AccountTable::Id).eq(account_id))
-        .and_where(Expr::col(AccountTable::UserId).eq(user_id.to_owned()))
+        .and_where(households::visible_to(
+            *user_id,
+            AccountTable::UserId,
+            AccountTable::HouseholdId,
+        ))
         .build(PostgresQueryBuilder);
     let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
     let account: Option<AccountRow> = query.fetch_optional(db).await?;
     account.ok_or(CommonError::NotFound)
 }
 
+/// The ones out of `account_ids` the user can see
+pub(crate) async fn fetch_visible_ids(
+    db: &PgPool,
+    user_id: Uuid,
+    account_ids: &[i32],
+) -> Result<Vec<i32>, CommonError> {
+    let (sql, values) = Query::select()
+        .column(AccountTable::Id)
+        .from(AccountTable::Table)
+        .and_where(Expr::col(AccountTable::Id).is_in(account_ids.iter().copied()))
+        .and_where(households::visible_to(
+            user_id,
+            AccountTable::UserId,
+            AccountTable::HouseholdId,
+        ))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+
+    Ok(query.fetch_all(db).await?)
+}
+
 pub(crate) async fn fetch_normal_accounts(
     db: &PgPool,
     user_id: &Uuid,
@@ -83,26 +155,40 @@ pub(crate) async fn fetch_normal_accounts(
             AccountTable::UserId,
         ])
         .from(AccountTable::Table)
-        .and_where(Expr::col(AccountTable::UserId).eq(user_id.to_owned()))
+        .and_where(households::visible_to(
+            *user_id,
+            AccountTable::UserId,
+            AccountTable::HouseholdId,
+        ))
         .and_where(Expr::col(AccountTable::IsAdhoc).eq(false))
         .build(PostgresQueryBuilder);
     let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
 
-    Ok(query.fetch_all(db).await.map_err(|e| CommonError::Db {
+    query.fetch_all(db).await.map_err(|e| CommonError::Db {
         msg: Some("Failed to fetch normal accounts from db".into()),
         source: e,
-    })?)
+    })
 }
 
 /// Create the given account.
 /// If it's a normal account but starting_money is None then set it to 0.
 ///
-/// Returns the created account's id if successful.
-pub(crate) async fn create_account(
-    db: &PgPool,
+/// Returns the created account if successful.
+pub(crate) async fn create_account<'c, A>(
+    db: A,
     user_id: Uuid,
     acc: AccountCreateRequest,
-) -> Result<i32, CommonError> {
+    client: &ClientInfo,
+) -> Result<AccountRow, CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+    if let Some(household_id) = acc.household_id {
+        households::require_member_role(&mut tx, user_id, household_id, MemberRole::can_write)
+            .await?;
+    }
+
     // If the account is adhoc we want to set money to None no matter what,
     // same with description
     let (money, description) = match acc.is_adhoc {
@@ -122,6 +208,7 @@ pub(crate) async fn create_account(
             AccountTable::TotalMoney,
             AccountTable::UserId,
             AccountTable::IsAdhoc,
+            AccountTable::HouseholdId,
         ])
         .values_panic([
             acc.name.into(),
@@ -130,12 +217,13 @@ pub(crate) async fn create_account(
             money.into(),
             user_id.into(),
             acc.is_adhoc.into(),
+            acc.household_id.into(),
         ])
-        .returning_col(AccountTable::Id)
+        .returning(Query::select().columns(AccountTable::iter().skip(1)).take())
         .build(PostgresQueryBuilder);
 
-    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
-    let r = query.fetch_one(db).await.map_err(|e| {
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let account: AccountRow = query.fetch_one(&mut tx).await.map_err(|e| {
         let msg = if utils::err_is_failed_constraint(&e) {
             Some(Cow::Borrowed("There already is an account with that name"))
         } else {
@@ -145,5 +233,300 @@ pub(crate) async fn create_account(
         CommonError::Db { msg, source: e }
     })?;
 
-    Ok(r)
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Create,
+        entity_type: AuditEntity::Account,
+        entity_id: Some(account.id.to_string()),
+        before: None,
+        after: Some(json!(account)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        account.user_id,
+        &[account.household_id],
+        WebhookEvent::AccountCreated,
+        json!(account),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(account)
+}
+
+/// Change the name and description of an account, if it's still at a version in `if_match`.
+/// The owner and the household members that can write can do that.
+///
+/// Returns the updated account.
+pub(crate) async fn update_account<'c, A>(
+    db: A,
+    user_id: Uuid,
+    account_id: i32,
+    update: AccountUpdate,
+    if_match: &EntityTags,
+    client: &ClientInfo,
+) -> Result<AccountRow, CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    let mut account = lock_writable(&mut tx, user_id, account_id).await?;
+    if !if_match.matches_version(account.version) {
+        return Err(CommonError::VersionMismatch);
+    }
+
+    // Adhoc accounts never have a description
+    let description = update.description.filter(|_| !account.is_adhoc);
+    let (sql, values) = Query::update()
+        .table(AccountTable::Table)
+        .values([
+            (AccountTable::Name, update.name.clone().into()),
+            (AccountTable::Description, description.clone().into()),
+            (AccountTable::Version, (account.version + 1).into()),
+        ])
+        .and_where(Expr::col(AccountTable::Id).eq(account_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await.map_err(|e| {
+        let msg = if utils::err_is_failed_constraint(&e) {
+            Some(Cow::Borrowed("There already is an account with that name"))
+        } else {
+            None
+        };
+
+        CommonError::Db { msg, source: e }
+    })?;
+
+    let before = json!(account);
+    account.name = update.name;
+    account.description = description;
+    account.version += 1;
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::Account,
+        entity_id: Some(account_id.to_string()),
+        before: Some(before),
+        after: Some(json!(account)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        account.user_id,
+        &[account.household_id],
+        WebhookEvent::AccountUpdated,
+        json!(account),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(account)
+}
+
+/// Delete the account, if it's still at a version in `if_match` and it has no transactions.
+/// Only the owner of the account can do that.
+pub(crate) async fn delete_account<'c, A>(
+    db: A,
+    user_id: Uuid,
+    account_id: i32,
+    if_match: &EntityTags,
+    client: &ClientInfo,
+) -> Result<(), CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    let (sql, values) = Query::select()
+        .columns(AccountTable::iter().skip(1))
+        .from(AccountTable::Table)
+        .and_where(Expr::col(AccountTable::Id).eq(account_id))
+        .and_where(Expr::col(AccountTable::UserId).eq(user_id))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let account: AccountRow = query
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    if !if_match.matches_version(account.version) {
+        return Err(CommonError::VersionMismatch);
+    }
+
+    // Their amounts are part of the balances of other accounts and of tags, so they stay
+    let (sql, values) = Query::select()
+        .expr(Expr::val(1))
+        .from(TransactionTable::Table)
+        .cond_where(
+            Cond::any()
+                .add(Expr::col(TransactionTable::SourceId).eq(account_id))
+                .add(Expr::col(TransactionTable::DestinationId).eq(account_id)),
+        )
+        .limit(1)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    if query.fetch_optional(&mut tx).await?.is_some() {
+        return Err(CommonError::Rejected {
+            code: ErrorCode::InUse,
+            detail: "The account has transactions, delete them first".into(),
+        });
+    }
+
+    let (sql, values) = Query::delete()
+        .from_table(AccountTable::Table)
+        .and_where(Expr::col(AccountTable::Id).eq(account_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Delete,
+        entity_type: AuditEntity::Account,
+        entity_id: Some(account_id.to_string()),
+        before: Some(json!(account)),
+        after: None,
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        account.user_id,
+        &[account.household_id],
+        WebhookEvent::AccountDeleted,
+        json!(account),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Share the account with a household or make it private again if `household_id` is None.
+/// Only the owner of the account can do that.
+pub(crate) async fn set_account_household(
+    db: &PgPool,
+    user_id: Uuid,
+    account_id: i32,
+    household_id: Option<i32>,
+    if_match: &EntityTags,
+    client: &ClientInfo,
+) -> Result<AccountRow, CommonError> {
+    if let Some(household_id) = household_id {
+        households::require_member_role(db, user_id, household_id, MemberRole::can_write).await?;
+    }
+
+    let mut tx = db.begin().await?;
+
+    let (sql, values) = Query::select()
+        .columns(AccountTable::iter().skip(1))
+        .from(AccountTable::Table)
+        .and_where(Expr::col(AccountTable::Id).eq(account_id))
+        .and_where(Expr::col(AccountTable::UserId).eq(user_id))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let mut account: AccountRow = query
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    if !if_match.matches_version(account.version) {
+        return Err(CommonError::VersionMismatch);
+    }
+
+    let (sql, values) = Query::update()
+        .table(AccountTable::Table)
+        .values([
+            (AccountTable::HouseholdId, household_id.into()),
+            (AccountTable::Version, (account.version + 1).into()),
+        ])
+        .and_where(Expr::col(AccountTable::Id).eq(account_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+
+    let before = json!(account);
+    let old_household_id = account.household_id;
+    account.household_id = household_id;
+    account.version += 1;
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::Account,
+        entity_id: Some(account_id.to_string()),
+        before: Some(before),
+        after: Some(json!(account)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        account.user_id,
+        &[old_household_id, account.household_id],
+        WebhookEvent::AccountUpdated,
+        json!(account),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(account)
+}
+
+/// The id of the user's own third party account named `name`, if they have one
+pub(crate) async fn find_own_adhoc(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    name: &str,
+) -> Result<Option<i32>, CommonError> {
+    let (sql, values) = Query::select()
+        .column(AccountTable::Id)
+        .from(AccountTable::Table)
+        .and_where(Expr::col(AccountTable::UserId).eq(user_id))
+        .and_where(Expr::col(AccountTable::Name).eq(name))
+        .and_where(Expr::col(AccountTable::IsAdhoc).eq(true))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+
+    query
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| CommonError::Db {
+            msg: Some("Failed to fetch the account from db".into()),
+            source: e,
+        })
+}
+
+/// Lock the account until the end of the transaction, if the user can change it:
+/// the owner and the members of the household it's shared with that can write can do that.
+pub(crate) async fn lock_writable(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    account_id: i32,
+) -> Result<AccountRow, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(AccountTable::iter().skip(1))
+        .from(AccountTable::Table)
+        .and_where(Expr::col(AccountTable::Id).eq(account_id))
+        .and_where(households::visible_to(
+            user_id,
+            AccountTable::UserId,
+            AccountTable::HouseholdId,
+        ))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let account: AccountRow = query
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    if account.user_id != user_id {
+        // It's only visible to someone else if it's shared with them
+        let household_id = account.household_id.ok_or(CommonError::NotFound)?;
+        households::require_member_role(conn, user_id, household_id, MemberRole::can_write).await?;
+    }
+    Ok(account)
 }
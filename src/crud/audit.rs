@@ -0,0 +1,190 @@
+use {
+    sea_query::{
+        bind_params_sqlx_postgres, Expr, Func, Order, PostgresQueryBuilder, Query, SelectStatement,
+        Value,
+    },
+    sqlx::{Executor, PgPool, Postgres},
+};
+
+use crate::{
+    error::FieldError,
+    models::{
+        audit::*,
+        user::{UserClaims, UserRow},
+    },
+    CommonError,
+};
+
+/// Most entries a page of the audit log can have
+const MAX_PER_PAGE: u64 = 200;
+
+/// Write an entry to the audit log.
+///
+/// Pass the same transaction as the change itself so both of them are saved or neither is.
+pub(crate) async fn record<'c, E>(executor: E, entry: AuditEntry<'_>) -> Result<(), CommonError>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let (sql, values) = Query::insert()
+        .into_table(AuditLogTable::Table)
+        .columns([
+            AuditLogTable::UserId,
+            AuditLogTable::Action,
+            AuditLogTable::EntityType,
+            AuditLogTable::EntityId,
+            AuditLogTable::Before,
+            AuditLogTable::After,
+            AuditLogTable::Ip,
+            AuditLogTable::UserAgent,
+        ])
+        .values_panic([
+            entry.user_id.into(),
+            entry.action.as_ref().into(),
+            entry.entity_type.as_ref().into(),
+            entry.entity_id.into(),
+            entry.before.into(),
+            entry.after.into(),
+            entry.client.ip.clone().into(),
+            entry.client.user_agent.clone().into(),
+        ])
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+
+    query.execute(executor).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to write to the audit log".into()),
+        source: e,
+    })?;
+    Ok(())
+}
+
+/// Apply the filters of the query, users that aren't admins only get their own entries
+fn filtered(user: &UserRow, q: &AuditLogQuery) -> SelectStatement {
+    let mut query = Query::select();
+    query.from(AuditLogTable::Table);
+
+    if !user.role.is_admin() {
+        query.and_where(Expr::col(AuditLogTable::UserId).eq(user.id));
+    } else if let Some(user_id) = q.user_id {
+        query.and_where(Expr::col(AuditLogTable::UserId).eq(user_id));
+    }
+    if let Some(action) = q.action {
+        query.and_where(Expr::col(AuditLogTable::Action).eq(action.as_ref()));
+    }
+    if let Some(entity_type) = q.entity_type {
+        query.and_where(Expr::col(AuditLogTable::EntityType).eq(entity_type.as_ref()));
+    }
+    if let Some(entity_id) = &q.entity_id {
+        query.and_where(Expr::col(AuditLogTable::EntityId).eq(entity_id.as_str()));
+    }
+    if let Some(since) = q.since {
+        query.and_where(Expr::col(AuditLogTable::CreatedAt).gte(since));
+    }
+    if let Some(until) = q.until {
+        query.and_where(Expr::col(AuditLogTable::CreatedAt).lt(until));
+    }
+
+    query.take()
+}
+
+/// Get a page of the audit log, newest entries first
+pub(crate) async fn fetch_audit_log(
+    db: &PgPool,
+    user: &UserClaims,
+    q: AuditLogQuery,
+) -> Result<AuditLogPage, CommonError> {
+    // The role in the jwt can be from before the user stopped being an admin
+    let user = user.fetch_user(db).await?;
+    let page = q.page.max(1);
+    let per_page = q.per_page.clamp(1, MAX_PER_PAGE);
+    // Postgres takes offsets up to `i64::MAX`
+    let offset = (page - 1)
+        .checked_mul(per_page)
+        .filter(|offset| i64::try_from(*offset).is_ok())
+        .ok_or_else(|| {
+            CommonError::Validation(vec![FieldError {
+                field: "page".into(),
+                messages: vec!["is past the end of the audit log".into()],
+            }])
+        })?;
+
+    let (sql, values) = filtered(&user, &q)
+        .expr(Func::count(Expr::col(AuditLogTable::Id)))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+    let total: i64 = query.fetch_one(db).await?;
+
+    let (sql, values) = filtered(&user, &q)
+        .columns([
+            AuditLogTable::Id,
+            AuditLogTable::CreatedAt,
+            AuditLogTable::UserId,
+            AuditLogTable::Action,
+            AuditLogTable::EntityType,
+            AuditLogTable::EntityId,
+            AuditLogTable::Before,
+            AuditLogTable::After,
+            AuditLogTable::Ip,
+            AuditLogTable::UserAgent,
+        ])
+        .order_by(AuditLogTable::Id, Order::Desc)
+        .limit(per_page)
+        .offset(offset)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let entries = query.fetch_all(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to fetch the audit log from db".into()),
+        source: e,
+    })?;
+
+    Ok(AuditLogPage {
+        entries,
+        page,
+        per_page,
+        total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::user::Role, test_utils};
+
+    fn page(page: u64, per_page: u64) -> AuditLogQuery {
+        AuditLogQuery {
+            action: None,
+            entity_type: None,
+            entity_id: None,
+            user_id: None,
+            since: None,
+            until: None,
+            page,
+            per_page,
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_past_what_postgres_can_skip_are_invalid() {
+        let db = test_utils::db().await;
+        let id = test_utils::user(&db, Role::User).await;
+        let user = UserClaims {
+            id,
+            username: String::new(),
+            role: Role::User,
+        };
+
+        let far = fetch_audit_log(&db, &user, page(1_000_000, 200))
+            .await
+            .unwrap();
+        assert!(far.entries.is_empty());
+        assert_eq!(far.total, 1);
+
+        for huge in [page(u64::MAX, 200), page(u64::MAX / 200, 200)] {
+            let res = fetch_audit_log(&db, &user, huge).await;
+            assert!(
+                matches!(&res, Err(CommonError::Validation(errors)) if errors[0].field == "page"),
+                "{:?}",
+                res.map(|page| page.page)
+            );
+        }
+    }
+}
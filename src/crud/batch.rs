@@ -0,0 +1,247 @@
+use {
+    sqlx::PgPool,
+    uuid::Uuid,
+    validator::{Validate, ValidationErrors},
+};
+
+use crate::{
+    crud::{accounts, tags, transactions},
+    error::{FieldError, ItemError},
+    models::audit::ClientInfo,
+    requests::BatchOperation,
+    responses::BatchItemResult,
+    utils::{etag::EntityTags, validation::field_errors},
+    CommonError,
+};
+
+/// Apply all the operations in a single transaction, each one in its own savepoint.
+///
+/// Every operation is tried even after one fails, so all the failures can be reported at once,
+/// but if any of them failed the whole transaction is rolled back.
+pub(crate) async fn run_batch(
+    db: &PgPool,
+    user_id: Uuid,
+    operations: Vec<BatchOperation>,
+    client: &ClientInfo,
+) -> Result<Vec<BatchItemResult>, CommonError> {
+    let mut tx = db.begin().await?;
+    let mut results = Vec::with_capacity(operations.len());
+    let mut failed = Vec::new();
+
+    for (index, op) in operations.into_iter().enumerate() {
+        let res = match op {
+            BatchOperation::CreateAccount { data } => match data.validate() {
+                Ok(()) => accounts::create_account(&mut tx, user_id, data, client)
+                    .await
+                    .map(|account| BatchItemResult {
+                        id: account.id,
+                        version: Some(account.version),
+                    }),
+                Err(e) => Err(invalid_data(&e)),
+            },
+            BatchOperation::UpdateAccount { id, version, data } => match data.validate() {
+                Ok(()) => {
+                    let if_match = EntityTags::version(version);
+                    accounts::update_account(&mut tx, user_id, id, data, &if_match, client)
+                        .await
+                        .map(|account| BatchItemResult {
+                            id,
+                            version: Some(account.version),
+                        })
+                }
+                Err(e) => Err(invalid_data(&e)),
+            },
+            BatchOperation::DeleteAccount { id, version } => {
+                let if_match = EntityTags::version(version);
+                accounts::delete_account(&mut tx, user_id, id, &if_match, client)
+                    .await
+                    .map(|_| BatchItemResult { id, version: None })
+            }
+            BatchOperation::CreateTag { data } => match data.validate() {
+                Ok(()) => tags::create_tag(&mut tx, user_id, data, client)
+                    .await
+                    .map(|tag| BatchItemResult {
+                        id: tag.id,
+                        version: Some(tag.version),
+                    }),
+                Err(e) => Err(invalid_data(&e)),
+            },
+            BatchOperation::UpdateTag { id, version, data } => match data.validate() {
+                Ok(()) => {
+                    let if_match = EntityTags::version(version);
+                    tags::update_tag(&mut tx, user_id, id, data, &if_match, client)
+                        .await
+                        .map(|tag| BatchItemResult {
+                            id,
+                            version: Some(tag.version),
+                        })
+                }
+                Err(e) => Err(invalid_data(&e)),
+            },
+            BatchOperation::DeleteTag { id, version } => {
+                let if_match = EntityTags::version(version);
+                tags::delete_tag(&mut tx, user_id, id, &if_match, client)
+                    .await
+                    .map(|_| BatchItemResult { id, version: None })
+            }
+            BatchOperation::UpdateTransactionTags { id, tag_ids } => {
+                transactions::set_transaction_tags(&mut tx, user_id, id, tag_ids, client)
+                    .await
+                    .map(|_| BatchItemResult { id, version: None })
+            }
+            BatchOperation::DeleteTransaction { id } => {
+                transactions::delete_transaction(&mut tx, user_id, id, client)
+                    .await
+                    .map(|_| BatchItemResult { id, version: None })
+            }
+        };
+
+        match res {
+            Ok(result) => results.push(result),
+            Err(err) => failed.push(ItemError::new(index, err)),
+        }
+    }
+
+    if !failed.is_empty() {
+        tx.rollback().await?;
+        return Err(CommonError::Batch(failed));
+    }
+    tx.commit().await?;
+
+    Ok(results)
+}
+
+/// The validation errors of an operation's `data`
+fn invalid_data(errors: &ValidationErrors) -> CommonError {
+    let errors = field_errors(errors)
+        .into_iter()
+        .map(|FieldError { field, messages }| FieldError {
+            field: format!("data.{}", field),
+            messages,
+        })
+        .collect();
+    CommonError::Validation(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use {chrono::NaiveDate, sqlx::types::BigDecimal};
+
+    use super::*;
+    use crate::{
+        models::user::Role,
+        requests::{AccountCreateRequest, Party, TagCreate, TransactionCreate},
+        test_utils,
+    };
+
+    async fn account(db: &PgPool, user_id: Uuid, name: &str) -> i32 {
+        let new = AccountCreateRequest {
+            name: name.into(),
+            description: None,
+            starting_money: Some(100.into()),
+            is_adhoc: false,
+            household_id: None,
+        };
+        accounts::create_account(db, user_id, new, &Default::default())
+            .await
+            .expect("Failed to create an account")
+            .id
+    }
+
+    async fn tag(db: &PgPool, user_id: Uuid, name: &str) -> i32 {
+        let new = TagCreate {
+            // Tag names are unique across all users
+            name: format!("{} {}", name, user_id),
+            description: None,
+            limit: None,
+            starting_money: None,
+            household_id: None,
+        };
+        tags::create_tag(db, user_id, new, &Default::default())
+            .await
+            .expect("Failed to create a tag")
+            .id
+    }
+
+    async fn transaction(db: &PgPool, user_id: Uuid, source: i32, tag_ids: Vec<i32>) -> i32 {
+        let new = TransactionCreate {
+            source: Party::Account(source),
+            destination: Party::NewPayee("Market".into()),
+            amount: 10.into(),
+            description: None,
+            happened_on: NaiveDate::from_ymd(2022, 2, 1),
+            tag_ids,
+            client_id: None,
+        };
+        transactions::create_transaction(db, user_id, new, &Default::default())
+            .await
+            .expect("Failed to create a transaction")
+            .id
+    }
+
+    async fn balance(db: &PgPool, user_id: Uuid, tag_id: i32) -> BigDecimal {
+        tags::fetch_tag(db, user_id, tag_id).await.unwrap().balance
+    }
+
+    async fn money(db: &PgPool, user_id: Uuid, account_id: i32) -> Option<BigDecimal> {
+        let account = accounts::fetch_account(db, &user_id, account_id).await;
+        account.unwrap().total_money
+    }
+
+    #[tokio::test]
+    async fn transactions_are_retagged_and_deleted() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let cash = account(&db, user, "Cash").await;
+        let (food, fun) = (tag(&db, user, "Food").await, tag(&db, user, "Fun").await);
+        let retagged = transaction(&db, user, cash, vec![food]).await;
+        let deleted = transaction(&db, user, cash, vec![food]).await;
+
+        let operations = vec![
+            BatchOperation::UpdateTransactionTags {
+                id: retagged,
+                tag_ids: vec![fun],
+            },
+            BatchOperation::DeleteTransaction { id: deleted },
+        ];
+        let results = run_batch(&db, user, operations, &Default::default())
+            .await
+            .expect("Failed to run the batch");
+        let ids: Vec<i32> = results.iter().map(|result| result.id).collect();
+        assert_eq!(ids, [retagged, deleted]);
+
+        assert_eq!(balance(&db, user, food).await, 0.into());
+        assert_eq!(balance(&db, user, fun).await, 10.into());
+        assert_eq!(money(&db, user, cash).await, Some(90.into()));
+    }
+
+    #[tokio::test]
+    async fn a_transaction_operation_that_fails_rolls_the_others_back() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let cash = account(&db, user, "Cash").await;
+        let food = tag(&db, user, "Food").await;
+        let deleted = transaction(&db, user, cash, vec![food]).await;
+        let retagged = transaction(&db, user, cash, vec![food]).await;
+        let stranger = test_utils::user(&db, Role::User).await;
+        let hidden = tag(&db, stranger, "Hidden").await;
+
+        let operations = vec![
+            BatchOperation::DeleteTransaction { id: deleted },
+            BatchOperation::UpdateTransactionTags {
+                id: retagged,
+                tag_ids: vec![hidden],
+            },
+        ];
+        let result = run_batch(&db, user, operations, &Default::default()).await;
+        let failed = match result {
+            Err(CommonError::Batch(failed)) => failed,
+            result => panic!("The batch didn't fail: {:?}", result),
+        };
+        let failed: Vec<(usize, u16)> = failed.iter().map(|e| (e.index, e.status)).collect();
+        assert_eq!(failed, [(1, 404)]);
+
+        assert_eq!(balance(&db, user, food).await, 20.into());
+        assert_eq!(money(&db, user, cash).await, Some(80.into()));
+    }
+}
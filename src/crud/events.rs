@@ -0,0 +1,58 @@
+use {
+    sea_query::{bind_params_sqlx_postgres, Expr, PostgresQueryBuilder, Query, Value},
+    serde_json::{json, Value as JsonValue},
+    sqlx::PgConnection,
+    uuid::Uuid,
+};
+
+use super::webhooks;
+use crate::{
+    models::{household::HouseholdMemberTable, webhook::WebhookEvent},
+    utils::events::CHANNEL,
+    CommonError,
+};
+
+/// Tell everyone about a change to an entity: the webhooks of the owner get it, and so do the event streams
+/// of the owner and of the members of `households`, the households it is or was shared with.
+///
+/// Pass the same transaction as the change itself, postgres only sends the notification when it's committed.
+pub(crate) async fn publish(
+    conn: &mut PgConnection,
+    owner: Uuid,
+    households: &[Option<i32>],
+    event: WebhookEvent,
+    data: JsonValue,
+) -> Result<(), CommonError> {
+    webhooks::enqueue(conn, owner, event, data.clone()).await?;
+
+    let mut users = vec![owner];
+    let households: Vec<i32> = households.iter().flatten().copied().collect();
+    if !households.is_empty() {
+        let (sql, values) = Query::select()
+            .distinct()
+            .column(HouseholdMemberTable::UserId)
+            .from(HouseholdMemberTable::Table)
+            .and_where(Expr::col(HouseholdMemberTable::HouseholdId).is_in(households))
+            .and_where(Expr::col(HouseholdMemberTable::UserId).ne(owner))
+            .build(PostgresQueryBuilder);
+        let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+        let members: Vec<Uuid> = query.fetch_all(&mut *conn).await?;
+        users.extend(members);
+    }
+
+    let payload = json!({
+        "users": users,
+        "event": event,
+        "data": data,
+    });
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(CHANNEL)
+        .bind(payload.to_string())
+        .execute(conn)
+        .await
+        .map_err(|e| CommonError::Db {
+            msg: Some("Failed to notify the event streams".into()),
+            source: e,
+        })?;
+    Ok(())
+}
@@ -0,0 +1,570 @@
+use std::borrow::Cow;
+
+use {
+    sea_query::{
+        bind_params_sqlx_postgres, Expr, LockType, Order, PostgresQueryBuilder, Query, Value,
+    },
+    serde_json::json,
+    sqlx::{types::BigDecimal, Acquire, PgConnection, PgPool, Postgres},
+    strum::IntoEnumIterator,
+    uuid::Uuid,
+};
+
+use super::{audit, events, webhooks};
+use crate::{
+    error::FieldError,
+    models::{
+        account::*,
+        audit::{AuditAction, AuditEntity, AuditEntry, ClientInfo},
+        goal::*,
+        webhook::WebhookEvent,
+    },
+    requests::{GoalCreate, GoalTransfer, GoalUpdate},
+    utils::{self, etag::EntityTags},
+    CommonError,
+};
+
+/// The goals of the user, they are never shared with a household
+pub(crate) async fn fetch_goals(db: &PgPool, user_id: Uuid) -> Result<Vec<GoalRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(GoalTable::iter().skip(1))
+        .from(GoalTable::Table)
+        .and_where(Expr::col(GoalTable::UserId).eq(user_id))
+        .order_by(GoalTable::Name, Order::Asc)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    Ok(query.fetch_all(db).await?)
+}
+
+pub(crate) async fn fetch_goal(
+    db: &PgPool,
+    user_id: Uuid,
+    id: i32,
+) -> Result<GoalRow, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(GoalTable::iter().skip(1))
+        .from(GoalTable::Table)
+        .and_where(Expr::col(GoalTable::Id).eq(id))
+        .and_where(Expr::col(GoalTable::UserId).eq(user_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    query.fetch_optional(db).await?.ok_or(CommonError::NotFound)
+}
+
+/// Create a goal with nothing set aside yet, in one of the user's own normal accounts
+pub(crate) async fn create_goal<'c, A>(
+    db: A,
+    user_id: Uuid,
+    goal: GoalCreate,
+    client: &ClientInfo,
+) -> Result<GoalRow, CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+    lock_account(&mut tx, user_id, goal.account_id)
+        .await?
+        .ok_or_else(|| invalid("account_id", "must be one of your normal accounts"))?;
+
+    let (sql, values) = Query::insert()
+        .into_table(GoalTable::Table)
+        .columns([
+            GoalTable::Name,
+            GoalTable::Description,
+            GoalTable::Target,
+            GoalTable::AccountId,
+            GoalTable::UserId,
+        ])
+        .values_panic([
+            goal.name.into(),
+            goal.description.into(),
+            goal.target.into(),
+            goal.account_id.into(),
+            user_id.into(),
+        ])
+        .returning(Query::select().columns(GoalTable::iter().skip(1)).take())
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let goal: GoalRow = query.fetch_one(&mut tx).await.map_err(|e| {
+        let msg = if utils::err_is_failed_constraint(&e) {
+            Some(Cow::Borrowed("There already is a goal with that name"))
+        } else {
+            None
+        };
+
+        CommonError::Db { msg, source: e }
+    })?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Create,
+        entity_type: AuditEntity::Goal,
+        entity_id: Some(goal.id.to_string()),
+        before: None,
+        after: Some(json!(goal)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        goal.user_id,
+        &[],
+        WebhookEvent::GoalCreated,
+        json!(goal),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(goal)
+}
+
+/// Change the name, description and target of a goal, if it's still at a version in `if_match`.
+///
+/// Returns the updated goal.
+pub(crate) async fn update_goal<'c, A>(
+    db: A,
+    user_id: Uuid,
+    goal_id: i32,
+    update: GoalUpdate,
+    if_match: &EntityTags,
+    client: &ClientInfo,
+) -> Result<GoalRow, CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    let mut goal = lock_goal(&mut tx, user_id, goal_id).await?;
+    if !if_match.matches_version(goal.version) {
+        return Err(CommonError::VersionMismatch);
+    }
+
+    let (sql, values) = Query::update()
+        .table(GoalTable::Table)
+        .values([
+            (GoalTable::Name, update.name.clone().into()),
+            (GoalTable::Description, update.description.clone().into()),
+            (GoalTable::Target, update.target.clone().into()),
+            (GoalTable::Version, (goal.version + 1).into()),
+        ])
+        .and_where(Expr::col(GoalTable::Id).eq(goal_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await.map_err(|e| {
+        let msg = if utils::err_is_failed_constraint(&e) {
+            Some(Cow::Borrowed("There already is a goal with that name"))
+        } else {
+            None
+        };
+
+        CommonError::Db { msg, source: e }
+    })?;
+
+    let was_reached = goal.is_reached();
+    let before = json!(goal);
+    goal.name = update.name;
+    goal.description = update.description;
+    goal.target = update.target;
+    goal.version += 1;
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::Goal,
+        entity_id: Some(goal_id.to_string()),
+        before: Some(before),
+        after: Some(json!(goal)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    publish_update(&mut tx, &goal, was_reached).await?;
+    tx.commit().await?;
+
+    Ok(goal)
+}
+
+/// Delete the goal, if it's still at a version in `if_match`.
+/// What was set aside for it is available in its account again.
+pub(crate) async fn delete_goal<'c, A>(
+    db: A,
+    user_id: Uuid,
+    goal_id: i32,
+    if_match: &EntityTags,
+    client: &ClientInfo,
+) -> Result<(), CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    let account_id = fetch_account_id(&mut tx, user_id, goal_id).await?;
+    let account = lock_account(&mut tx, user_id, account_id)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    let goal = lock_goal(&mut tx, user_id, goal_id).await?;
+    if !if_match.matches_version(goal.version) {
+        return Err(CommonError::VersionMismatch);
+    }
+
+    let (sql, values) = Query::delete()
+        .from_table(GoalTable::Table)
+        .and_where(Expr::col(GoalTable::Id).eq(goal_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+    set_aside(&mut tx, account, &-goal.balance.clone()).await?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Delete,
+        entity_type: AuditEntity::Goal,
+        entity_id: Some(goal_id.to_string()),
+        before: Some(json!(goal)),
+        after: None,
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        goal.user_id,
+        &[],
+        WebhookEvent::GoalDeleted,
+        json!(goal),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Set money of the account aside for the goal, or give it back to the account when the amount is negative.
+/// Neither the available money of the account nor the balance of the goal can go below 0 because of it.
+///
+/// Returns the updated goal.
+pub(crate) async fn transfer<'c, A>(
+    db: A,
+    user_id: Uuid,
+    goal_id: i32,
+    transfer: GoalTransfer,
+    client: &ClientInfo,
+) -> Result<GoalRow, CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    // The account is locked first, the same as for transactions
+    let account_id = fetch_account_id(&mut tx, user_id, goal_id).await?;
+    let account = lock_account(&mut tx, user_id, account_id)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    let mut goal = lock_goal(&mut tx, user_id, goal_id).await?;
+
+    let zero = BigDecimal::from(0);
+    let available = account.available_money.clone().unwrap_or_default();
+    if transfer.amount > zero && transfer.amount > available {
+        return Err(invalid("amount", "is more than the account has available"));
+    }
+    if transfer.amount < zero && -transfer.amount.clone() > goal.balance {
+        return Err(invalid("amount", "is more than was set aside for the goal"));
+    }
+
+    let balance = &goal.balance + &transfer.amount;
+    let (sql, values) = Query::update()
+        .table(GoalTable::Table)
+        .values([
+            (GoalTable::Balance, balance.clone().into()),
+            (GoalTable::Version, (goal.version + 1).into()),
+        ])
+        .and_where(Expr::col(GoalTable::Id).eq(goal_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+    set_aside(&mut tx, account, &transfer.amount).await?;
+
+    let was_reached = goal.is_reached();
+    let before = json!(goal);
+    goal.balance = balance;
+    goal.version += 1;
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::Goal,
+        entity_id: Some(goal_id.to_string()),
+        before: Some(before),
+        after: Some(json!(goal)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    publish_update(&mut tx, &goal, was_reached).await?;
+    tx.commit().await?;
+
+    Ok(goal)
+}
+
+/// Send `goal.updated`, and `goal.reached` too if it wasn't reached before the change
+async fn publish_update(
+    conn: &mut PgConnection,
+    goal: &GoalRow,
+    was_reached: bool,
+) -> Result<(), CommonError> {
+    events::publish(
+        &mut *conn,
+        goal.user_id,
+        &[],
+        WebhookEvent::GoalUpdated,
+        json!(goal),
+    )
+    .await?;
+    if goal.is_reached() && !was_reached {
+        events::publish(
+            conn,
+            goal.user_id,
+            &[],
+            WebhookEvent::GoalReached,
+            json!(goal),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Take `amount` out of the available money of the account, the total money stays the same
+async fn set_aside(
+    conn: &mut PgConnection,
+    mut account: AccountRow,
+    amount: &BigDecimal,
+) -> Result<(), CommonError> {
+    if amount == &BigDecimal::from(0) {
+        return Ok(());
+    }
+
+    let before = account.available_money.clone().unwrap_or_default();
+    let after = &before - amount;
+    account.available_money = Some(after.clone());
+    account.version += 1;
+    let (sql, values) = Query::update()
+        .table(AccountTable::Table)
+        .values([
+            (
+                AccountTable::AvailableMoney,
+                account.available_money.clone().into(),
+            ),
+            (AccountTable::Version, account.version.into()),
+        ])
+        .and_where(Expr::col(AccountTable::Id).eq(account.id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut *conn).await?;
+
+    events::publish(
+        &mut *conn,
+        account.user_id,
+        &[account.household_id],
+        WebhookEvent::AccountUpdated,
+        json!(account),
+    )
+    .await?;
+    webhooks::enqueue_balance_below(conn, account.user_id, &before, &after, json!(account)).await
+}
+
+/// The account of the goal, without locking anything so the account can be locked before the goal
+async fn fetch_account_id(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    goal_id: i32,
+) -> Result<i32, CommonError> {
+    let (sql, values) = Query::select()
+        .column(GoalTable::AccountId)
+        .from(GoalTable::Table)
+        .and_where(Expr::col(GoalTable::Id).eq(goal_id))
+        .and_where(Expr::col(GoalTable::UserId).eq(user_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+
+    query
+        .fetch_optional(conn)
+        .await?
+        .ok_or(CommonError::NotFound)
+}
+
+/// Lock one of the user's own normal accounts, goals can't be in accounts of anyone else
+async fn lock_account(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    account_id: i32,
+) -> Result<Option<AccountRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(AccountTable::iter().skip(1))
+        .from(AccountTable::Table)
+        .and_where(Expr::col(AccountTable::Id).eq(account_id))
+        .and_where(Expr::col(AccountTable::UserId).eq(user_id))
+        .and_where(Expr::col(AccountTable::IsAdhoc).eq(false))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    Ok(query.fetch_optional(conn).await?)
+}
+
+async fn lock_goal(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    goal_id: i32,
+) -> Result<GoalRow, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(GoalTable::iter().skip(1))
+        .from(GoalTable::Table)
+        .and_where(Expr::col(GoalTable::Id).eq(goal_id))
+        .and_where(Expr::col(GoalTable::UserId).eq(user_id))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    query
+        .fetch_optional(conn)
+        .await?
+        .ok_or(CommonError::NotFound)
+}
+
+fn invalid(field: &str, message: &str) -> CommonError {
+    CommonError::Validation(vec![FieldError {
+        field: field.into(),
+        messages: vec![message.into()],
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crud::{accounts, webhooks},
+        models::user::Role,
+        requests::AccountCreateRequest,
+        test_utils,
+    };
+
+    async fn available_money(db: &PgPool, account_id: i32) -> (BigDecimal, BigDecimal) {
+        sqlx::query_as("SELECT available_money, total_money FROM accounts WHERE id = $1")
+            .bind(account_id)
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn transfers_set_money_aside_and_the_goal_is_reached_once_per_crossing() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let client = ClientInfo::default();
+        let account = AccountCreateRequest {
+            name: "Savings".into(),
+            description: None,
+            starting_money: Some(100.into()),
+            is_adhoc: false,
+            household_id: None,
+        };
+        let account = accounts::create_account(&db, user, account, &client)
+            .await
+            .unwrap();
+        // Inserted directly, creating it would look up the host of the url
+        let webhook_id: i32 = sqlx::query_scalar(
+            "INSERT INTO webhooks (user_id, url, secret, events) VALUES ($1, $2, '', $3) RETURNING id",
+        )
+        .bind(user)
+        .bind("https://example.com/hook")
+        .bind(json!([WebhookEvent::GoalReached]))
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+        let goal = GoalCreate {
+            name: "Bike".into(),
+            description: None,
+            account_id: account.id,
+            target: 50.into(),
+        };
+        let goal = create_goal(&db, user, goal, &client).await.unwrap();
+        let move_money = |amount: i32| {
+            transfer(
+                &db,
+                user,
+                goal.id,
+                GoalTransfer {
+                    amount: amount.into(),
+                },
+                &client,
+            )
+        };
+
+        let goal = move_money(30).await.unwrap();
+        assert_eq!(goal.balance, 30.into());
+        assert_eq!(
+            available_money(&db, account.id).await,
+            (70.into(), 100.into())
+        );
+        assert!(matches!(
+            move_money(71).await,
+            Err(CommonError::Validation(errors)) if errors[0].field == "amount"
+        ));
+        assert!(matches!(
+            move_money(-31).await,
+            Err(CommonError::Validation(errors)) if errors[0].field == "amount"
+        ));
+
+        // Reached, still reached, not reached anymore and reached again
+        for amount in [20, 10, -20, 10] {
+            move_money(amount).await.unwrap();
+        }
+        let reached = webhooks::fetch_deliveries(&db, user, webhook_id)
+            .await
+            .unwrap();
+        assert_eq!(reached.len(), 2);
+        assert_eq!(reached[0].payload["data"]["balance"], "50");
+
+        let goal = fetch_goal(&db, user, goal.id).await.unwrap();
+        assert_eq!(goal.balance, 50.into());
+        let if_match = EntityTags::version(goal.version);
+        delete_goal(&db, user, goal.id, &if_match, &client)
+            .await
+            .unwrap();
+        assert_eq!(
+            available_money(&db, account.id).await,
+            (100.into(), 100.into())
+        );
+    }
+
+    #[tokio::test]
+    async fn goals_are_only_in_normal_accounts_of_their_owner() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let stranger = test_utils::user(&db, Role::User).await;
+        let client = ClientInfo::default();
+        let account = |name: &str, is_adhoc| AccountCreateRequest {
+            name: name.into(),
+            description: None,
+            starting_money: Some(10.into()),
+            is_adhoc,
+            household_id: None,
+        };
+        let adhoc = accounts::create_account(&db, user, account("Kiosk", true), &client)
+            .await
+            .unwrap();
+        let other = accounts::create_account(&db, stranger, account("Wallet", false), &client)
+            .await
+            .unwrap();
+
+        for account_id in [adhoc.id, other.id] {
+            let goal = GoalCreate {
+                name: "Holidays".into(),
+                description: None,
+                account_id,
+                target: 10.into(),
+            };
+            assert!(matches!(
+                create_goal(&db, user, goal, &client).await,
+                Err(CommonError::Validation(errors)) if errors[0].field == "account_id"
+            ));
+        }
+    }
+}
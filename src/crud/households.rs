@@ -0,0 +1,586 @@
+use std::borrow::Cow;
+
+use {
+    axum::http::StatusCode,
+    sea_query::{
+        bind_params_sqlx_postgres, Expr, IntoColumnRef, LockType, PostgresQueryBuilder, Query,
+        SelectStatement, SimpleExpr, Value,
+    },
+    serde_json::json,
+    sqlx::{Executor, PgPool, Postgres, Transaction},
+    uuid::Uuid,
+};
+
+use super::audit;
+use crate::{
+    error::ErrorCode,
+    models::{audit::*, household::*, user::*},
+    requests::{HouseholdCreate, InviteCreate},
+    utils, CommonError,
+};
+
+/// Ids of the households the user is a member of, if `roles` isn't empty only those where they have one of the `roles`
+fn member_households(user_id: Uuid, roles: &[MemberRole]) -> SelectStatement {
+    let mut query = Query::select();
+    query
+        .column(HouseholdMemberTable::HouseholdId)
+        .from(HouseholdMemberTable::Table)
+        .and_where(Expr::col(HouseholdMemberTable::UserId).eq(user_id));
+    if !roles.is_empty() {
+        query.and_where(
+            Expr::col(HouseholdMemberTable::Role).is_in(roles.iter().map(|r| r.as_ref())),
+        );
+    }
+    query.take()
+}
+
+/// A condition that matches rows the user can see.
+///
+/// That's rows they own or rows that belong to a household they are a member of.
+pub(crate) fn visible_to<O, H>(user_id: Uuid, owner_col: O, household_col: H) -> SimpleExpr
+where
+    O: IntoColumnRef,
+    H: IntoColumnRef,
+{
+    Expr::col(owner_col)
+        .eq(user_id)
+        .or(Expr::col(household_col).in_subquery(member_households(user_id, &[])))
+}
+
+/// Get the role the user has in the given household, if they are a member
+pub(crate) async fn fetch_member_role<'c, E>(
+    db: E,
+    user_id: Uuid,
+    household_id: i32,
+) -> Result<Option<MemberRole>, CommonError>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let (sql, values) = Query::select()
+        .column(HouseholdMemberTable::Role)
+        .from(HouseholdMemberTable::Table)
+        .and_where(Expr::col(HouseholdMemberTable::HouseholdId).eq(household_id))
+        .and_where(Expr::col(HouseholdMemberTable::UserId).eq(user_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+
+    Ok(query.fetch_optional(db).await?)
+}
+
+/// Make sure the user is a member of the household and that their role passes `check`
+pub(crate) async fn require_member_role<'c, E>(
+    db: E,
+    user_id: Uuid,
+    household_id: i32,
+    check: fn(MemberRole) -> bool,
+) -> Result<(), CommonError>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    match fetch_member_role(db, user_id, household_id).await? {
+        Some(role) if check(role) => Ok(()),
+        Some(_) => Err(CommonError::Forbidden),
+        None => Err(CommonError::NotFound),
+    }
+}
+
+/// Get the households the user is a member of
+pub(crate) async fn fetch_households(
+    db: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<HouseholdRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns([
+            HouseholdTable::Id,
+            HouseholdTable::Name,
+            HouseholdTable::CreatedBy,
+        ])
+        .from(HouseholdTable::Table)
+        .and_where(Expr::col(HouseholdTable::Id).in_subquery(member_households(user_id, &[])))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    query.fetch_all(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to fetch households from db".into()),
+        source: e,
+    })
+}
+
+/// Get the households with the given ids, leaving out the ones the user is not a member of
+pub(crate) async fn fetch_households_by_id(
+    db: &PgPool,
+    user_id: Uuid,
+    ids: &[i32],
+) -> Result<Vec<HouseholdRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns([
+            HouseholdTable::Id,
+            HouseholdTable::Name,
+            HouseholdTable::CreatedBy,
+        ])
+        .from(HouseholdTable::Table)
+        .and_where(Expr::col(HouseholdTable::Id).is_in(ids.iter().copied()))
+        .and_where(Expr::col(HouseholdTable::Id).in_subquery(member_households(user_id, &[])))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    Ok(query.fetch_all(db).await?)
+}
+
+/// Get the members of the given households, leaving out the households the user is not a member of
+pub(crate) async fn fetch_memberships(
+    db: &PgPool,
+    user_id: Uuid,
+    household_ids: &[i32],
+) -> Result<Vec<HouseholdMembership>, CommonError> {
+    let (sql, values) = Query::select()
+        .column((
+            HouseholdMemberTable::Table,
+            HouseholdMemberTable::HouseholdId,
+        ))
+        .column((HouseholdMemberTable::Table, HouseholdMemberTable::UserId))
+        .column((UserTable::Table, UserTable::Username))
+        .column((HouseholdMemberTable::Table, HouseholdMemberTable::Role))
+        .from(HouseholdMemberTable::Table)
+        .inner_join(
+            UserTable::Table,
+            Expr::tbl(UserTable::Table, UserTable::Id)
+                .equals(HouseholdMemberTable::Table, HouseholdMemberTable::UserId),
+        )
+        .and_where(
+            Expr::tbl(
+                HouseholdMemberTable::Table,
+                HouseholdMemberTable::HouseholdId,
+            )
+            .is_in(household_ids.iter().copied()),
+        )
+        .and_where(
+            Expr::tbl(
+                HouseholdMemberTable::Table,
+                HouseholdMemberTable::HouseholdId,
+            )
+            .in_subquery(member_households(user_id, &[])),
+        )
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    Ok(query.fetch_all(db).await?)
+}
+
+/// Get a household together with its members, as long as the user is a member
+pub(crate) async fn fetch_household(
+    db: &PgPool,
+    user_id: Uuid,
+    id: i32,
+) -> Result<HouseholdDetails, CommonError> {
+    let (sql, values) = Query::select()
+        .columns([
+            HouseholdTable::Id,
+            HouseholdTable::Name,
+            HouseholdTable::CreatedBy,
+        ])
+        .from(HouseholdTable::Table)
+        .and_where(Expr::col(HouseholdTable::Id).eq(id))
+        .and_where(Expr::col(HouseholdTable::Id).in_subquery(member_households(user_id, &[])))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let household: HouseholdRow = query
+        .fetch_optional(db)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+
+    let (sql, values) = Query::select()
+        .column((HouseholdMemberTable::Table, HouseholdMemberTable::UserId))
+        .column((UserTable::Table, UserTable::Username))
+        .column((HouseholdMemberTable::Table, HouseholdMemberTable::Role))
+        .from(HouseholdMemberTable::Table)
+        .inner_join(
+            UserTable::Table,
+            Expr::tbl(UserTable::Table, UserTable::Id)
+                .equals(HouseholdMemberTable::Table, HouseholdMemberTable::UserId),
+        )
+        .and_where(
+            Expr::tbl(
+                HouseholdMemberTable::Table,
+                HouseholdMemberTable::HouseholdId,
+            )
+            .eq(id),
+        )
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let members = query.fetch_all(db).await?;
+
+    Ok(HouseholdDetails { household, members })
+}
+
+/// Create a household, the user creating it becomes its owner.
+///
+/// Returns the created household's id if successful.
+pub(crate) async fn create_household(
+    db: &PgPool,
+    user_id: Uuid,
+    household: HouseholdCreate,
+    client: &ClientInfo,
+) -> Result<i32, CommonError> {
+    let mut tx = db.begin().await?;
+
+    let (sql, values) = Query::insert()
+        .into_table(HouseholdTable::Table)
+        .columns([HouseholdTable::Name, HouseholdTable::CreatedBy])
+        .values_panic([household.name.into(), user_id.into()])
+        .returning(
+            Query::select()
+                .columns([
+                    HouseholdTable::Id,
+                    HouseholdTable::Name,
+                    HouseholdTable::CreatedBy,
+                ])
+                .take(),
+        )
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let household: HouseholdRow = query.fetch_one(&mut tx).await?;
+
+    add_member(&mut tx, household.id, user_id, MemberRole::Owner).await?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Create,
+        entity_type: AuditEntity::Household,
+        entity_id: Some(household.id.to_string()),
+        before: None,
+        after: Some(json!(household)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(household.id)
+}
+
+async fn add_member(
+    tx: &mut Transaction<'_, Postgres>,
+    household_id: i32,
+    user_id: Uuid,
+    role: MemberRole,
+) -> Result<(), CommonError> {
+    let (sql, values) = Query::insert()
+        .into_table(HouseholdMemberTable::Table)
+        .columns([
+            HouseholdMemberTable::HouseholdId,
+            HouseholdMemberTable::UserId,
+            HouseholdMemberTable::Role,
+        ])
+        .values_panic([household_id.into(), user_id.into(), role.as_ref().into()])
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(tx).await?;
+    Ok(())
+}
+
+/// Invite a user to a household, only owners can invite people.
+///
+/// Returns the created invite's id if successful.
+pub(crate) async fn create_invite(
+    db: &PgPool,
+    user_id: Uuid,
+    household_id: i32,
+    invite: InviteCreate,
+    client: &ClientInfo,
+) -> Result<i32, CommonError> {
+    require_member_role(db, user_id, household_id, MemberRole::can_manage).await?;
+
+    let invitee = crate::crud::fetch_user_from(db, &UserIdent::Username(invite.username))
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    if fetch_member_role(db, invitee.id, household_id)
+        .await?
+        .is_some()
+    {
+        let err = (StatusCode::CONFLICT, "User is already a member");
+        return Err(err.into());
+    }
+
+    let (sql, values) = Query::insert()
+        .into_table(HouseholdInviteTable::Table)
+        .columns([
+            HouseholdInviteTable::HouseholdId,
+            HouseholdInviteTable::UserId,
+            HouseholdInviteTable::InvitedBy,
+            HouseholdInviteTable::Role,
+        ])
+        .values_panic([
+            household_id.into(),
+            invitee.id.into(),
+            user_id.into(),
+            invite.role.unwrap_or(MemberRole::Editor).as_ref().into(),
+        ])
+        .returning(invite_columns())
+        .build(PostgresQueryBuilder);
+
+    let mut tx = db.begin().await?;
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let invite: HouseholdInviteRow = query.fetch_one(&mut tx).await.map_err(|e| {
+        let msg = if utils::err_is_failed_constraint(&e) {
+            Some(Cow::Borrowed("User has already been invited"))
+        } else {
+            None
+        };
+
+        CommonError::Db { msg, source: e }
+    })?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Create,
+        entity_type: AuditEntity::HouseholdInvite,
+        entity_id: Some(invite.id.to_string()),
+        before: None,
+        after: Some(json!(invite)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(invite.id)
+}
+
+fn invite_columns() -> SelectStatement {
+    Query::select()
+        .columns([
+            HouseholdInviteTable::Id,
+            HouseholdInviteTable::HouseholdId,
+            HouseholdInviteTable::UserId,
+            HouseholdInviteTable::InvitedBy,
+            HouseholdInviteTable::Role,
+        ])
+        .take()
+}
+
+/// Get the pending invites of the user
+pub(crate) async fn fetch_invites(
+    db: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<HouseholdInviteRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns([
+            HouseholdInviteTable::Id,
+            HouseholdInviteTable::HouseholdId,
+            HouseholdInviteTable::UserId,
+            HouseholdInviteTable::InvitedBy,
+            HouseholdInviteTable::Role,
+        ])
+        .from(HouseholdInviteTable::Table)
+        .and_where(Expr::col(HouseholdInviteTable::UserId).eq(user_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    query.fetch_all(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to fetch invites from db".into()),
+        source: e,
+    })
+}
+
+/// Accept an invite, making the user a member of the household
+pub(crate) async fn accept_invite(
+    db: &PgPool,
+    user_id: Uuid,
+    invite_id: i32,
+    client: &ClientInfo,
+) -> Result<(), CommonError> {
+    let mut tx = db.begin().await?;
+    let invite = take_invite(&mut tx, user_id, invite_id).await?;
+
+    add_member(&mut tx, invite.household_id, user_id, invite.role).await?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Create,
+        entity_type: AuditEntity::HouseholdMember,
+        entity_id: Some(format!("{}/{}", invite.household_id, user_id)),
+        before: None,
+        after: Some(json!(invite)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Decline an invite
+pub(crate) async fn delete_invite(
+    db: &PgPool,
+    user_id: Uuid,
+    invite_id: i32,
+    client: &ClientInfo,
+) -> Result<(), CommonError> {
+    let mut tx = db.begin().await?;
+    let invite = take_invite(&mut tx, user_id, invite_id).await?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Delete,
+        entity_type: AuditEntity::HouseholdInvite,
+        entity_id: Some(invite_id.to_string()),
+        before: Some(json!(invite)),
+        after: None,
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Delete an invite of the user and return it
+async fn take_invite(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    invite_id: i32,
+) -> Result<HouseholdInviteRow, CommonError> {
+    let (sql, values) = Query::delete()
+        .from_table(HouseholdInviteTable::Table)
+        .and_where(Expr::col(HouseholdInviteTable::Id).eq(invite_id))
+        .and_where(Expr::col(HouseholdInviteTable::UserId).eq(user_id))
+        .returning(invite_columns())
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    query.fetch_optional(tx).await?.ok_or(CommonError::NotFound)
+}
+
+/// Remove a member from a household.
+/// Anyone can leave a household but only owners can remove other members, and the last owner can't be removed.
+pub(crate) async fn remove_member(
+    db: &PgPool,
+    user_id: Uuid,
+    household_id: i32,
+    member_id: Uuid,
+    client: &ClientInfo,
+) -> Result<(), CommonError> {
+    if user_id != member_id {
+        require_member_role(db, user_id, household_id, MemberRole::can_manage).await?;
+    }
+
+    let mut tx = db.begin().await?;
+    // Locked so two owners can't remove each other at the same time
+    let (sql, values) = Query::select()
+        .column(HouseholdMemberTable::UserId)
+        .from(HouseholdMemberTable::Table)
+        .and_where(Expr::col(HouseholdMemberTable::HouseholdId).eq(household_id))
+        .and_where(Expr::col(HouseholdMemberTable::Role).eq(MemberRole::Owner.as_ref()))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+    let owners: Vec<Uuid> = query.fetch_all(&mut tx).await?;
+    if owners == [member_id] {
+        return Err(CommonError::Rejected {
+            code: ErrorCode::LastOwner,
+            detail: "The household needs another owner before its last one can be removed".into(),
+        });
+    }
+
+    let (sql, values) = Query::delete()
+        .from_table(HouseholdMemberTable::Table)
+        .and_where(Expr::col(HouseholdMemberTable::HouseholdId).eq(household_id))
+        .and_where(Expr::col(HouseholdMemberTable::UserId).eq(member_id))
+        .returning(Query::select().column(HouseholdMemberTable::Role).take())
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+    let role: MemberRole = query
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Delete,
+        entity_type: AuditEntity::HouseholdMember,
+        entity_id: Some(format!("{}/{}", household_id, member_id)),
+        before: Some(json!({ "household_id": household_id, "user_id": member_id, "role": role })),
+        after: None,
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crud, test_utils};
+
+    /// A new household of `owner` with `member` in it
+    async fn household(db: &PgPool, owner: Uuid, member: Uuid, role: MemberRole) -> i32 {
+        let client = ClientInfo::default();
+        let new = HouseholdCreate {
+            name: "Home".into(),
+        };
+        let household_id = create_household(db, owner, new, &client).await.unwrap();
+
+        let member_row = crud::fetch_user_from(db, &UserIdent::Id(member))
+            .await
+            .unwrap()
+            .expect("The member exists");
+        let invite = InviteCreate {
+            username: member_row.username,
+            role: Some(role),
+        };
+        let invite_id = create_invite(db, owner, household_id, invite, &client)
+            .await
+            .unwrap();
+        accept_invite(db, member, invite_id, &client).await.unwrap();
+        household_id
+    }
+
+    #[tokio::test]
+    async fn the_last_owner_cant_be_removed() {
+        let db = test_utils::db().await;
+        let (owner, member) = (
+            test_utils::user(&db, Role::User).await,
+            test_utils::user(&db, Role::User).await,
+        );
+        let household_id = household(&db, owner, member, MemberRole::Owner).await;
+        let client = ClientInfo::default();
+
+        // One of the two owners can leave, then the other one has to stay
+        remove_member(&db, member, household_id, member, &client)
+            .await
+            .expect("Failed to leave while there is another owner");
+        let left = remove_member(&db, owner, household_id, owner, &client).await;
+        assert!(matches!(
+            left,
+            Err(CommonError::Rejected {
+                code: ErrorCode::LastOwner,
+                ..
+            })
+        ));
+        assert_eq!(
+            fetch_member_role(&db, owner, household_id).await.unwrap(),
+            Some(MemberRole::Owner)
+        );
+    }
+
+    #[tokio::test]
+    async fn owners_cant_remove_each_other_at_the_same_time() {
+        let db = test_utils::db().await;
+        let (first, second) = (
+            test_utils::user(&db, Role::User).await,
+            test_utils::user(&db, Role::User).await,
+        );
+        let household_id = household(&db, first, second, MemberRole::Owner).await;
+        let client = ClientInfo::default();
+
+        let (removed_second, removed_first) = tokio::join!(
+            remove_member(&db, first, household_id, second, &client),
+            remove_member(&db, second, household_id, first, &client),
+        );
+        assert!(removed_second.is_ok() != removed_first.is_ok());
+        let first_role = fetch_member_role(&db, first, household_id).await.unwrap();
+        let second_role = fetch_member_role(&db, second, household_id).await.unwrap();
+        assert!(first_role.is_some() != second_role.is_some());
+    }
+}
@@ -0,0 +1,264 @@
+use {
+    chrono::{Duration, Utc},
+    sea_query::{bind_params_sqlx_postgres, Cond, Expr, PostgresQueryBuilder, Query, Value},
+    serde_json::Value as Json,
+    sqlx::PgPool,
+    uuid::Uuid,
+};
+
+use crate::{error::ErrorCode, models::idempotency::*, utils, CommonError};
+
+/// How long a key stays reserved without being refreshed, after that the request is thought to have died with
+/// the server and the key can be reserved again
+pub(crate) const RESERVATION_LEASE_SECS: i64 = 30;
+
+/// Try to reserve `key` for a new request of the user, as `reservation`.
+///
+/// Returns None if the key is now reserved, or the earlier request if the key was already used.
+/// Keys older than `window` and reservations that weren't refreshed for [`RESERVATION_LEASE_SECS`] are deleted first,
+/// so they can be used again.
+pub(crate) async fn claim_key(
+    db: &PgPool,
+    user_id: Uuid,
+    key: &str,
+    reservation: Uuid,
+    request_hash: &[u8],
+    window: Duration,
+) -> Result<Option<IdempotencyKeyRow>, CommonError> {
+    let (sql, values) = Query::delete()
+        .from_table(IdempotencyKeyTable::Table)
+        .and_where(Expr::col(IdempotencyKeyTable::UserId).eq(user_id))
+        .cond_where(
+            Cond::any()
+                .add(Expr::col(IdempotencyKeyTable::CreatedAt).lt(Utc::now() - window))
+                .add(
+                    Cond::all()
+                        .add(Expr::col(IdempotencyKeyTable::Status).is_null())
+                        .add(
+                            Expr::col(IdempotencyKeyTable::RefreshedAt)
+                                .lt(Utc::now() - Duration::seconds(RESERVATION_LEASE_SECS)),
+                        ),
+                ),
+        )
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(db).await?;
+
+    let (sql, values) = Query::insert()
+        .into_table(IdempotencyKeyTable::Table)
+        .columns([
+            IdempotencyKeyTable::UserId,
+            IdempotencyKeyTable::Key,
+            IdempotencyKeyTable::Reservation,
+            IdempotencyKeyTable::RequestHash,
+        ])
+        .values_panic([
+            user_id.into(),
+            key.into(),
+            reservation.into(),
+            request_hash.to_vec().into(),
+        ])
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    match query.execute(db).await {
+        Ok(_) => return Ok(None),
+        Err(e) if utils::err_is_failed_constraint(&e) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let (sql, values) = Query::select()
+        .columns([
+            IdempotencyKeyTable::RequestHash,
+            IdempotencyKeyTable::Status,
+            IdempotencyKeyTable::Headers,
+            IdempotencyKeyTable::Body,
+        ])
+        .from(IdempotencyKeyTable::Table)
+        .and_where(Expr::col(IdempotencyKeyTable::UserId).eq(user_id))
+        .and_where(Expr::col(IdempotencyKeyTable::Key).eq(key))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    // It can only be missing if the other request failed in between, so it's fine to retry
+    let row = query
+        .fetch_optional(db)
+        .await?
+        .ok_or(CommonError::Rejected {
+            code: ErrorCode::RequestInProgress,
+            detail: "The request with this idempotency key just failed, try again".into(),
+        })?;
+    Ok(Some(row))
+}
+
+/// Keep `key` reserved for the request that is still running, for another [`RESERVATION_LEASE_SECS`]
+pub(crate) async fn refresh_reservation(
+    db: &PgPool,
+    user_id: Uuid,
+    key: &str,
+    reservation: Uuid,
+) -> Result<(), CommonError> {
+    let (sql, values) = Query::update()
+        .table(IdempotencyKeyTable::Table)
+        .value(IdempotencyKeyTable::RefreshedAt, Utc::now().into())
+        .and_where(Expr::col(IdempotencyKeyTable::UserId).eq(user_id))
+        .and_where(Expr::col(IdempotencyKeyTable::Key).eq(key))
+        .and_where(Expr::col(IdempotencyKeyTable::Reservation).eq(reservation))
+        .and_where(Expr::col(IdempotencyKeyTable::Status).is_null())
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(db).await?;
+    Ok(())
+}
+
+/// Remember the response to the request that reserved `key` as `reservation`.
+///
+/// Nothing is saved if the reservation ran out and the key was reserved again by a retry,
+/// the retry's response is the one that is kept.
+pub(crate) async fn save_response(
+    db: &PgPool,
+    user_id: Uuid,
+    key: &str,
+    reservation: Uuid,
+    status: u16,
+    headers: Json,
+    body: Vec<u8>,
+) -> Result<(), CommonError> {
+    let (sql, values) = Query::update()
+        .table(IdempotencyKeyTable::Table)
+        .values([
+            (IdempotencyKeyTable::Status, (status as i16).into()),
+            (IdempotencyKeyTable::Headers, headers.into()),
+            (IdempotencyKeyTable::Body, body.into()),
+        ])
+        .and_where(Expr::col(IdempotencyKeyTable::UserId).eq(user_id))
+        .and_where(Expr::col(IdempotencyKeyTable::Key).eq(key))
+        .and_where(Expr::col(IdempotencyKeyTable::Reservation).eq(reservation))
+        .and_where(Expr::col(IdempotencyKeyTable::Status).is_null())
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    let result = query.execute(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to save the response for the idempotency key".into()),
+        source: e,
+    })?;
+    if result.rows_affected() == 0 {
+        tracing::warn!(
+            "The reservation of idempotency key {:?} ran out before its response was saved",
+            key
+        );
+    }
+    Ok(())
+}
+
+/// Forget `key` if it's still reserved as `reservation`, so the request can be retried after it failed
+pub(crate) async fn release_key(
+    db: &PgPool,
+    user_id: Uuid,
+    key: &str,
+    reservation: Uuid,
+) -> Result<(), CommonError> {
+    let (sql, values) = Query::delete()
+        .from_table(IdempotencyKeyTable::Table)
+        .and_where(Expr::col(IdempotencyKeyTable::UserId).eq(user_id))
+        .and_where(Expr::col(IdempotencyKeyTable::Key).eq(key))
+        .and_where(Expr::col(IdempotencyKeyTable::Reservation).eq(reservation))
+        .and_where(Expr::col(IdempotencyKeyTable::Status).is_null())
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(db).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::user::Role, test_utils};
+
+    /// Make the keys of the user older than the lease of reservations
+    async fn backdate(db: &PgPool, user_id: Uuid) {
+        sqlx::query(
+            "UPDATE idempotency_keys SET created_at = created_at - interval '31 seconds', \
+             refreshed_at = refreshed_at - interval '31 seconds' WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .execute(db)
+        .await
+        .expect("Failed to backdate the keys");
+    }
+
+    #[tokio::test]
+    async fn reservations_without_a_response_can_be_claimed_again_after_their_lease() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let window = Duration::hours(24);
+        let (first, retry) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let claimed = claim_key(&db, user, "key", first, b"hash", window)
+            .await
+            .unwrap();
+        assert!(claimed.is_none());
+        let running = claim_key(&db, user, "key", retry, b"hash", window)
+            .await
+            .unwrap();
+        assert_eq!(running.map(|row| row.status), Some(None));
+
+        // The request died without a response
+        backdate(&db, user).await;
+        let reclaimed = claim_key(&db, user, "key", retry, b"hash", window)
+            .await
+            .unwrap();
+        assert!(reclaimed.is_none());
+
+        // A response is kept for the whole window
+        save_response(&db, user, "key", retry, 201, Json::Null, Vec::new())
+            .await
+            .unwrap();
+        backdate(&db, user).await;
+        let answered = claim_key(&db, user, "key", first, b"hash", window)
+            .await
+            .unwrap();
+        assert_eq!(answered.and_then(|row| row.status), Some(201));
+    }
+
+    #[tokio::test]
+    async fn refreshed_reservations_are_kept_and_only_their_request_saves_a_response() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let window = Duration::hours(24);
+        let (slow, retry) = (Uuid::new_v4(), Uuid::new_v4());
+
+        claim_key(&db, user, "key", slow, b"hash", window)
+            .await
+            .unwrap();
+        backdate(&db, user).await;
+        // The request is still running
+        refresh_reservation(&db, user, "key", slow).await.unwrap();
+        let running = claim_key(&db, user, "key", retry, b"hash", window)
+            .await
+            .unwrap();
+        assert_eq!(running.map(|row| row.status), Some(None));
+
+        // It stopped refreshing, the retry gets the key
+        backdate(&db, user).await;
+        let reclaimed = claim_key(&db, user, "key", retry, b"hash", window)
+            .await
+            .unwrap();
+        assert!(reclaimed.is_none());
+
+        // Neither saving nor releasing touches the retry's reservation
+        save_response(&db, user, "key", slow, 201, Json::Null, Vec::new())
+            .await
+            .unwrap();
+        release_key(&db, user, "key", slow).await.unwrap();
+        let running = claim_key(&db, user, "key", slow, b"hash", window)
+            .await
+            .unwrap();
+        assert_eq!(running.map(|row| row.status), Some(None));
+
+        save_response(&db, user, "key", retry, 202, Json::Null, Vec::new())
+            .await
+            .unwrap();
+        let answered = claim_key(&db, user, "key", slow, b"hash", window)
+            .await
+            .unwrap();
+        assert_eq!(answered.and_then(|row| row.status), Some(202));
+    }
+}
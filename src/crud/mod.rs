@@ -1,18 +1,42 @@
 pub(crate) mod accounts;
+pub(crate) mod audit;
+pub(crate) mod batch;
+pub(crate) mod events;
+pub(crate) mod goals;
+pub(crate) mod households;
+pub(crate) mod idempotency;
+pub(crate) mod quick_add;
+pub(crate) mod settings;
 pub(crate) mod tags;
+pub(crate) mod transactions;
+pub(crate) mod webhooks;
 
-use std::env;
+use std::{borrow::Cow, env};
 
 use {
     anyhow::Context,
-    sea_query::{self, bind_params_sqlx_postgres, Expr, Func, PostgresQueryBuilder, Query, Value},
-    sqlx::PgPool,
+    axum::http::StatusCode,
+    sea_query::{
+        self, bind_params_sqlx_postgres, Expr, Func, LockType, Order, PostgresQueryBuilder, Query,
+        Value,
+    },
+    serde_json::json,
+    sqlx::{Acquire, PgPool, Postgres},
+    uuid::Uuid,
 };
 
 use crate::{
-    models::user::*,
-    requests::LoginRequest,
-    utils::auth::{create_jwt, validate_password},
+    error::ErrorCode,
+    models::{
+        audit::{AuditAction, AuditEntity, AuditEntry, ClientInfo},
+        user::*,
+    },
+    requests::{LoginRequest, UserCreateRequest},
+    utils::{
+        self,
+        auth::{create_jwt, hash_password, validate_password},
+        oidc::OidcIdentity,
+    },
     CommonError,
 };
 
@@ -32,7 +56,7 @@ pub(crate) async fn create_db() -> anyhow::Result<PgPool> {
 pub(crate) async fn add_default_user(db: &PgPool) -> anyhow::Result<()> {
     let (sql, values) = Query::select()
         .from(UserTable::Table)
-        .and_where(Expr::col(UserTable::Admin).eq(true))
+        .and_where(Expr::col(UserTable::Role).eq(Role::Admin.as_ref()))
         .expr(Func::count(Expr::col(UserTable::Id)))
         .build(PostgresQueryBuilder);
     let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
@@ -47,12 +71,12 @@ pub(crate) async fn add_default_user(db: &PgPool) -> anyhow::Result<()> {
             .columns([
                 UserTable::Username,
                 UserTable::PasswordHash,
-                UserTable::Admin,
+                UserTable::Role,
             ])
             .values_panic([
                 "admin".into(),
-                crate::utils::auth::hash_password("admin").unwrap().into(),
-                true.into(),
+                hash_password("admin").unwrap().into(),
+                Role::Admin.as_ref().into(),
             ])
             .build(PostgresQueryBuilder);
         let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
@@ -72,6 +96,7 @@ pub(crate) async fn fetch_user_from(
     let expr = match ident {
         UserIdent::Id(id) => Expr::col(UserTable::Id).eq(id.to_owned()),
         UserIdent::Username(username) => Expr::col(UserTable::Username).eq(username.as_str()),
+        UserIdent::OidcSubject(subject) => Expr::col(UserTable::OidcSubject).eq(subject.as_str()),
     };
 
     let (sql, values) = Query::select()
@@ -79,31 +104,331 @@ pub(crate) async fn fetch_user_from(
             UserTable::Id,
             UserTable::Username,
             UserTable::PasswordHash,
-            UserTable::Admin,
+            UserTable::Role,
         ])
         .from(UserTable::Table)
         .and_where(expr)
         .build(PostgresQueryBuilder);
     let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
 
-    Ok(query
-        .fetch_optional(db)
-        .await
-        .map_err(|e| CommonError::Db {
-            msg: Some("Failed to fetch user from db".into()),
-            source: e,
-        })?)
+    query.fetch_optional(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to fetch user from db".into()),
+        source: e,
+    })
+}
+
+/// Get all the users, only meant for admins
+pub(crate) async fn fetch_users(db: &PgPool) -> Result<Vec<UserRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns([
+            UserTable::Id,
+            UserTable::Username,
+            UserTable::PasswordHash,
+            UserTable::Role,
+        ])
+        .from(UserTable::Table)
+        .order_by(UserTable::Username, sea_query::Order::Asc)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    query.fetch_all(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to fetch users from db".into()),
+        source: e,
+    })
+}
+
+/// Create a new user, if no role is given it will be a normal user.
+/// `created_by` is the admin creating the user, None if the user is creating themselves.
+///
+/// Returns the created user's id if successful.
+pub(crate) async fn create_user(
+    db: &PgPool,
+    created_by: Option<Uuid>,
+    user: UserCreateRequest,
+    client: &ClientInfo,
+) -> Result<Uuid, CommonError> {
+    let hash = hash_password(&user.password).map_err(|e| {
+        tracing::error!("Failed to hash password: {}", e);
+        CommonError::from((StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password"))
+    })?;
+    let role = user.role.unwrap_or(Role::User);
+
+    let (sql, values) = Query::insert()
+        .into_table(UserTable::Table)
+        .columns([
+            UserTable::Username,
+            UserTable::PasswordHash,
+            UserTable::Role,
+        ])
+        .values_panic([
+            user.username.clone().into(),
+            hash.into(),
+            role.as_ref().into(),
+        ])
+        .returning_col(UserTable::Id)
+        .build(PostgresQueryBuilder);
+
+    let mut tx = db.begin().await?;
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+    let id: Uuid = query.fetch_one(&mut tx).await.map_err(|e| {
+        let msg = if utils::err_is_failed_constraint(&e) {
+            Some(Cow::Borrowed("There already is a user with that username"))
+        } else {
+            None
+        };
+
+        CommonError::Db { msg, source: e }
+    })?;
+
+    let entry = AuditEntry {
+        user_id: Some(created_by.unwrap_or(id)),
+        action: AuditAction::Create,
+        entity_type: AuditEntity::User,
+        entity_id: Some(id.to_string()),
+        before: None,
+        after: Some(json!({ "id": id, "username": user.username, "role": role })),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(id)
+}
+
+/// Change the role of the user with the given `id`, as long as there is another admin when it's an admin
+pub(crate) async fn update_user_role<'c, A>(
+    db: A,
+    admin_id: Uuid,
+    id: Uuid,
+    role: Role,
+    client: &ClientInfo,
+) -> Result<(), CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    // Locked so two admins can't take the role from each other at the same time
+    let (sql, values) = Query::select()
+        .column(UserTable::Id)
+        .from(UserTable::Table)
+        .and_where(Expr::col(UserTable::Role).eq(Role::Admin.as_ref()))
+        .order_by(UserTable::Id, Order::Asc)
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+    let admins: Vec<Uuid> = query.fetch_all(&mut tx).await?;
+    if !role.is_admin() && admins == [id] {
+        return Err(CommonError::Rejected {
+            code: ErrorCode::LastAdmin,
+            detail: "There has to be another admin before the last one can get another role".into(),
+        });
+    }
+
+    let (sql, values) = Query::select()
+        .column(UserTable::Role)
+        .from(UserTable::Table)
+        .and_where(Expr::col(UserTable::Id).eq(id))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+    let old_role: Role = query
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+
+    let (sql, values) = Query::update()
+        .table(UserTable::Table)
+        .values([(UserTable::Role, role.as_ref().into())])
+        .and_where(Expr::col(UserTable::Id).eq(id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+
+    let entry = AuditEntry {
+        user_id: Some(admin_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::User,
+        entity_id: Some(id.to_string()),
+        before: Some(json!({ "role": old_role })),
+        after: Some(json!({ "role": role })),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(())
 }
 
 /// Try to validate the username and password, if successful get a jwt
-pub(crate) async fn login(req: LoginRequest, db: &PgPool) -> Result<String, CommonError> {
-    let user = fetch_user_from(db, &UserIdent::Username(req.username.clone()))
+pub(crate) async fn login(
+    req: LoginRequest,
+    db: &PgPool,
+    client: &ClientInfo,
+) -> Result<String, CommonError> {
+    let user = fetch_user_from(db, &UserIdent::Username(req.username.clone())).await?;
+
+    let user = match user {
+        Some(user) if validate_password(&user.password_hash, &req.password) => user,
+        user => {
+            record_login(db, user.map(|u| u.id), &req.username, false, client).await?;
+            return Err(CommonError::WrongCredentials);
+        }
+    };
+
+    record_login(db, Some(user.id), &user.username, true, client).await?;
+    Ok(create_jwt(user)?)
+}
+
+async fn record_login(
+    db: &PgPool,
+    user_id: Option<Uuid>,
+    username: &str,
+    success: bool,
+    client: &ClientInfo,
+) -> Result<(), CommonError> {
+    let entry = AuditEntry {
+        user_id,
+        action: if success {
+            AuditAction::LoginSuccess
+        } else {
+            AuditAction::LoginFailure
+        },
+        entity_type: AuditEntity::User,
+        entity_id: user_id.map(|id| id.to_string()),
+        before: None,
+        after: Some(json!({ "username": username })),
+        client,
+    };
+    audit::record(db, entry).await
+}
+
+/// Log in a user that was authenticated by the OIDC provider and get a jwt.
+///
+/// If no user is linked to the identity yet it gets linked to `current_user` if someone is logged in,
+/// otherwise a new user is created if `auto_provision` is enabled.
+pub(crate) async fn login_oidc(
+    db: &PgPool,
+    identity: OidcIdentity,
+    current_user: Option<Uuid>,
+    auto_provision: bool,
+    client: &ClientInfo,
+) -> Result<String, CommonError> {
+    let ident = UserIdent::OidcSubject(identity.subject.clone());
+    if let Some(user) = fetch_user_from(db, &ident).await? {
+        record_login(db, Some(user.id), &user.username, true, client).await?;
+        return Ok(create_jwt(user)?);
+    }
+
+    let user_id = match (current_user, auto_provision) {
+        (Some(id), _) => id,
+        (None, true) => {
+            // The user can only log in through the provider, so nobody should know the password
+            let user = UserCreateRequest {
+                username: identity
+                    .username
+                    .unwrap_or_else(|| identity.subject.clone()),
+                password: Uuid::new_v4().to_string(),
+                role: None,
+            };
+            create_user(db, None, user, client).await?
+        }
+        (None, false) => {
+            let username = identity.username.as_deref().unwrap_or(&identity.subject);
+            record_login(db, None, username, false, client).await?;
+            return Err(CommonError::WrongCredentials);
+        }
+    };
+    link_oidc_subject(db, user_id, &identity.subject, client).await?;
+
+    let user = fetch_user_from(db, &ident)
         .await?
         .ok_or(CommonError::WrongCredentials)?;
+    record_login(db, Some(user.id), &user.username, true, client).await?;
+    Ok(create_jwt(user)?)
+}
+
+/// Link the identity to the user, unless the user is already linked to another one
+async fn link_oidc_subject(
+    db: &PgPool,
+    user_id: Uuid,
+    subject: &str,
+    client: &ClientInfo,
+) -> Result<(), CommonError> {
+    let (sql, values) = Query::update()
+        .table(UserTable::Table)
+        .values([(UserTable::OidcSubject, subject.into())])
+        .and_where(Expr::col(UserTable::Id).eq(user_id))
+        .and_where(Expr::col(UserTable::OidcSubject).is_null())
+        .build(PostgresQueryBuilder);
 
-    if !validate_password(&user.password_hash, &req.password) {
-        return Err(CommonError::WrongCredentials);
+    let mut tx = db.begin().await?;
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    let linked = query.execute(&mut tx).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to link the identity to the user".into()),
+        source: e,
+    })?;
+    if linked.rows_affected() == 0 {
+        return Err(CommonError::from((
+            StatusCode::CONFLICT,
+            "Your user is already linked to another identity of the provider",
+        )));
     }
 
-    Ok(create_jwt(user)?)
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::User,
+        entity_id: Some(user_id.to_string()),
+        before: None,
+        after: Some(json!({ "oidc_subject": subject })),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils;
+
+    #[tokio::test]
+    async fn the_last_admin_keeps_their_role() {
+        let db = test_utils::db().await;
+        let admin = test_utils::user(&db, Role::Admin).await;
+        let other = test_utils::user(&db, Role::Admin).await;
+        let client = ClientInfo::default();
+
+        // Rolled back at the end, so no other test sees the admins it doesn't know of lose their role
+        let mut tx = db.begin().await.unwrap();
+        sqlx::query("UPDATE users SET role = $1 WHERE role = $2 AND id <> ALL($3)")
+            .bind(Role::User.as_ref())
+            .bind(Role::Admin.as_ref())
+            .bind(&[admin, other][..])
+            .execute(&mut tx)
+            .await
+            .expect("Failed to leave only the admins of this test");
+
+        update_user_role(&mut tx, admin, other, Role::User, &client)
+            .await
+            .expect("Failed to take the role of the other admin");
+        let demoted = update_user_role(&mut tx, admin, admin, Role::User, &client).await;
+        assert!(matches!(
+            demoted,
+            Err(CommonError::Rejected {
+                code: ErrorCode::LastAdmin,
+                ..
+            })
+        ));
+        let role: String = sqlx::query_scalar("SELECT role FROM users WHERE id = $1")
+            .bind(admin)
+            .fetch_one(&mut tx)
+            .await
+            .unwrap();
+        assert_eq!(role, Role::Admin.as_ref());
+        tx.rollback().await.unwrap();
+    }
 }
@@ -0,0 +1,286 @@
+use {chrono::Utc, sqlx::PgPool, uuid::Uuid};
+
+use super::{accounts, settings, tags, transactions};
+use crate::{
+    error::FieldError,
+    models::{account::AccountRow, audit::ClientInfo, tag::TagRow, transaction::SavedTransaction},
+    responses::{Ambiguity, AmbiguityKind, NamedRef, QuickAddField, QuickAddPreview},
+    utils::{
+        quick_add::{self, QuickAddLine},
+        validation::field_errors,
+    },
+    CommonError,
+};
+
+/// What `line` would save, without saving it
+pub(crate) async fn preview(
+    db: &PgPool,
+    user_id: Uuid,
+    line: &str,
+) -> Result<QuickAddPreview, CommonError> {
+    let (preview, _, _) = read_line(db, user_id, line).await?;
+    Ok(preview)
+}
+
+/// Save the transaction of `line`, a line with ambiguities is invalid.
+///
+/// Returns the id of its account with the transaction.
+pub(crate) async fn save(
+    db: &PgPool,
+    user_id: Uuid,
+    line: &str,
+    client_id: Option<Uuid>,
+    client: &ClientInfo,
+) -> Result<(i32, SavedTransaction), CommonError> {
+    let (preview, accounts, tags) = read_line(db, user_id, line).await?;
+    let (account_id, form) = match preview.to_form(client_id) {
+        Some(form) => form,
+        None => {
+            let errors = preview
+                .ambiguities
+                .into_iter()
+                .map(|ambiguity| FieldError {
+                    field: "line".into(),
+                    messages: vec![ambiguity.message],
+                })
+                .collect();
+            return Err(CommonError::Validation(errors));
+        }
+    };
+
+    let new = form
+        .to_create(account_id, &accounts, &tags)
+        .map_err(|e| CommonError::Validation(field_errors(&e)))?;
+    let saved = transactions::save_entry(db, user_id, new, client).await?;
+    Ok((account_id, saved))
+}
+
+async fn read_line(
+    db: &PgPool,
+    user_id: Uuid,
+    line: &str,
+) -> Result<(QuickAddPreview, Vec<AccountRow>, Vec<TagRow>), CommonError> {
+    let accounts = accounts::fetch_accounts(db, &user_id).await?;
+    let tags = tags::fetch_tags(db, user_id).await?;
+    let settings = settings::fetch_settings(db, user_id).await?;
+
+    let parsed = quick_add::parse(line, Utc::today().naive_utc());
+    let preview = resolve(parsed, &accounts, &tags, settings.default_account_id);
+    Ok((preview, accounts, tags))
+}
+
+/// Match the names of the line to the accounts and tags the user can see
+fn resolve(
+    line: QuickAddLine,
+    accounts: &[AccountRow],
+    tags: &[TagRow],
+    default_account_id: Option<i32>,
+) -> QuickAddPreview {
+    let mut ambiguities = Vec::new();
+    let mut add = |field, kind, text: &str, candidates: Vec<String>, message: String| {
+        ambiguities.push(Ambiguity {
+            field,
+            kind,
+            text: text.to_owned(),
+            candidates,
+            message,
+        })
+    };
+
+    let amount = match (line.amounts.as_slice(), line.unclear_amounts.as_slice()) {
+        ([], []) => {
+            add(
+                QuickAddField::Amount,
+                AmbiguityKind::Missing,
+                "",
+                Vec::new(),
+                "There is no amount".into(),
+            );
+            None
+        }
+        ([amount], []) => {
+            if *amount <= 0.into() {
+                add(
+                    QuickAddField::Amount,
+                    AmbiguityKind::Invalid,
+                    &amount.to_string(),
+                    Vec::new(),
+                    "The amount must be more than 0".into(),
+                );
+            }
+            Some(amount.clone())
+        }
+        ([], [unclear]) => {
+            add(
+                QuickAddField::Amount,
+                AmbiguityKind::Unclear,
+                &unclear.typed,
+                vec![unclear.thousands.to_string(), unclear.decimals.to_string()],
+                format!(
+                    "{} could be {} or {}, type it without the separator or with two decimals",
+                    unclear.typed, unclear.thousands, unclear.decimals
+                ),
+            );
+            None
+        }
+        _ => {
+            let typed: Vec<String> = line
+                .amounts
+                .iter()
+                .map(ToString::to_string)
+                .chain(line.unclear_amounts.iter().map(|a| a.typed.clone()))
+                .collect();
+            add(
+                QuickAddField::Amount,
+                AmbiguityKind::Repeated,
+                &typed[0],
+                typed.clone(),
+                "There is more than one amount".into(),
+            );
+            None
+        }
+    };
+
+    // Third party accounts only ever are the payee
+    let normal: Vec<&AccountRow> = accounts.iter().filter(|a| !a.is_adhoc).collect();
+    let account = match line.accounts.as_slice() {
+        [] => match default_account_id.and_then(|id| normal.iter().find(|a| a.id == id)) {
+            Some(account) => Some(*account),
+            None => {
+                add(
+                    QuickAddField::Account,
+                    AmbiguityKind::Missing,
+                    "",
+                    Vec::new(),
+                    "There is no @account and no default account in the settings".into(),
+                );
+                None
+            }
+        },
+        [name] => match find(name, &normal, |a| &a.name) {
+            Ok(account) => Some(*account),
+            Err(candidates) => {
+                let (kind, message) = unmatched("account", name, &candidates);
+                add(QuickAddField::Account, kind, name, candidates, message);
+                None
+            }
+        },
+        [first, ..] => {
+            add(
+                QuickAddField::Account,
+                AmbiguityKind::Repeated,
+                first,
+                line.accounts.clone(),
+                "There is more than one @account".into(),
+            );
+            None
+        }
+    };
+
+    let payee = line.payee.trim();
+    let other = accounts.iter().find(|a| a.name.eq_ignore_ascii_case(payee));
+    if payee.is_empty() {
+        add(
+            QuickAddField::Payee,
+            AmbiguityKind::Missing,
+            "",
+            Vec::new(),
+            "There is no payee".into(),
+        );
+    } else if let (Some(other), Some(account)) = (other, account) {
+        if other.id == account.id {
+            add(
+                QuickAddField::Payee,
+                AmbiguityKind::Invalid,
+                payee,
+                Vec::new(),
+                "The payee must be a different account than the @account".into(),
+            );
+        }
+    }
+
+    let mut matched_tags: Vec<NamedRef> = Vec::new();
+    for name in &line.tags {
+        match find(name, tags, |t| &t.name) {
+            Ok(tag) if matched_tags.iter().any(|t| t.id == tag.id) => {}
+            Ok(tag) => matched_tags.push(NamedRef {
+                id: tag.id,
+                name: tag.name.clone(),
+            }),
+            Err(candidates) => {
+                let (kind, message) = unmatched("tag", name, &candidates);
+                add(QuickAddField::Tags, kind, name, candidates, message);
+            }
+        }
+    }
+
+    let happened_on = match line.dates.as_slice() {
+        [] => Utc::today().naive_utc(),
+        [date] => *date,
+        [first, ..] => {
+            add(
+                QuickAddField::HappenedOn,
+                AmbiguityKind::Repeated,
+                &first.to_string(),
+                line.dates.iter().map(ToString::to_string).collect(),
+                "There is more than one date".into(),
+            );
+            *first
+        }
+    };
+
+    QuickAddPreview {
+        incoming: line.incoming,
+        amount,
+        account: account.map(|a| NamedRef {
+            id: a.id,
+            name: a.name.clone(),
+        }),
+        payee: Some(payee.to_owned()).filter(|p| !p.is_empty()),
+        new_payee: !payee.is_empty() && other.is_none(),
+        tags: matched_tags,
+        description: line.description,
+        happened_on,
+        ambiguities,
+    }
+}
+
+/// The item named `typed`, or the only one whose name starts with it.
+///
+/// Otherwise the names that start with it, none if nothing does.
+fn find<'a, T>(
+    typed: &str,
+    items: &'a [T],
+    name: impl Fn(&T) -> &str,
+) -> Result<&'a T, Vec<String>> {
+    let typed = typed.to_lowercase();
+    if let Some(item) = items.iter().find(|i| name(i).to_lowercase() == typed) {
+        return Ok(item);
+    }
+    let starting: Vec<&T> = items
+        .iter()
+        .filter(|i| name(i).to_lowercase().starts_with(&typed))
+        .collect();
+    match starting.as_slice() {
+        [item] => Ok(*item),
+        _ => Err(starting.into_iter().map(|i| name(i).to_owned()).collect()),
+    }
+}
+
+fn unmatched(what: &str, typed: &str, candidates: &[String]) -> (AmbiguityKind, String) {
+    match candidates.is_empty() {
+        true => (
+            AmbiguityKind::Unknown,
+            format!("There is no {} named {}", what, typed),
+        ),
+        false => (
+            AmbiguityKind::Several,
+            format!(
+                "There is more than one {} starting with {}: {}",
+                what,
+                typed,
+                candidates.join(", ")
+            ),
+        ),
+    }
+}
@@ -0,0 +1,191 @@
+use {
+    chrono::NaiveDate,
+    sea_query::{
+        bind_params_sqlx_postgres, Expr, Iden, LockType, PostgresQueryBuilder, Query, Value,
+    },
+    serde_json::json,
+    sqlx::{PgConnection, PgPool},
+    uuid::Uuid,
+};
+
+use super::{accounts, audit};
+use crate::{
+    error::FieldError,
+    models::{
+        audit::{AuditAction, AuditEntity, AuditEntry, ClientInfo},
+        settings::*,
+    },
+    requests::SettingsUpdate,
+    CommonError,
+};
+
+const SETTINGS_COLUMNS: [UserSettingsTable; 6] = [
+    UserSettingsTable::Currency,
+    UserSettingsTable::Locale,
+    UserSettingsTable::FirstDayOfWeek,
+    UserSettingsTable::PeriodStartDay,
+    UserSettingsTable::DefaultAccountId,
+    UserSettingsTable::Theme,
+];
+
+/// The settings of the user, the defaults if they never changed them
+pub(crate) async fn fetch_settings(
+    db: &PgPool,
+    user_id: Uuid,
+) -> Result<UserSettings, CommonError> {
+    let mut conn = db.acquire().await?;
+    let settings = select_settings(&mut conn, user_id, false).await?;
+    Ok(settings.unwrap_or_default())
+}
+
+/// The first day of the budget period `day` is in, by the settings of the user
+pub(crate) async fn fetch_period_start(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    day: NaiveDate,
+) -> Result<NaiveDate, CommonError> {
+    let settings = select_settings(conn, user_id, false).await?;
+    Ok(settings.unwrap_or_default().period_start(day))
+}
+
+async fn select_settings(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    lock: bool,
+) -> Result<Option<UserSettings>, CommonError> {
+    let mut query = Query::select();
+    query
+        .columns(SETTINGS_COLUMNS)
+        .from(UserSettingsTable::Table)
+        .and_where(Expr::col(UserSettingsTable::UserId).eq(user_id));
+    if lock {
+        query.lock(LockType::Exclusive);
+    }
+    let (sql, values) = query.build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    query
+        .fetch_optional(conn)
+        .await
+        .map_err(|e| CommonError::Db {
+            msg: Some("Failed to fetch settings from db".into()),
+            source: e,
+        })
+}
+
+/// Replace the settings of the user.
+/// The default account has to be a normal account the user can see.
+///
+/// Returns the new settings.
+pub(crate) async fn update_settings(
+    db: &PgPool,
+    user_id: Uuid,
+    update: SettingsUpdate,
+    client: &ClientInfo,
+) -> Result<UserSettings, CommonError> {
+    if let Some(account_id) = update.default_account_id {
+        let invalid = |message: &str| {
+            CommonError::Validation(vec![FieldError {
+                field: "default_account_id".into(),
+                messages: vec![message.into()],
+            }])
+        };
+        match accounts::fetch_account(db, &user_id, account_id).await {
+            Ok(account) if account.is_adhoc => {
+                return Err(invalid("must not be a third party account"))
+            }
+            Ok(_) => {}
+            Err(CommonError::NotFound) => return Err(invalid("is not one of your accounts")),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut tx = db.begin().await?;
+    let before = select_settings(&mut tx, user_id, true).await?;
+
+    let values: [Value; 6] = [
+        update.currency.into(),
+        update.locale.map(|locale| locale.code()).into(),
+        update.first_day_of_week.as_ref().into(),
+        update.period_start_day.into(),
+        update.default_account_id.into(),
+        update.theme.as_ref().into(),
+    ];
+    // Users get a row the first time they change something, two first changes at once both end up updating it.
+    // sea-query can't write `ON CONFLICT`, so it's added to its insert
+    let (insert, params) = Query::insert()
+        .into_table(UserSettingsTable::Table)
+        .columns(
+            [UserSettingsTable::UserId]
+                .into_iter()
+                .chain(SETTINGS_COLUMNS),
+        )
+        .values_panic([user_id.into()].into_iter().chain(values))
+        .build(PostgresQueryBuilder);
+    let columns: Vec<String> = SETTINGS_COLUMNS
+        .iter()
+        .map(|column| format!(r#""{}""#, column.to_string()))
+        .collect();
+    let sql = format!(
+        r#"{} ON CONFLICT ("{}") DO UPDATE SET {} RETURNING {}"#,
+        insert,
+        UserSettingsTable::UserId.to_string(),
+        columns
+            .iter()
+            .map(|column| format!("{0} = EXCLUDED.{0}", column))
+            .collect::<Vec<_>>()
+            .join(", "),
+        columns.join(", "),
+    );
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), params);
+    let settings: UserSettings = query
+        .fetch_one(&mut tx)
+        .await
+        .map_err(|e| CommonError::Db {
+            msg: Some("Failed to save the settings".into()),
+            source: e,
+        })?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::User,
+        entity_id: Some(user_id.to_string()),
+        before: Some(json!({ "settings": before.unwrap_or_default() })),
+        after: Some(json!({ "settings": settings })),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::user::Role, test_utils};
+
+    #[tokio::test]
+    async fn first_changes_at_the_same_time_are_both_saved() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let update = |currency: &str, period_start_day| SettingsUpdate {
+            currency: Some(currency.into()),
+            period_start_day,
+            ..UserSettings::default().into()
+        };
+
+        let client = ClientInfo::default();
+        let (first, second) = tokio::join!(
+            update_settings(&db, user, update("EUR", 5), &client),
+            update_settings(&db, user, update("USD", 10), &client),
+        );
+        first.expect("Failed to save the first change");
+        second.expect("Failed to save the second change");
+
+        let saved = fetch_settings(&db, user).await.unwrap();
+        assert!([(Some("EUR"), 5), (Some("USD"), 10)]
+            .contains(&(saved.currency.as_deref(), saved.period_start_day)));
+    }
+}
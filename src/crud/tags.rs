@@ -1,19 +1,49 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap};
 
 use {
-    sea_query::{bind_params_sqlx_postgres, Expr, PostgresQueryBuilder, Query, Value},
-    sqlx::PgPool,
+    chrono::{NaiveDate, Utc},
+    sea_query::{
+        bind_params_sqlx_postgres, Cond, Expr, LockType, Order, PostgresQueryBuilder, Query, Value,
+    },
+    serde_json::json,
+    sqlx::{types::BigDecimal, Acquire, FromRow, PgConnection, PgPool, Postgres, Row},
     strum::IntoEnumIterator,
     uuid::Uuid,
 };
 
-use crate::{models::tag::*, requests::TagCreate, utils, CommonError};
+use super::{accounts, audit, events, households, settings, transactions};
+use crate::{
+    models::{
+        audit::{AuditAction, AuditEntity, AuditEntry, ClientInfo},
+        household::MemberRole,
+        tag::*,
+        transaction::{TransactionTable, TransactionTagTable},
+        webhook::WebhookEvent,
+    },
+    requests::{TagCreate, TagUpdate},
+    utils::{self, etag::EntityTags},
+    CommonError,
+};
+
+/// What was spent with the tag since the start of the budget period of its owner, with that start
+pub(crate) async fn fetch_spent_this_period(
+    conn: &mut PgConnection,
+    tag: &TagRow,
+) -> Result<(NaiveDate, BigDecimal), CommonError> {
+    let since = settings::fetch_period_start(conn, tag.user_id, Utc::today().naive_utc()).await?;
+    let spent = transactions::fetch_tag_spent_since(&mut *conn, &[tag.id], since).await?;
+    Ok((since, spent.get(&tag.id).cloned().unwrap_or_default()))
+}
 
 pub(crate) async fn fetch_tags(db: &PgPool, user_id: Uuid) -> Result<Vec<TagRow>, CommonError> {
     let (sql, values) = Query::select()
         .columns(TagTable::iter().skip(1))
         .from(TagTable::Table)
-        .and_where(Expr::col(TagTable::UserId).eq(user_id))
+        .and_where(households::visible_to(
+            user_id,
+            TagTable::UserId,
+            TagTable::HouseholdId,
+        ))
         .build(PostgresQueryBuilder);
     let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
     Ok(query.fetch_all(db).await?)
@@ -23,7 +53,11 @@ pub(crate) async fn fetch_tag(db: &PgPool, user_id: Uuid, id: i32) -> Result<Tag
     let (sql, values) = Query::select()
         .columns(TagTable::iter().skip(1))
         .from(TagTable::Table)
-        .and_where(Expr::col(TagTable::UserId).eq(user_id))
+        .and_where(households::visible_to(
+            user_id,
+            TagTable::UserId,
+            TagTable::HouseholdId,
+        ))
         .and_where(Expr::col(TagTable::Id).eq(id))
         .build(PostgresQueryBuilder);
     let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
@@ -31,11 +65,109 @@ pub(crate) async fn fetch_tag(db: &PgPool, user_id: Uuid, id: i32) -> Result<Tag
     query.fetch_optional(db).await?.ok_or(CommonError::NotFound)
 }
 
-pub(crate) async fn create_tag(
+/// The tags the user can see on the transactions of each of the accounts, by name.
+///
+/// Leaves out the accounts the user can't see.
+pub(crate) async fn fetch_account_tags(
     db: &PgPool,
     user_id: Uuid,
+    account_ids: &[i32],
+) -> Result<HashMap<i32, Vec<TagRow>>, CommonError> {
+    let visible = accounts::fetch_visible_ids(db, user_id, account_ids).await?;
+    let (sql, values) = Query::select()
+        .distinct()
+        .columns(TagTable::iter().skip(1).map(|c| (TagTable::Table, c)))
+        .columns([
+            (TransactionTable::Table, TransactionTable::SourceId),
+            (TransactionTable::Table, TransactionTable::DestinationId),
+        ])
+        .from(TagTable::Table)
+        .inner_join(
+            TransactionTagTable::Table,
+            Expr::tbl(TransactionTagTable::Table, TransactionTagTable::TagId)
+                .equals(TagTable::Table, TagTable::Id),
+        )
+        .inner_join(
+            TransactionTable::Table,
+            Expr::tbl(TransactionTable::Table, TransactionTable::Id).equals(
+                TransactionTagTable::Table,
+                TransactionTagTable::TransactionId,
+            ),
+        )
+        .cond_where(
+            Cond::any()
+                .add(
+                    Expr::tbl(TransactionTable::Table, TransactionTable::SourceId)
+                        .is_in(visible.iter().copied()),
+                )
+                .add(
+                    Expr::tbl(TransactionTable::Table, TransactionTable::DestinationId)
+                        .is_in(visible.iter().copied()),
+                ),
+        )
+        .and_where(households::visible_to(
+            user_id,
+            (TagTable::Table, TagTable::UserId),
+            (TagTable::Table, TagTable::HouseholdId),
+        ))
+        .order_by((TagTable::Table, TagTable::Name), Order::Asc)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    let rows = query.fetch_all(db).await?;
+
+    let mut by_account: HashMap<i32, Vec<TagRow>> = HashMap::new();
+    for row in rows {
+        let tag = TagRow::from_row(&row)?;
+        for column in ["source_id", "destination_id"] {
+            let account_id: i32 = row.try_get(column)?;
+            if !visible.contains(&account_id) {
+                continue;
+            }
+            let tags = by_account.entry(account_id).or_default();
+            if !tags.iter().any(|t| t.id == tag.id) {
+                tags.push(tag.clone());
+            }
+        }
+    }
+    Ok(by_account)
+}
+
+/// Get the tags shared with the given households, leaving out the households the user is not a member of
+pub(crate) async fn fetch_household_tags(
+    db: &PgPool,
+    user_id: Uuid,
+    household_ids: &[i32],
+) -> Result<Vec<TagRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(TagTable::iter().skip(1))
+        .from(TagTable::Table)
+        .and_where(Expr::col(TagTable::HouseholdId).is_in(household_ids.iter().copied()))
+        .and_where(households::visible_to(
+            user_id,
+            TagTable::UserId,
+            TagTable::HouseholdId,
+        ))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    Ok(query.fetch_all(db).await?)
+}
+
+pub(crate) async fn create_tag<'c, A>(
+    db: A,
+    user_id: Uuid,
     tag: TagCreate,
-) -> Result<i32, CommonError> {
+    client: &ClientInfo,
+) -> Result<TagRow, CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+    if let Some(household_id) = tag.household_id {
+        households::require_member_role(&mut tx, user_id, household_id, MemberRole::can_write)
+            .await?;
+    }
+
     let (sql, values) = Query::insert()
         .into_table(TagTable::Table)
         .columns([
@@ -44,6 +176,7 @@ pub(crate) async fn create_tag(
             TagTable::Limit,
             TagTable::Balance,
             TagTable::UserId,
+            TagTable::HouseholdId,
         ])
         .values_panic([
             tag.name.into(),
@@ -51,13 +184,15 @@ pub(crate) async fn create_tag(
             tag.limit.into(),
             tag.starting_money.unwrap_or_default().into(),
             user_id.into(),
+            tag.household_id.into(),
         ])
-        .returning_col(TagTable::Id)
+        .returning(Query::select().columns(TagTable::iter().skip(1)).take())
         .build(PostgresQueryBuilder);
     tracing::trace!("SQL: {}", &sql);
     tracing::trace!("Values: {:?}", &values);
-    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
-    let r = query.fetch_one(db).await.map_err(|e| {
+
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let tag: TagRow = query.fetch_one(&mut tx).await.map_err(|e| {
         let msg = if utils::err_is_failed_constraint(&e) {
             Some(Cow::Borrowed("There already is a tag with that name"))
         } else {
@@ -67,5 +202,269 @@ pub(crate) async fn create_tag(
         CommonError::Db { msg, source: e }
     })?;
 
-    Ok(r)
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Create,
+        entity_type: AuditEntity::Tag,
+        entity_id: Some(tag.id.to_string()),
+        before: None,
+        after: Some(json!(tag)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        tag.user_id,
+        &[tag.household_id],
+        WebhookEvent::TagCreated,
+        json!(tag),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(tag)
+}
+
+/// Change the name, description and limit of a tag, if it's still at a version in `if_match`.
+/// The owner and the household members that can write can do that.
+///
+/// Returns the updated tag.
+pub(crate) async fn update_tag<'c, A>(
+    db: A,
+    user_id: Uuid,
+    tag_id: i32,
+    update: TagUpdate,
+    if_match: &EntityTags,
+    client: &ClientInfo,
+) -> Result<TagRow, CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    let mut tag = lock_writable(&mut tx, user_id, tag_id).await?;
+    if !if_match.matches_version(tag.version) {
+        return Err(CommonError::VersionMismatch);
+    }
+
+    let (sql, values) = Query::update()
+        .table(TagTable::Table)
+        .values([
+            (TagTable::Name, update.name.clone().into()),
+            (TagTable::Description, update.description.clone().into()),
+            (TagTable::Limit, update.limit.clone().into()),
+            (TagTable::Version, (tag.version + 1).into()),
+        ])
+        .and_where(Expr::col(TagTable::Id).eq(tag_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await.map_err(|e| {
+        let msg = if utils::err_is_failed_constraint(&e) {
+            Some(Cow::Borrowed("There already is a tag with that name"))
+        } else {
+            None
+        };
+
+        CommonError::Db { msg, source: e }
+    })?;
+
+    let (_, spent) = fetch_spent_this_period(&mut tx, &tag).await?;
+    let was_over_limit = tag.is_over_limit(&spent);
+    let before = json!(tag);
+    tag.name = update.name;
+    tag.description = update.description;
+    tag.limit = update.limit;
+    tag.version += 1;
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::Tag,
+        entity_id: Some(tag_id.to_string()),
+        before: Some(before),
+        after: Some(json!(tag)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        tag.user_id,
+        &[tag.household_id],
+        WebhookEvent::TagUpdated,
+        json!(tag),
+    )
+    .await?;
+    if tag.is_over_limit(&spent) && !was_over_limit {
+        events::publish(
+            &mut tx,
+            tag.user_id,
+            &[tag.household_id],
+            WebhookEvent::TagLimitExceeded,
+            json!(tag),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(tag)
+}
+
+/// Delete the tag, if it's still at a version in `if_match`.
+/// Only the owner of the tag can do that.
+pub(crate) async fn delete_tag<'c, A>(
+    db: A,
+    user_id: Uuid,
+    tag_id: i32,
+    if_match: &EntityTags,
+    client: &ClientInfo,
+) -> Result<(), CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    let (sql, values) = Query::select()
+        .columns(TagTable::iter().skip(1))
+        .from(TagTable::Table)
+        .and_where(Expr::col(TagTable::Id).eq(tag_id))
+        .and_where(Expr::col(TagTable::UserId).eq(user_id))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let tag: TagRow = query
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    if !if_match.matches_version(tag.version) {
+        return Err(CommonError::VersionMismatch);
+    }
+
+    let (sql, values) = Query::delete()
+        .from_table(TagTable::Table)
+        .and_where(Expr::col(TagTable::Id).eq(tag_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Delete,
+        entity_type: AuditEntity::Tag,
+        entity_id: Some(tag_id.to_string()),
+        before: Some(json!(tag)),
+        after: None,
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        tag.user_id,
+        &[tag.household_id],
+        WebhookEvent::TagDeleted,
+        json!(tag),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Share the tag with a household or make it private again if `household_id` is None.
+/// Only the owner of the tag can do that.
+pub(crate) async fn set_tag_household(
+    db: &PgPool,
+    user_id: Uuid,
+    tag_id: i32,
+    household_id: Option<i32>,
+    if_match: &EntityTags,
+    client: &ClientInfo,
+) -> Result<TagRow, CommonError> {
+    if let Some(household_id) = household_id {
+        households::require_member_role(db, user_id, household_id, MemberRole::can_write).await?;
+    }
+
+    let mut tx = db.begin().await?;
+
+    let (sql, values) = Query::select()
+        .columns(TagTable::iter().skip(1))
+        .from(TagTable::Table)
+        .and_where(Expr::col(TagTable::Id).eq(tag_id))
+        .and_where(Expr::col(TagTable::UserId).eq(user_id))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let mut tag: TagRow = query
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    if !if_match.matches_version(tag.version) {
+        return Err(CommonError::VersionMismatch);
+    }
+
+    let (sql, values) = Query::update()
+        .table(TagTable::Table)
+        .values([
+            (TagTable::HouseholdId, household_id.into()),
+            (TagTable::Version, (tag.version + 1).into()),
+        ])
+        .and_where(Expr::col(TagTable::Id).eq(tag_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+
+    let before = json!(tag);
+    let old_household_id = tag.household_id;
+    tag.household_id = household_id;
+    tag.version += 1;
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::Tag,
+        entity_id: Some(tag_id.to_string()),
+        before: Some(before),
+        after: Some(json!(tag)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        tag.user_id,
+        &[old_household_id, tag.household_id],
+        WebhookEvent::TagUpdated,
+        json!(tag),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(tag)
+}
+
+/// Lock the tag until the end of the transaction, if the user can change it:
+/// the owner and the members of the household it's shared with that can write can do that.
+pub(crate) async fn lock_writable(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    tag_id: i32,
+) -> Result<TagRow, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(TagTable::iter().skip(1))
+        .from(TagTable::Table)
+        .and_where(Expr::col(TagTable::Id).eq(tag_id))
+        .and_where(households::visible_to(
+            user_id,
+            TagTable::UserId,
+            TagTable::HouseholdId,
+        ))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let tag: TagRow = query
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    if tag.user_id != user_id {
+        // It's only visible to someone else if it's shared with them
+        let household_id = tag.household_id.ok_or(CommonError::NotFound)?;
+        households::require_member_role(conn, user_id, household_id, MemberRole::can_write).await?;
+    }
+    Ok(tag)
 }
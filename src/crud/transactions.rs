@@ -0,0 +1,1097 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use {
+    chrono::NaiveDate,
+    sea_query::{
+        bind_params_sqlx_postgres, Alias, Expr, Func, JoinType, LockType, Order,
+        PostgresQueryBuilder, Query, SelectStatement, Value,
+    },
+    serde_json::json,
+    sqlx::{types::BigDecimal, Acquire, Executor, FromRow, PgConnection, PgPool, Postgres, Row},
+    uuid::Uuid,
+};
+
+use super::{accounts, audit, events, tags, webhooks};
+use crate::{
+    error::FieldError,
+    models::{
+        account::{AccountRow, AccountTable},
+        audit::{AuditAction, AuditEntity, AuditEntry, ClientInfo},
+        tag::{TagRow, TagTable},
+        transaction::*,
+        webhook::WebhookEvent,
+    },
+    requests::{
+        AccountCreateRequest, Party, QueuedTransaction, TransactionCreate, TransactionForm,
+    },
+    responses::{SyncStatus, SyncedEntry},
+    utils::{self, validation::field_errors},
+    CommonError,
+};
+
+/// The unique index on the client ids of the transactions of a user, from the migrations
+const CLIENT_ID_INDEX: &str = "transactions_user_id_client_id_idx";
+
+/// The latest transactions from or to an account the user can see, newest first
+pub(crate) async fn fetch_account_transactions(
+    db: &PgPool,
+    user_id: Uuid,
+    account_id: i32,
+    limit: u64,
+) -> Result<Vec<TransactionDetails>, CommonError> {
+    // Makes sure the user can see the account
+    accounts::fetch_account(db, &user_id, account_id).await?;
+
+    let mut transactions = fetch_accounts_transactions(db, user_id, &[account_id], limit).await?;
+    Ok(transactions.remove(&account_id).unwrap_or_default())
+}
+
+/// The latest `$2` transactions from or to each of the accounts in `$1`, with the account they are the latest of.
+///
+/// In plain SQL because sea-query can't join laterally.
+const LATEST_OF_ACCOUNTS: &str = "
+SELECT account.id AS account_id, latest.id, latest.source_id, source.name AS source_name,
+    latest.destination_id, destination.name AS destination_name,
+    latest.amount, latest.description, latest.happened_on
+FROM unnest($1::INTEGER[]) AS account (id)
+CROSS JOIN LATERAL (
+    SELECT * FROM transactions
+    WHERE transactions.source_id = account.id OR transactions.destination_id = account.id
+    ORDER BY transactions.happened_on DESC, transactions.id DESC
+    LIMIT $2
+) AS latest
+INNER JOIN accounts AS source ON source.id = latest.source_id
+INNER JOIN accounts AS destination ON destination.id = latest.destination_id
+ORDER BY account.id, latest.happened_on DESC, latest.id DESC";
+
+/// The latest `limit` transactions of each of the accounts, newest first, leaving out the accounts the user can't see
+pub(crate) async fn fetch_accounts_transactions(
+    db: &PgPool,
+    user_id: Uuid,
+    account_ids: &[i32],
+    limit: u64,
+) -> Result<HashMap<i32, Vec<TransactionDetails>>, CommonError> {
+    let visible = accounts::fetch_visible_ids(db, user_id, account_ids).await?;
+    let rows = sqlx::query(LATEST_OF_ACCOUNTS)
+        .bind(visible)
+        .bind(limit as i64)
+        .fetch_all(db)
+        .await
+        .map_err(|e| CommonError::Db {
+            msg: Some("Failed to fetch transactions from db".into()),
+            source: e,
+        })?;
+
+    let mut owners = Vec::with_capacity(rows.len());
+    let mut transactions = Vec::with_capacity(rows.len());
+    for row in rows {
+        owners.push(row.try_get::<i32, _>("account_id")?);
+        transactions.push(TransactionDetails::from_row(&row)?);
+    }
+
+    let mut by_account: HashMap<i32, Vec<TransactionDetails>> = HashMap::new();
+    for (account_id, transaction) in owners.into_iter().zip(with_tags(db, transactions).await?) {
+        by_account.entry(account_id).or_default().push(transaction);
+    }
+    Ok(by_account)
+}
+
+/// The transactions with a tag the user can see that happened on `since` or later, newest first
+pub(crate) async fn fetch_tag_transactions(
+    db: &PgPool,
+    user_id: Uuid,
+    tag_id: i32,
+    since: NaiveDate,
+) -> Result<Vec<TransactionDetails>, CommonError> {
+    // Makes sure the user can see the tag
+    tags::fetch_tag(db, user_id, tag_id).await?;
+
+    let (sql, values) = details()
+        .inner_join(
+            TransactionTagTable::Table,
+            Expr::tbl(
+                TransactionTagTable::Table,
+                TransactionTagTable::TransactionId,
+            )
+            .equals(TransactionTable::Table, TransactionTable::Id),
+        )
+        .and_where(Expr::tbl(TransactionTagTable::Table, TransactionTagTable::TagId).eq(tag_id))
+        .and_where(Expr::tbl(TransactionTable::Table, TransactionTable::HappenedOn).gte(since))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let transactions = query.fetch_all(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to fetch transactions from db".into()),
+        source: e,
+    })?;
+
+    with_tags(db, transactions).await
+}
+
+/// How much was spent with each of the tags on `since` or later, tags without such transactions are left out
+pub(crate) async fn fetch_tag_spent_since<'c, E>(
+    db: E,
+    tag_ids: &[i32],
+    since: NaiveDate,
+) -> Result<HashMap<i32, BigDecimal>, CommonError>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    if tag_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let (sql, values) = Query::select()
+        .column((TransactionTagTable::Table, TransactionTagTable::TagId))
+        .expr(Func::sum(Expr::tbl(
+            TransactionTable::Table,
+            TransactionTable::Amount,
+        )))
+        .from(TransactionTagTable::Table)
+        .inner_join(
+            TransactionTable::Table,
+            Expr::tbl(TransactionTable::Table, TransactionTable::Id).equals(
+                TransactionTagTable::Table,
+                TransactionTagTable::TransactionId,
+            ),
+        )
+        .and_where(
+            Expr::tbl(TransactionTagTable::Table, TransactionTagTable::TagId)
+                .is_in(tag_ids.iter().copied()),
+        )
+        .and_where(Expr::tbl(TransactionTable::Table, TransactionTable::HappenedOn).gte(since))
+        .group_by_col((TransactionTagTable::Table, TransactionTagTable::TagId))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let spent: Vec<(i32, BigDecimal)> = query.fetch_all(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to sum the transactions".into()),
+        source: e,
+    })?;
+    Ok(spent.into_iter().collect())
+}
+
+/// How much money went from `account_ids` to other accounts on `since` or later
+pub(crate) async fn fetch_spent_since(
+    db: &PgPool,
+    account_ids: &[i32],
+    since: NaiveDate,
+) -> Result<BigDecimal, CommonError> {
+    if account_ids.is_empty() {
+        return Ok(BigDecimal::default());
+    }
+
+    let ids = account_ids.iter().copied();
+    let (sql, values) = Query::select()
+        .expr(Func::sum(Expr::col(TransactionTable::Amount)))
+        .from(TransactionTable::Table)
+        .and_where(Expr::col(TransactionTable::SourceId).is_in(ids.clone()))
+        .and_where(Expr::col(TransactionTable::DestinationId).is_not_in(ids))
+        .and_where(Expr::col(TransactionTable::HappenedOn).gte(since))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+    let spent: Option<BigDecimal> = query.fetch_one(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to sum the transactions".into()),
+        source: e,
+    })?;
+    Ok(spent.unwrap_or_default())
+}
+
+/// Selects `TransactionDetails`, without the tags
+fn details() -> SelectStatement {
+    let (source, destination) = (Alias::new("source"), Alias::new("destination"));
+    Query::select()
+        .columns([
+            (TransactionTable::Table, TransactionTable::Id),
+            (TransactionTable::Table, TransactionTable::SourceId),
+            (TransactionTable::Table, TransactionTable::DestinationId),
+            (TransactionTable::Table, TransactionTable::Amount),
+            (TransactionTable::Table, TransactionTable::Description),
+            (TransactionTable::Table, TransactionTable::HappenedOn),
+        ])
+        .expr_as(
+            Expr::tbl(source.clone(), AccountTable::Name),
+            Alias::new("source_name"),
+        )
+        .expr_as(
+            Expr::tbl(destination.clone(), AccountTable::Name),
+            Alias::new("destination_name"),
+        )
+        .from(TransactionTable::Table)
+        .join_as(
+            JoinType::InnerJoin,
+            AccountTable::Table,
+            source.clone(),
+            Expr::tbl(source, AccountTable::Id)
+                .equals(TransactionTable::Table, TransactionTable::SourceId),
+        )
+        .join_as(
+            JoinType::InnerJoin,
+            AccountTable::Table,
+            destination.clone(),
+            Expr::tbl(destination, AccountTable::Id)
+                .equals(TransactionTable::Table, TransactionTable::DestinationId),
+        )
+        .order_by(
+            (TransactionTable::Table, TransactionTable::HappenedOn),
+            Order::Desc,
+        )
+        .order_by((TransactionTable::Table, TransactionTable::Id), Order::Desc)
+        .take()
+}
+
+/// Fill in the names of the tags of the transactions
+async fn with_tags(
+    db: &PgPool,
+    mut transactions: Vec<TransactionDetails>,
+) -> Result<Vec<TransactionDetails>, CommonError> {
+    if transactions.is_empty() {
+        return Ok(transactions);
+    }
+
+    let ids: Vec<i32> = transactions.iter().map(|t| t.id).collect();
+    let (sql, values) = Query::select()
+        .column((
+            TransactionTagTable::Table,
+            TransactionTagTable::TransactionId,
+        ))
+        .column((TagTable::Table, TagTable::Name))
+        .from(TransactionTagTable::Table)
+        .inner_join(
+            TagTable::Table,
+            Expr::tbl(TagTable::Table, TagTable::Id)
+                .equals(TransactionTagTable::Table, TransactionTagTable::TagId),
+        )
+        .and_where(
+            Expr::tbl(
+                TransactionTagTable::Table,
+                TransactionTagTable::TransactionId,
+            )
+            .is_in(ids),
+        )
+        .order_by((TagTable::Table, TagTable::Name), Order::Asc)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let rows: Vec<(i32, String)> = query.fetch_all(db).await?;
+
+    let mut names: HashMap<i32, Vec<String>> = HashMap::new();
+    for (transaction_id, name) in rows {
+        names.entry(transaction_id).or_default().push(name);
+    }
+    for transaction in &mut transactions {
+        // A transaction between two of the accounts is there once for each of them
+        transaction.tags = names.get(&transaction.id).cloned().unwrap_or_default();
+    }
+    Ok(transactions)
+}
+
+/// Move money from one account to another and add it to the balance of the tags.
+/// The user needs to be able to change both accounts and every tag.
+///
+/// Returns the created transaction.
+pub(crate) async fn create_transaction<'c, A>(
+    db: A,
+    user_id: Uuid,
+    new: TransactionCreate,
+    client: &ClientInfo,
+) -> Result<TransactionRow, CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+
+    let source_id = party_account(&mut tx, user_id, new.source, client).await?;
+    let destination_id = party_account(&mut tx, user_id, new.destination, client).await?;
+    if source_id == destination_id {
+        return Err(CommonError::Validation(vec![FieldError {
+            field: "payee".into(),
+            messages: vec!["must be a different account".into()],
+        }]));
+    }
+
+    let (source, destination) = lock_accounts(&mut tx, user_id, source_id, destination_id).await?;
+
+    let mut tag_ids = new.tag_ids;
+    tag_ids.sort_unstable();
+    tag_ids.dedup();
+    let mut tags = Vec::with_capacity(tag_ids.len());
+    for tag_id in tag_ids {
+        tags.push(tags::lock_writable(&mut tx, user_id, tag_id).await?);
+    }
+
+    let (sql, values) = Query::insert()
+        .into_table(TransactionTable::Table)
+        .columns([
+            TransactionTable::SourceId,
+            TransactionTable::DestinationId,
+            TransactionTable::Amount,
+            TransactionTable::Description,
+            TransactionTable::HappenedOn,
+            TransactionTable::UserId,
+            TransactionTable::ClientId,
+        ])
+        .values_panic([
+            source_id.into(),
+            destination_id.into(),
+            new.amount.clone().into(),
+            new.description.into(),
+            new.happened_on.into(),
+            user_id.into(),
+            new.client_id.into(),
+        ])
+        .returning(
+            Query::select()
+                .columns([
+                    TransactionTable::Id,
+                    TransactionTable::SourceId,
+                    TransactionTable::DestinationId,
+                    TransactionTable::Amount,
+                    TransactionTable::Description,
+                    TransactionTable::HappenedOn,
+                    TransactionTable::UserId,
+                    TransactionTable::CreatedAt,
+                ])
+                .take(),
+        )
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let transaction: TransactionRow = query.fetch_one(&mut tx).await.map_err(|e| {
+        let msg = if utils::err_is_failed_constraint(&e) {
+            Cow::Borrowed("This transaction has already been saved")
+        } else {
+            Cow::Borrowed("Failed to save the transaction")
+        };
+
+        CommonError::Db {
+            msg: Some(msg),
+            source: e,
+        }
+    })?;
+
+    if !tags.is_empty() {
+        let mut insert = Query::insert();
+        insert.into_table(TransactionTagTable::Table).columns([
+            TransactionTagTable::TransactionId,
+            TransactionTagTable::TagId,
+        ]);
+        for tag in &tags {
+            insert.values_panic([transaction.id.into(), tag.id.into()]);
+        }
+        let (sql, values) = insert.build(PostgresQueryBuilder);
+        let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+        query.execute(&mut tx).await?;
+    }
+
+    let (owner, households) = notified(&source, &destination);
+    let amount = &new.amount;
+    add_money(&mut tx, source, &-amount.clone()).await?;
+    add_money(&mut tx, destination, amount).await?;
+    for tag in tags {
+        spend(&mut tx, tag, amount, transaction.happened_on).await?;
+    }
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Create,
+        entity_type: AuditEntity::Transaction,
+        entity_id: Some(transaction.id.to_string()),
+        before: None,
+        after: Some(json!(transaction)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        owner,
+        &households,
+        WebhookEvent::TransactionCreated,
+        json!(transaction),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(transaction)
+}
+
+/// Replace the tags of the transaction, its amount moves from the balances of the tags it loses
+/// to the ones of the tags it gets. The user needs to be able to change both accounts and all of these tags.
+pub(crate) async fn set_transaction_tags<'c, A>(
+    db: A,
+    user_id: Uuid,
+    transaction_id: i32,
+    mut tag_ids: Vec<i32>,
+    client: &ClientInfo,
+) -> Result<TransactionRow, CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+    let (transaction, ..) = lock_transaction(&mut tx, user_id, transaction_id).await?;
+
+    let before = fetch_tag_ids(&mut tx, transaction_id).await?;
+    tag_ids.sort_unstable();
+    tag_ids.dedup();
+    // Only the tags it loses or gets change, locked in the same order as everywhere else
+    let mut changed: Vec<i32> = before
+        .iter()
+        .filter(|id| !tag_ids.contains(id))
+        .chain(tag_ids.iter().filter(|id| !before.contains(id)))
+        .copied()
+        .collect();
+    if changed.is_empty() {
+        return Ok(transaction);
+    }
+    changed.sort_unstable();
+    let mut tags = Vec::with_capacity(changed.len());
+    for tag_id in changed {
+        tags.push(tags::lock_writable(&mut tx, user_id, tag_id).await?);
+    }
+
+    let (sql, values) = Query::delete()
+        .from_table(TransactionTagTable::Table)
+        .and_where(Expr::col(TransactionTagTable::TransactionId).eq(transaction_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+    if !tag_ids.is_empty() {
+        let mut insert = Query::insert();
+        insert.into_table(TransactionTagTable::Table).columns([
+            TransactionTagTable::TransactionId,
+            TransactionTagTable::TagId,
+        ]);
+        for tag_id in &tag_ids {
+            insert.values_panic([transaction_id.into(), (*tag_id).into()]);
+        }
+        let (sql, values) = insert.build(PostgresQueryBuilder);
+        let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+        query.execute(&mut tx).await?;
+    }
+
+    let amount = &transaction.amount;
+    for tag in tags {
+        let amount = match tag_ids.contains(&tag.id) {
+            true => amount.clone(),
+            false => -amount.clone(),
+        };
+        spend(&mut tx, tag, &amount, transaction.happened_on).await?;
+    }
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Update,
+        entity_type: AuditEntity::Transaction,
+        entity_id: Some(transaction_id.to_string()),
+        before: Some(with_tag_ids(&transaction, &before)),
+        after: Some(with_tag_ids(&transaction, &tag_ids)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(transaction)
+}
+
+/// Delete the transaction and take its amount back out of the money of its accounts and the balances of its tags.
+/// The user needs to be able to change both accounts and every tag.
+pub(crate) async fn delete_transaction<'c, A>(
+    db: A,
+    user_id: Uuid,
+    transaction_id: i32,
+    client: &ClientInfo,
+) -> Result<(), CommonError>
+where
+    A: Acquire<'c, Database = Postgres>,
+{
+    let mut tx = db.begin().await?;
+    let (transaction, source, destination) =
+        lock_transaction(&mut tx, user_id, transaction_id).await?;
+
+    let mut tags = Vec::new();
+    for tag_id in fetch_tag_ids(&mut tx, transaction_id).await? {
+        tags.push(tags::lock_writable(&mut tx, user_id, tag_id).await?);
+    }
+
+    // Its tags go with it
+    let (sql, values) = Query::delete()
+        .from_table(TransactionTable::Table)
+        .and_where(Expr::col(TransactionTable::Id).eq(transaction_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+
+    let (owner, households) = notified(&source, &destination);
+    let amount = &transaction.amount;
+    add_money(&mut tx, source, amount).await?;
+    add_money(&mut tx, destination, &-amount.clone()).await?;
+    for tag in tags {
+        spend(&mut tx, tag, &-amount.clone(), transaction.happened_on).await?;
+    }
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Delete,
+        entity_type: AuditEntity::Transaction,
+        entity_id: Some(transaction.id.to_string()),
+        before: Some(json!(transaction)),
+        after: None,
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    events::publish(
+        &mut tx,
+        owner,
+        &households,
+        WebhookEvent::TransactionDeleted,
+        json!(transaction),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Lock both accounts, if the user can change them.
+/// Always locked in the same order so two transactions between the same accounts can't deadlock.
+async fn lock_accounts(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    source_id: i32,
+    destination_id: i32,
+) -> Result<(AccountRow, AccountRow), CommonError> {
+    let (first, second) = (source_id.min(destination_id), source_id.max(destination_id));
+    let first = accounts::lock_writable(&mut *conn, user_id, first).await?;
+    let second = accounts::lock_writable(&mut *conn, user_id, second).await?;
+    match first.id == source_id {
+        true => Ok((first, second)),
+        false => Ok((second, first)),
+    }
+}
+
+/// Lock the transaction and its source and destination accounts, if the user can change them
+async fn lock_transaction(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    transaction_id: i32,
+) -> Result<(TransactionRow, AccountRow, AccountRow), CommonError> {
+    let (sql, values) = Query::select()
+        .columns([TransactionTable::SourceId, TransactionTable::DestinationId])
+        .from(TransactionTable::Table)
+        .and_where(Expr::col(TransactionTable::Id).eq(transaction_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let (source_id, destination_id): (i32, i32) = query
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    let (source, destination) = lock_accounts(conn, user_id, source_id, destination_id).await?;
+
+    // The accounts come first, like when it was created
+    let (sql, values) = Query::select()
+        .columns([
+            TransactionTable::Id,
+            TransactionTable::SourceId,
+            TransactionTable::DestinationId,
+            TransactionTable::Amount,
+            TransactionTable::Description,
+            TransactionTable::HappenedOn,
+            TransactionTable::UserId,
+            TransactionTable::CreatedAt,
+        ])
+        .from(TransactionTable::Table)
+        .and_where(Expr::col(TransactionTable::Id).eq(transaction_id))
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    // Someone else deleted it while we waited for the accounts
+    let transaction = query
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+    Ok((transaction, source, destination))
+}
+
+/// The ids of the tags of the transaction, in the order they are locked in
+async fn fetch_tag_ids(
+    conn: &mut PgConnection,
+    transaction_id: i32,
+) -> Result<Vec<i32>, CommonError> {
+    let (sql, values) = Query::select()
+        .column(TransactionTagTable::TagId)
+        .from(TransactionTagTable::Table)
+        .and_where(Expr::col(TransactionTagTable::TransactionId).eq(transaction_id))
+        .order_by(TransactionTagTable::TagId, Order::Asc)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_scalar(&sql), values);
+    Ok(query.fetch_all(conn).await?)
+}
+
+/// The transaction with the ids of its tags, for the audit log
+fn with_tag_ids(transaction: &TransactionRow, tag_ids: &[i32]) -> serde_json::Value {
+    let mut value = json!(transaction);
+    value["tag_ids"] = json!(tag_ids);
+    value
+}
+
+/// Who hears about a transaction between the accounts: it belongs with the side the user keeps track of,
+/// the other one can be a third party
+fn notified(source: &AccountRow, destination: &AccountRow) -> (Uuid, [Option<i32>; 2]) {
+    match source.is_adhoc {
+        true => (
+            destination.user_id,
+            [destination.household_id, source.household_id],
+        ),
+        false => (
+            source.user_id,
+            [source.household_id, destination.household_id],
+        ),
+    }
+}
+
+/// Create a transaction unless the user already saved one with the same client id,
+/// so an entry that is sent again, e.g. after it was queued offline, isn't saved twice.
+pub(crate) async fn save_entry(
+    db: &PgPool,
+    user_id: Uuid,
+    new: TransactionCreate,
+    client: &ClientInfo,
+) -> Result<SavedTransaction, CommonError> {
+    let client_id = match new.client_id {
+        Some(client_id) => client_id,
+        None => {
+            let transaction = create_transaction(db, user_id, new, client).await?;
+            return Ok(SavedTransaction::Created(transaction));
+        }
+    };
+    if let Some(transaction) = fetch_client_transaction(db, user_id, client_id).await? {
+        return Ok(SavedTransaction::Duplicate(transaction));
+    }
+
+    match create_transaction(db, user_id, new, client).await {
+        Ok(transaction) => Ok(SavedTransaction::Created(transaction)),
+        // The same entry was saved by another request in the meantime
+        Err(CommonError::Db { msg, source }) if is_client_id_conflict(&source) => {
+            fetch_client_transaction(db, user_id, client_id)
+                .await?
+                .map(SavedTransaction::Duplicate)
+                .ok_or(CommonError::Db { msg, source })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// If the insert failed because the user already has a transaction with its client id
+fn is_client_id_conflict(err: &sqlx::Error) -> bool {
+    utils::err_is_failed_constraint(err)
+        && err.as_database_error().and_then(|e| e.constraint()) == Some(CLIENT_ID_INDEX)
+}
+
+/// Save the entries that were queued while offline, each one on its own.
+///
+/// Entries that are invalid by now are reported as conflicts with the reasons, the rest is still saved.
+pub(crate) async fn sync_entries(
+    db: &PgPool,
+    user_id: Uuid,
+    entries: Vec<QueuedTransaction>,
+    client: &ClientInfo,
+) -> Result<Vec<SyncedEntry>, CommonError> {
+    let mut accounts = accounts::fetch_accounts(db, &user_id).await?;
+    let tags = tags::fetch_tags(db, user_id).await?;
+
+    let mut results = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let client_id = entry.client_id;
+        let account_id = entry.account_id;
+        let conflict = |errors| SyncedEntry {
+            client_id,
+            status: SyncStatus::Conflict,
+            transaction_id: None,
+            errors,
+        };
+
+        let new = match TransactionForm::from(entry).to_create(account_id, &accounts, &tags) {
+            Ok(new) => new,
+            Err(e) => {
+                results.push(conflict(field_errors(&e)));
+                continue;
+            }
+        };
+        let new_payee = matches!(new.source, Party::NewPayee(_))
+            || matches!(new.destination, Party::NewPayee(_));
+
+        let result = match save_entry(db, user_id, new, client).await {
+            Ok(SavedTransaction::Created(transaction)) => SyncedEntry {
+                client_id,
+                status: SyncStatus::Created,
+                transaction_id: Some(transaction.id),
+                errors: Vec::new(),
+            },
+            Ok(SavedTransaction::Duplicate(transaction)) => SyncedEntry {
+                client_id,
+                status: SyncStatus::Duplicate,
+                transaction_id: Some(transaction.id),
+                errors: Vec::new(),
+            },
+            Err(CommonError::Validation(errors)) => conflict(errors),
+            Err(CommonError::NotFound) => conflict(vec![FieldError {
+                field: "account_id".into(),
+                messages: vec!["is not one of your accounts".into()],
+            }]),
+            Err(CommonError::Forbidden) => conflict(vec![FieldError {
+                field: "account_id".into(),
+                messages: vec!["can't be changed by you".into()],
+            }]),
+            // One entry that can't be saved mustn't hold back the ones after it
+            Err(e) => {
+                let message = match e {
+                    CommonError::Db { msg, source } => {
+                        tracing::error!("Failed to sync entry {}: {:?}", client_id, source);
+                        msg.unwrap_or(Cow::Borrowed("Database error")).into_owned()
+                    }
+                    e => e.to_string(),
+                };
+                conflict(vec![FieldError {
+                    field: "entry".into(),
+                    messages: vec![message],
+                }])
+            }
+        };
+        // Later entries can be for the payee that was just created
+        if new_payee && result.status == SyncStatus::Created {
+            accounts = accounts::fetch_accounts(db, &user_id).await?;
+        }
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// The transaction the user saved with `client_id`, if any
+async fn fetch_client_transaction(
+    db: &PgPool,
+    user_id: Uuid,
+    client_id: Uuid,
+) -> Result<Option<TransactionRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns([
+            TransactionTable::Id,
+            TransactionTable::SourceId,
+            TransactionTable::DestinationId,
+            TransactionTable::Amount,
+            TransactionTable::Description,
+            TransactionTable::HappenedOn,
+            TransactionTable::UserId,
+            TransactionTable::CreatedAt,
+        ])
+        .from(TransactionTable::Table)
+        .and_where(Expr::col(TransactionTable::UserId).eq(user_id))
+        .and_where(Expr::col(TransactionTable::ClientId).eq(client_id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+
+    query.fetch_optional(db).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to fetch the transaction from db".into()),
+        source: e,
+    })
+}
+
+/// The id of the account of one side of the transaction, creating it if it's a new payee
+async fn party_account(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    party: Party,
+    client: &ClientInfo,
+) -> Result<i32, CommonError> {
+    match party {
+        Party::Account(id) => Ok(id),
+        Party::NewPayee(name) => {
+            // Names are only unique per user, so the payee is looked for among the user's own accounts
+            if let Some(id) = accounts::find_own_adhoc(&mut *conn, user_id, &name).await? {
+                return Ok(id);
+            }
+            let payee = AccountCreateRequest {
+                name,
+                description: None,
+                starting_money: None,
+                is_adhoc: true,
+                household_id: None,
+            };
+            let account = accounts::create_account(&mut *conn, user_id, payee, client)
+                .await
+                .map_err(|e| match e {
+                    // One of the user's normal accounts has that name
+                    CommonError::Db { source, .. } if utils::err_is_failed_constraint(&source) => {
+                        CommonError::Validation(vec![FieldError {
+                            field: "payee".into(),
+                            messages: vec!["There already is an account with that name".into()],
+                        }])
+                    }
+                    e => e,
+                })?;
+            Ok(account.id)
+        }
+    }
+}
+
+/// Change the money of a normal account, third party accounts don't keep track of their money
+async fn add_money(
+    conn: &mut PgConnection,
+    mut account: AccountRow,
+    amount: &BigDecimal,
+) -> Result<(), CommonError> {
+    if account.is_adhoc {
+        return Ok(());
+    }
+
+    let before = account.available_money.clone();
+    account.available_money = account.available_money.map(|money| money + amount);
+    account.total_money = account.total_money.map(|money| money + amount);
+    account.version += 1;
+    let (sql, values) = Query::update()
+        .table(AccountTable::Table)
+        .values([
+            (
+                AccountTable::AvailableMoney,
+                account.available_money.clone().into(),
+            ),
+            (AccountTable::TotalMoney, account.total_money.clone().into()),
+            (AccountTable::Version, account.version.into()),
+        ])
+        .and_where(Expr::col(AccountTable::Id).eq(account.id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut *conn).await?;
+
+    events::publish(
+        &mut *conn,
+        account.user_id,
+        &[account.household_id],
+        WebhookEvent::AccountUpdated,
+        json!(account),
+    )
+    .await?;
+    if let (Some(before), Some(after)) = (&before, &account.available_money) {
+        webhooks::enqueue_balance_below(conn, account.user_id, before, after, json!(account))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Add to the balance of a tag, the transaction of `amount` is already linked to it.
+/// A deleted transaction is taken back out with its negative amount once it's unlinked.
+async fn spend(
+    conn: &mut PgConnection,
+    mut tag: TagRow,
+    amount: &BigDecimal,
+    happened_on: NaiveDate,
+) -> Result<(), CommonError> {
+    let (period_start, spent) = tags::fetch_spent_this_period(conn, &tag).await?;
+    let spent_before = match happened_on >= period_start {
+        true => &spent - amount,
+        false => spent.clone(),
+    };
+    let was_over_limit = tag.is_over_limit(&spent_before);
+    tag.balance += amount;
+    tag.version += 1;
+    let (sql, values) = Query::update()
+        .table(TagTable::Table)
+        .values([
+            (TagTable::Balance, tag.balance.clone().into()),
+            (TagTable::Version, tag.version.into()),
+        ])
+        .and_where(Expr::col(TagTable::Id).eq(tag.id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut *conn).await?;
+
+    events::publish(
+        conn,
+        tag.user_id,
+        &[tag.household_id],
+        WebhookEvent::TagUpdated,
+        json!(tag),
+    )
+    .await?;
+    if tag.is_over_limit(&spent) && !was_over_limit {
+        events::publish(
+            conn,
+            tag.user_id,
+            &[tag.household_id],
+            WebhookEvent::TagLimitExceeded,
+            json!(tag),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::user::Role, requests::TagCreate, test_utils, utils::etag::EntityTags};
+
+    async fn account(db: &PgPool, user_id: Uuid, name: &str) -> i32 {
+        let new = AccountCreateRequest {
+            name: name.into(),
+            description: None,
+            starting_money: Some(100.into()),
+            is_adhoc: false,
+            household_id: None,
+        };
+        accounts::create_account(db, user_id, new, &Default::default())
+            .await
+            .expect("Failed to create an account")
+            .id
+    }
+
+    async fn transaction(
+        db: &PgPool,
+        user_id: Uuid,
+        source: i32,
+        destination: Party,
+        day: u32,
+    ) -> i32 {
+        let new = TransactionCreate {
+            source: Party::Account(source),
+            destination,
+            amount: 1.into(),
+            description: None,
+            happened_on: NaiveDate::from_ymd(2022, 2, day),
+            tag_ids: Vec::new(),
+            client_id: None,
+        };
+        match save_entry(db, user_id, new, &Default::default()).await {
+            Ok(SavedTransaction::Created(row)) => row.id,
+            saved => panic!("Failed to save a transaction: {:?}", saved),
+        }
+    }
+
+    #[tokio::test]
+    async fn latest_transactions_are_limited_per_account_and_only_of_visible_accounts() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let (cash, bank) = (
+            account(&db, user, "Cash").await,
+            account(&db, user, "Bank").await,
+        );
+        let oldest = transaction(&db, user, cash, Party::Account(bank), 1).await;
+        let middle = transaction(&db, user, cash, Party::Account(bank), 2).await;
+        let newest = transaction(&db, user, bank, Party::NewPayee("Kiosk".into()), 3).await;
+
+        let stranger = test_utils::user(&db, Role::User).await;
+        let hidden = account(&db, stranger, "Hidden").await;
+        transaction(&db, stranger, hidden, Party::NewPayee("Kiosk".into()), 3).await;
+
+        let latest = fetch_accounts_transactions(&db, user, &[cash, bank, hidden], 2)
+            .await
+            .expect("Failed to fetch the transactions");
+        let ids = |account_id| -> Vec<i32> { latest[&account_id].iter().map(|t| t.id).collect() };
+        assert_eq!(ids(cash), [middle, oldest]);
+        assert_eq!(ids(bank), [newest, middle]);
+        assert!(!latest.contains_key(&hidden));
+    }
+
+    #[tokio::test]
+    async fn queued_entries_with_a_payee_too_long_for_an_account_name_are_conflicts() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let cash = account(&db, user, "Cash").await;
+        let entry = |payee: String| QueuedTransaction {
+            client_id: Uuid::new_v4(),
+            account_id: cash,
+            direction: String::new(),
+            payee,
+            amount: "1".into(),
+            tags: String::new(),
+            description: String::new(),
+            happened_on: String::new(),
+        };
+        let entries = vec![entry("x".repeat(101)), entry("y".repeat(100))];
+
+        let synced = sync_entries(&db, user, entries, &Default::default())
+            .await
+            .expect("Failed to sync the entries");
+        let statuses: Vec<SyncStatus> = synced.iter().map(|entry| entry.status).collect();
+        assert_eq!(statuses, [SyncStatus::Conflict, SyncStatus::Created]);
+        let fields: Vec<&str> = synced[0].errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["payee"]);
+        let payees = accounts::fetch_adhoc_accounts(&db, &user).await.unwrap();
+        let lengths: Vec<usize> = payees.iter().map(|a| a.name.len()).collect();
+        assert_eq!(lengths, [100]);
+    }
+
+    #[tokio::test]
+    async fn deleted_transactions_are_taken_back_out_and_free_their_accounts() {
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::User).await;
+        let (cash, bank) = (
+            account(&db, user, "Cash").await,
+            account(&db, user, "Bank").await,
+        );
+        let new = TagCreate {
+            // Tag names are unique across all users
+            name: format!("Food {}", user),
+            description: None,
+            limit: None,
+            starting_money: None,
+            household_id: None,
+        };
+        let tag = tags::create_tag(&db, user, new, &Default::default())
+            .await
+            .expect("Failed to create a tag");
+        let new = TransactionCreate {
+            source: Party::Account(cash),
+            destination: Party::Account(bank),
+            amount: 30.into(),
+            description: None,
+            happened_on: NaiveDate::from_ymd(2022, 2, 1),
+            tag_ids: vec![tag.id],
+            client_id: None,
+        };
+        let transaction = create_transaction(&db, user, new, &Default::default())
+            .await
+            .expect("Failed to create a transaction");
+
+        let stranger = test_utils::user(&db, Role::User).await;
+        let result = delete_transaction(&db, stranger, transaction.id, &Default::default()).await;
+        assert!(matches!(result, Err(CommonError::NotFound)), "{:?}", result);
+
+        let bank_row = accounts::fetch_account(&db, &user, bank).await.unwrap();
+        let result = accounts::delete_account(
+            &db,
+            user,
+            bank,
+            &EntityTags::version(bank_row.version),
+            &Default::default(),
+        )
+        .await;
+        assert!(
+            matches!(result, Err(CommonError::Rejected { .. })),
+            "{:?}",
+            result
+        );
+
+        delete_transaction(&db, user, transaction.id, &Default::default())
+            .await
+            .expect("Failed to delete the transaction");
+        let money = |account: AccountRow| (account.available_money, account.total_money);
+        let cash_row = accounts::fetch_account(&db, &user, cash).await.unwrap();
+        assert_eq!(money(cash_row), (Some(100.into()), Some(100.into())));
+        let bank_row = accounts::fetch_account(&db, &user, bank).await.unwrap();
+        assert_eq!(
+            money(bank_row.clone()),
+            (Some(100.into()), Some(100.into()))
+        );
+        let tag = tags::fetch_tag(&db, user, tag.id).await.unwrap();
+        assert_eq!(tag.balance, 0.into());
+
+        let result = delete_transaction(&db, user, transaction.id, &Default::default()).await;
+        assert!(matches!(result, Err(CommonError::NotFound)), "{:?}", result);
+        accounts::delete_account(
+            &db,
+            user,
+            bank,
+            &EntityTags::version(bank_row.version),
+            &Default::default(),
+        )
+        .await
+        .expect("Failed to delete the account without transactions");
+    }
+}
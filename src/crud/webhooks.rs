@@ -0,0 +1,392 @@
+use {
+    chrono::{DateTime, Duration, FixedOffset, Utc},
+    sea_query::{
+        bind_params_sqlx_postgres, Expr, LockType, Order, PostgresQueryBuilder, Query, Value,
+    },
+    serde_json::{json, Value as JsonValue},
+    sqlx::{
+        types::{BigDecimal, Json},
+        PgConnection, PgPool,
+    },
+    uuid::Uuid,
+};
+
+use crate::{
+    crud::audit,
+    error::FieldError,
+    models::{
+        audit::{AuditAction, AuditEntity, AuditEntry, ClientInfo},
+        webhook::*,
+    },
+    requests::WebhookCreate,
+    utils::webhooks,
+    CommonError,
+};
+
+/// Most entries of the delivery log that are returned
+const MAX_DELIVERIES: u64 = 100;
+
+/// How long a delivery is left alone after it was picked up, so it's not sent twice at the same time
+const LEASE_SECONDS: i64 = 60;
+
+const WEBHOOK_COLUMNS: [WebhookTable; 6] = [
+    WebhookTable::Id,
+    WebhookTable::Url,
+    WebhookTable::Events,
+    WebhookTable::BalanceThreshold,
+    WebhookTable::Active,
+    WebhookTable::CreatedAt,
+];
+
+pub(crate) async fn fetch_webhooks(
+    db: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<WebhookRow>, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(WEBHOOK_COLUMNS)
+        .from(WebhookTable::Table)
+        .and_where(Expr::col(WebhookTable::UserId).eq(user_id))
+        .order_by(WebhookTable::Id, Order::Asc)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    Ok(query.fetch_all(db).await?)
+}
+
+pub(crate) async fn fetch_webhook(
+    db: &PgPool,
+    user_id: Uuid,
+    id: i32,
+) -> Result<WebhookRow, CommonError> {
+    let (sql, values) = Query::select()
+        .columns(WEBHOOK_COLUMNS)
+        .from(WebhookTable::Table)
+        .and_where(Expr::col(WebhookTable::UserId).eq(user_id))
+        .and_where(Expr::col(WebhookTable::Id).eq(id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    query.fetch_optional(db).await?.ok_or(CommonError::NotFound)
+}
+
+/// Register a webhook with a new random secret.
+///
+/// Returns the webhook and its secret.
+pub(crate) async fn create_webhook(
+    db: &PgPool,
+    user_id: Uuid,
+    webhook: WebhookCreate,
+    client: &ClientInfo,
+) -> Result<(WebhookRow, String), CommonError> {
+    if let Err(message) = webhooks::resolve_public(&webhook.url).await {
+        return Err(CommonError::Validation(vec![FieldError {
+            field: "url".into(),
+            messages: vec![message.into()],
+        }]));
+    }
+
+    // Two v4 uuids are 244 random bits
+    let secret = format!(
+        "{}{}",
+        Uuid::new_v4().to_simple(),
+        Uuid::new_v4().to_simple()
+    );
+
+    let mut tx = db.begin().await?;
+    let (sql, values) = Query::insert()
+        .into_table(WebhookTable::Table)
+        .columns([
+            WebhookTable::UserId,
+            WebhookTable::Url,
+            WebhookTable::Secret,
+            WebhookTable::Events,
+            WebhookTable::BalanceThreshold,
+        ])
+        .values_panic([
+            user_id.into(),
+            webhook.url.into(),
+            secret.clone().into(),
+            json!(webhook.events).into(),
+            webhook.balance_threshold.into(),
+        ])
+        .returning(Query::select().columns(WEBHOOK_COLUMNS).take())
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let webhook: WebhookRow = query.fetch_one(&mut tx).await?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Create,
+        entity_type: AuditEntity::Webhook,
+        entity_id: Some(webhook.id.to_string()),
+        before: None,
+        after: Some(json!(webhook)),
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok((webhook, secret))
+}
+
+/// Delete the webhook and its delivery log
+pub(crate) async fn delete_webhook(
+    db: &PgPool,
+    user_id: Uuid,
+    id: i32,
+    client: &ClientInfo,
+) -> Result<(), CommonError> {
+    let mut tx = db.begin().await?;
+    let (sql, values) = Query::delete()
+        .from_table(WebhookTable::Table)
+        .and_where(Expr::col(WebhookTable::UserId).eq(user_id))
+        .and_where(Expr::col(WebhookTable::Id).eq(id))
+        .returning(Query::select().columns(WEBHOOK_COLUMNS).take())
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let webhook: WebhookRow = query
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(CommonError::NotFound)?;
+
+    let entry = AuditEntry {
+        user_id: Some(user_id),
+        action: AuditAction::Delete,
+        entity_type: AuditEntity::Webhook,
+        entity_id: Some(webhook.id.to_string()),
+        before: Some(json!(webhook)),
+        after: None,
+        client,
+    };
+    audit::record(&mut tx, entry).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// The newest deliveries of the webhook, newest first
+pub(crate) async fn fetch_deliveries(
+    db: &PgPool,
+    user_id: Uuid,
+    webhook_id: i32,
+) -> Result<Vec<WebhookDeliveryRow>, CommonError> {
+    // Makes sure it's the user's webhook
+    fetch_webhook(db, user_id, webhook_id).await?;
+
+    let (sql, values) = Query::select()
+        .columns([
+            WebhookDeliveryTable::Id,
+            WebhookDeliveryTable::Event,
+            WebhookDeliveryTable::Payload,
+            WebhookDeliveryTable::Status,
+            WebhookDeliveryTable::Attempts,
+            WebhookDeliveryTable::ResponseStatus,
+            WebhookDeliveryTable::Error,
+            WebhookDeliveryTable::NextAttemptAt,
+            WebhookDeliveryTable::CreatedAt,
+            WebhookDeliveryTable::DeliveredAt,
+        ])
+        .from(WebhookDeliveryTable::Table)
+        .and_where(Expr::col(WebhookDeliveryTable::WebhookId).eq(webhook_id))
+        .order_by(WebhookDeliveryTable::Id, Order::Desc)
+        .limit(MAX_DELIVERIES)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    Ok(query.fetch_all(db).await?)
+}
+
+/// Queue a delivery of the event to every active webhook of the user that is subscribed to it.
+///
+/// Pass the same transaction as the change itself, so the event is only sent if the change is saved.
+pub(crate) async fn enqueue(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    event: WebhookEvent,
+    data: JsonValue,
+) -> Result<(), CommonError> {
+    let (sql, values) = Query::select()
+        .columns([WebhookTable::Id, WebhookTable::Events])
+        .from(WebhookTable::Table)
+        .and_where(Expr::col(WebhookTable::UserId).eq(user_id))
+        .and_where(Expr::col(WebhookTable::Active).eq(true))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let webhooks: Vec<(i32, Json<Vec<WebhookEvent>>)> = query.fetch_all(&mut *conn).await?;
+
+    let subscribed = webhooks
+        .into_iter()
+        .filter(|(_, events)| events.contains(&event))
+        .map(|(id, _)| id);
+    insert_deliveries(conn, subscribed, event, data).await
+}
+
+/// Queue `account.balance_below` to the active webhooks of the user with a threshold that is between the available
+/// money of the account `before` and `after` it changed, so it's sent once every time it goes below.
+pub(crate) async fn enqueue_balance_below(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    before: &BigDecimal,
+    after: &BigDecimal,
+    data: JsonValue,
+) -> Result<(), CommonError> {
+    let event = WebhookEvent::AccountBalanceBelow;
+    let (sql, values) = Query::select()
+        .columns([WebhookTable::Id, WebhookTable::Events])
+        .from(WebhookTable::Table)
+        .and_where(Expr::col(WebhookTable::UserId).eq(user_id))
+        .and_where(Expr::col(WebhookTable::Active).eq(true))
+        .and_where(Expr::col(WebhookTable::BalanceThreshold).lte(before.clone()))
+        .and_where(Expr::col(WebhookTable::BalanceThreshold).gt(after.clone()))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let webhooks: Vec<(i32, Json<Vec<WebhookEvent>>)> = query.fetch_all(&mut *conn).await?;
+
+    let subscribed = webhooks
+        .into_iter()
+        .filter(|(_, events)| events.contains(&event))
+        .map(|(id, _)| id);
+    insert_deliveries(conn, subscribed, event, data).await
+}
+
+async fn insert_deliveries(
+    conn: &mut PgConnection,
+    webhook_ids: impl Iterator<Item = i32>,
+    event: WebhookEvent,
+    data: JsonValue,
+) -> Result<(), CommonError> {
+    let payload = json!({
+        "event": event,
+        "created_at": Utc::now(),
+        "data": data,
+    });
+
+    let mut insert = Query::insert();
+    insert.into_table(WebhookDeliveryTable::Table).columns([
+        WebhookDeliveryTable::WebhookId,
+        WebhookDeliveryTable::Event,
+        WebhookDeliveryTable::Payload,
+    ]);
+    let mut any = false;
+    for webhook_id in webhook_ids {
+        insert.values_panic([
+            webhook_id.into(),
+            event.as_ref().into(),
+            payload.clone().into(),
+        ]);
+        any = true;
+    }
+    if !any {
+        return Ok(());
+    }
+
+    let (sql, values) = insert.build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(conn).await.map_err(|e| CommonError::Db {
+        msg: Some("Failed to queue the webhook deliveries".into()),
+        source: e,
+    })?;
+    Ok(())
+}
+
+/// Pick up to `limit` deliveries that are due, they won't be picked again for a while
+pub(crate) async fn claim_due(
+    db: &PgPool,
+    limit: u64,
+) -> Result<Vec<PendingDelivery>, CommonError> {
+    let mut tx = db.begin().await?;
+
+    let (sql, values) = Query::select()
+        .column((WebhookDeliveryTable::Table, WebhookDeliveryTable::Id))
+        .column((WebhookDeliveryTable::Table, WebhookDeliveryTable::Event))
+        .column((WebhookDeliveryTable::Table, WebhookDeliveryTable::Payload))
+        .column((WebhookDeliveryTable::Table, WebhookDeliveryTable::Attempts))
+        .column((WebhookTable::Table, WebhookTable::Url))
+        .column((WebhookTable::Table, WebhookTable::Secret))
+        .from(WebhookDeliveryTable::Table)
+        .inner_join(
+            WebhookTable::Table,
+            Expr::tbl(WebhookTable::Table, WebhookTable::Id)
+                .equals(WebhookDeliveryTable::Table, WebhookDeliveryTable::WebhookId),
+        )
+        .and_where(
+            Expr::tbl(WebhookDeliveryTable::Table, WebhookDeliveryTable::Status)
+                .eq(DeliveryStatus::Pending.as_ref()),
+        )
+        .and_where(
+            Expr::tbl(
+                WebhookDeliveryTable::Table,
+                WebhookDeliveryTable::NextAttemptAt,
+            )
+            .lte(Utc::now()),
+        )
+        .order_by(
+            (
+                WebhookDeliveryTable::Table,
+                WebhookDeliveryTable::NextAttemptAt,
+            ),
+            Order::Asc,
+        )
+        .limit(limit)
+        .lock(LockType::Exclusive)
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query_as(&sql), values);
+    let due: Vec<PendingDelivery> = query.fetch_all(&mut tx).await?;
+    if due.is_empty() {
+        return Ok(due);
+    }
+
+    let (sql, values) = Query::update()
+        .table(WebhookDeliveryTable::Table)
+        .values([(
+            WebhookDeliveryTable::NextAttemptAt,
+            (Utc::now() + Duration::seconds(LEASE_SECONDS)).into(),
+        )])
+        .and_where(Expr::col(WebhookDeliveryTable::Id).is_in(due.iter().map(|d| d.id)))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(due)
+}
+
+/// Save how an attempt to send a delivery went.
+///
+/// If it failed and `retry_at` is None it's given up on.
+pub(crate) async fn record_attempt(
+    db: &PgPool,
+    id: i64,
+    response_status: Option<u16>,
+    error: Option<String>,
+    retry_at: Option<DateTime<Utc>>,
+) -> Result<(), CommonError> {
+    let now = Utc::now();
+    let (status, next_attempt_at, delivered_at) = match (&error, retry_at) {
+        (None, _) => (DeliveryStatus::Delivered, now, Some(now)),
+        (Some(_), Some(retry_at)) => (DeliveryStatus::Pending, retry_at, None),
+        (Some(_), None) => (DeliveryStatus::Failed, now, None),
+    };
+
+    let (sql, values) = Query::update()
+        .table(WebhookDeliveryTable::Table)
+        .values([
+            (WebhookDeliveryTable::Status, status.as_ref().into()),
+            (
+                WebhookDeliveryTable::ResponseStatus,
+                response_status.map(|s| s as i16).into(),
+            ),
+            (WebhookDeliveryTable::Error, error.into()),
+            (WebhookDeliveryTable::NextAttemptAt, next_attempt_at.into()),
+            (
+                WebhookDeliveryTable::DeliveredAt,
+                delivered_at.map(DateTime::<FixedOffset>::from).into(),
+            ),
+        ])
+        .value_expr(
+            WebhookDeliveryTable::Attempts,
+            Expr::col(WebhookDeliveryTable::Attempts).add(1),
+        )
+        .and_where(Expr::col(WebhookDeliveryTable::Id).eq(id))
+        .build(PostgresQueryBuilder);
+    let query = bind_params_sqlx_postgres!(sqlx::query(&sql), values);
+    query.execute(db).await?;
+    Ok(())
+}
@@ -2,21 +2,33 @@ use std::borrow::Cow;
 
 use {
     axum::{
-        http::{StatusCode, Uri},
+        http::{header, HeaderValue, StatusCode},
         response::{IntoResponse, Redirect, Response},
         Json,
     },
-    serde_json::json,
+    serde::Serialize,
+    strum::AsRefStr,
+    utoipa::ToSchema,
+};
+
+use crate::{
+    html_template::HtmlTemplate,
+    utils::auth::login_uri,
+    views::{views_404::View404, views_500::View500},
 };
 
 #[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum Error {
     #[error(transparent)]
     /// An error that will return a JSON response to the user
     ApiError(CommonError),
     #[error(transparent)]
-    /// An error that will return Html or a redirect to the user
+    /// An error that will return an error page or a redirect to the login page to the user
     HtmlError(CommonError),
+    /// A page that needs the user to log in, `next` is the page to go back to after that
+    #[error("Log in to continue")]
+    LoginRequired { next: Option<String> },
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -29,6 +41,19 @@ pub(crate) enum CommonError {
     InvalidCredentials(#[from] jwt_simple::Error),
     #[error("Wrong credentials provided")]
     WrongCredentials,
+    #[error("You don't have permission to do that")]
+    Forbidden,
+    /// The `If-Match` header doesn't have the current version
+    #[error("It was changed by someone else, get it again and retry")]
+    VersionMismatch,
+    #[error("Some fields are invalid")]
+    Validation(Vec<FieldError>),
+    /// Some operations of a batch failed, so none of them were applied
+    #[error("Some operations failed, nothing was changed")]
+    Batch(Vec<ItemError>),
+    /// An extractor couldn't read the request, like a body that isn't JSON
+    #[error("{detail}")]
+    Rejected { code: ErrorCode, detail: String },
     #[error("Database error")]
     Db {
         msg: Option<Cow<'static, str>>,
@@ -41,48 +66,213 @@ pub(crate) enum CommonError {
     },
 }
 
+/// A stable, machine-readable name for each kind of error, clients should match on this instead of the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, AsRefStr, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum ErrorCode {
+    NotFound,
+    MissingCredentials,
+    InvalidCredentials,
+    WrongCredentials,
+    Forbidden,
+    AlreadyExists,
+    /// Something else still needs it, like an account with transactions
+    InUse,
+    /// It's the only owner of a household
+    LastOwner,
+    /// It's the only admin
+    LastAdmin,
+    ValidationFailed,
+    InvalidJson,
+    InvalidQuery,
+    InvalidPath,
+    UnsupportedMediaType,
+    InvalidIdempotencyKey,
+    IdempotencyKeyReused,
+    RequestInProgress,
+    PreconditionFailed,
+    /// An update without an `If-Match` header
+    PreconditionRequired,
+    BatchFailed,
+    BadRequest,
+    UpstreamError,
+    DatabaseError,
+    InternalError,
+}
+
+impl ErrorCode {
+    /// The code of errors that only have a status code
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::NOT_FOUND => Self::NotFound,
+            StatusCode::FORBIDDEN => Self::Forbidden,
+            StatusCode::CONFLICT => Self::AlreadyExists,
+            StatusCode::PRECONDITION_FAILED => Self::PreconditionFailed,
+            StatusCode::PRECONDITION_REQUIRED => Self::PreconditionRequired,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => Self::UnsupportedMediaType,
+            StatusCode::UNPROCESSABLE_ENTITY => Self::ValidationFailed,
+            StatusCode::BAD_GATEWAY => Self::UpstreamError,
+            status if status.is_server_error() => Self::InternalError,
+            _ => Self::BadRequest,
+        }
+    }
+
+    fn status(self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::MissingCredentials
+            | Self::InvalidCredentials
+            | Self::WrongCredentials
+            | Self::InvalidJson
+            | Self::InvalidQuery
+            | Self::InvalidPath
+            | Self::InvalidIdempotencyKey
+            | Self::BadRequest => StatusCode::BAD_REQUEST,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::AlreadyExists
+            | Self::InUse
+            | Self::LastOwner
+            | Self::LastAdmin
+            | Self::RequestInProgress => StatusCode::CONFLICT,
+            Self::ValidationFailed | Self::IdempotencyKeyReused | Self::BatchFailed => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Self::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Self::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::UpstreamError => StatusCode::BAD_GATEWAY,
+            Self::DatabaseError | Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Everything that is wrong with a single field of the request
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct FieldError {
+    /// Path to the field, like `name` or `items[2].amount`
+    pub(crate) field: String,
+    pub(crate) messages: Vec<String>,
+}
+
+/// Why a single operation of a batch failed
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ItemError {
+    /// Position of the operation in the batch
+    pub(crate) index: usize,
+    pub(crate) status: u16,
+    pub(crate) code: ErrorCode,
+    pub(crate) detail: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) errors: Vec<FieldError>,
+}
+
+impl ItemError {
+    pub(crate) fn new(index: usize, err: CommonError) -> Self {
+        let problem = Problem::from(err);
+        Self {
+            index,
+            status: problem.status,
+            code: problem.code,
+            detail: problem.detail,
+            errors: problem.errors,
+        }
+    }
+}
+
+/// An RFC 7807 problem, the body of every error the api returns as `application/problem+json`
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct Problem {
+    #[serde(rename = "type")]
+    pub(crate) type_uri: String,
+    pub(crate) title: String,
+    pub(crate) status: u16,
+    pub(crate) detail: String,
+    pub(crate) code: ErrorCode,
+    /// Only there for `validation_failed`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) errors: Vec<FieldError>,
+    /// Only there for `batch_failed`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) items: Vec<ItemError>,
+}
+
+impl Problem {
+    fn new(status: StatusCode, code: ErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            type_uri: format!("urn:budgetman:error:{}", code.as_ref()),
+            title: status.canonical_reason().unwrap_or("Error").to_owned(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            code,
+            errors: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+}
+
+impl From<CommonError> for Problem {
+    fn from(err: CommonError) -> Self {
+        let code = match &err {
+            CommonError::NotFound => ErrorCode::NotFound,
+            CommonError::MissingCredentials(_) => ErrorCode::MissingCredentials,
+            CommonError::InvalidCredentials(_) => ErrorCode::InvalidCredentials,
+            CommonError::WrongCredentials => ErrorCode::WrongCredentials,
+            CommonError::Forbidden => ErrorCode::Forbidden,
+            CommonError::VersionMismatch => ErrorCode::PreconditionFailed,
+            CommonError::Validation(_) => ErrorCode::ValidationFailed,
+            CommonError::Batch(_) => ErrorCode::BatchFailed,
+            CommonError::Rejected { code, .. } => *code,
+            CommonError::Db { source, .. } => {
+                tracing::error!("Db Error: {:?}", source);
+                if crate::utils::err_is_failed_constraint(source) {
+                    ErrorCode::AlreadyExists
+                } else {
+                    ErrorCode::DatabaseError
+                }
+            }
+            CommonError::Other { code, .. } => ErrorCode::from_status(*code),
+        };
+        let status = match &err {
+            CommonError::Other { code, .. } => *code,
+            _ => code.status(),
+        };
+
+        match err {
+            CommonError::Validation(errors) => Self {
+                errors,
+                ..Self::new(status, code, "Some fields are invalid")
+            },
+            CommonError::Batch(items) => Self {
+                items,
+                ..Self::new(status, code, "Some operations failed, nothing was changed")
+            },
+            CommonError::Db { msg, .. } => {
+                Self::new(status, code, msg.unwrap_or(Cow::Borrowed("Database error")))
+            }
+            err => Self::new(status, code, err.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status, Json(self)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        response
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         match self {
-            Error::ApiError(err) => {
-                let (code, err_msg) = match err {
-                    CommonError::NotFound => {
-                        (StatusCode::NOT_FOUND, Cow::Borrowed("Resource not found"))
-                    }
-                    CommonError::MissingCredentials(e) => {
-                        (StatusCode::BAD_REQUEST, Cow::Borrowed(e))
-                    }
-                    CommonError::InvalidCredentials(e) => {
-                        (StatusCode::BAD_REQUEST, Cow::Owned(format!("{}", e)))
-                    }
-                    CommonError::WrongCredentials => (
-                        StatusCode::BAD_REQUEST,
-                        Cow::Borrowed("Wrong credentials provided"),
-                    ),
-                    CommonError::Db { msg, source } => {
-                        tracing::error!("Db Error: {:?}", source);
-                        let code = if let Some(e) = source.as_database_error() {
-                            if e.message().starts_with("UNIQUE constraint failed") {
-                                StatusCode::BAD_REQUEST
-                            } else {
-                                StatusCode::INTERNAL_SERVER_ERROR
-                            }
-                        } else {
-                            StatusCode::INTERNAL_SERVER_ERROR
-                        };
-
-                        let msg = match msg {
-                            Some(msg) => msg,
-                            None => Cow::Borrowed("Database error"),
-                        };
-
-                        (code, msg)
-                    }
-                    CommonError::Other { code, msg } => (code, msg),
-                };
-
-                let body = Json(json!({ "error": err_msg }));
-                (code, body).into_response()
+            Error::ApiError(err) => Problem::from(err).into_response(),
+            Error::LoginRequired { next } => {
+                Redirect::to(login_uri(next.as_deref())).into_response()
             }
             Error::HtmlError(err) => {
                 if matches!(
@@ -91,9 +281,23 @@ impl IntoResponse for Error {
                         | CommonError::WrongCredentials
                         | CommonError::InvalidCredentials(_)
                 ) {
-                    Redirect::to(Uri::from_static("/login")).into_response()
-                } else {
-                    Redirect::to(Uri::from_static("/505")).into_response()
+                    return Redirect::to(login_uri(None)).into_response();
+                }
+
+                let problem = Problem::from(err);
+                let status = StatusCode::from_u16(problem.status)
+                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                if status.is_server_error() {
+                    tracing::error!("Showing the error page: {}", problem.detail);
+                }
+                match status {
+                    StatusCode::NOT_FOUND => {
+                        (status, HtmlTemplate(View404::new(None))).into_response()
+                    }
+                    _ => {
+                        let view = View500::error(None, status, problem.detail);
+                        (status, HtmlTemplate(view)).into_response()
+                    }
                 }
             }
         }
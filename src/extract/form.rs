@@ -0,0 +1,100 @@
+use {
+    axum::{
+        async_trait,
+        body::Bytes,
+        extract::{FromRequest, RequestParts},
+        http::StatusCode,
+        BoxError,
+    },
+    serde::{de::DeserializeOwned, Deserialize},
+    tower_cookies::Cookies,
+};
+
+use crate::{utils::cookies, CommonError, Error};
+
+/// A custom `Form` extractor that also checks the CSRF token of the form.
+///
+/// The form must have a `csrf_token` field with the same value as the `csrf_token` cookie,
+/// use a [`CsrfToken`] when rendering the form to get it.
+pub(crate) struct Form<T>(pub T);
+
+#[derive(Deserialize)]
+struct CsrfField {
+    csrf_token: String,
+}
+
+#[async_trait]
+impl<B, T> FromRequest<B> for Form<T>
+where
+    T: DeserializeOwned,
+    B: axum::body::HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let cookies = Cookies::from_request(req)
+            .await
+            .expect("`Cookies` not found");
+        let body = Bytes::from_request(req).await.map_err(|e| {
+            let err = (StatusCode::BAD_REQUEST, format!("Invalid form: {}", e));
+            Error::HtmlError(err.into())
+        })?;
+
+        let expected = cookies.get(cookies::CSRF_TOKEN);
+        let submitted = serde_urlencoded::from_bytes::<CsrfField>(&body).ok();
+        match (expected, submitted) {
+            (Some(expected), Some(submitted))
+                if constant_time_eq(expected.value(), &submitted.csrf_token) => {}
+            _ => {
+                let err = (StatusCode::FORBIDDEN, "Invalid CSRF token");
+                return Err(Error::HtmlError(err.into()));
+            }
+        }
+
+        let value = serde_urlencoded::from_bytes(&body).map_err(|e| {
+            let err = (StatusCode::BAD_REQUEST, format!("Invalid form: {}", e));
+            Error::HtmlError(CommonError::from(err))
+        })?;
+        Ok(Self(value))
+    }
+}
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// The CSRF token to put in forms, it gets created if the user doesn't have one yet
+pub(crate) struct CsrfToken(pub String);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for CsrfToken {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let cookies = Cookies::from_request(req)
+            .await
+            .expect("`Cookies` not found");
+        Ok(Self(csrf_token(&cookies)))
+    }
+}
+
+/// The CSRF token in `cookies`, a new one is added to them if there is none yet
+pub(crate) fn csrf_token(cookies: &Cookies) -> String {
+    if let Some(cookie) = cookies.get(cookies::CSRF_TOKEN) {
+        return cookie.value().to_string();
+    }
+
+    let token = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().to_simple(),
+        uuid::Uuid::new_v4().to_simple()
+    );
+    cookies.add(cookies::private_cookie(cookies::CSRF_TOKEN, token.clone()));
+    token
+}
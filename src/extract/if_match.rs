@@ -0,0 +1,28 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+    http::header,
+};
+
+use crate::{error::ErrorCode, utils::etag::EntityTags, CommonError, Error};
+
+/// The required `If-Match` header of an update, it must have the `ETag` of the current version
+pub(crate) struct IfMatch(pub EntityTags);
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for IfMatch {
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        req.headers()
+            .and_then(|headers| headers.get(header::IF_MATCH))
+            .and_then(|value| value.to_str().ok())
+            .map(|value| Self(EntityTags::parse(value)))
+            .ok_or_else(|| {
+                Error::ApiError(CommonError::Rejected {
+                    code: ErrorCode::PreconditionRequired,
+                    detail: "Updates need an `If-Match` header with the `ETag` you got".into(),
+                })
+            })
+    }
+}
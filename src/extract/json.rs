@@ -1,19 +1,24 @@
-use std::error::Error;
-
 use {
     axum::{
         async_trait,
-        extract::{rejection::JsonRejection, FromRequest, RequestParts},
-        http::StatusCode,
+        body::Bytes,
+        extract::{FromRequest, RequestParts},
+        http::header,
         response::{IntoResponse, Response},
         BoxError,
     },
-    serde::de::DeserializeOwned,
-    serde::Serialize,
-    serde_json::{json, Value},
+    serde::{de::DeserializeOwned, Serialize},
+    serde_json::error::Category,
+};
+
+use crate::{
+    error::{ErrorCode, FieldError},
+    CommonError, Error,
 };
 
-/// A custom `Json` Extractor / Response that allows as to customise the error message if it fails
+/// A custom `Json` Extractor / Response that allows as to customise the error message if it fails.
+///
+/// Bodies with the wrong type of values are rejected with the path of the field that is wrong.
 pub(crate) struct Json<T>(pub T);
 
 #[async_trait]
@@ -25,37 +30,32 @@ where
     B::Data: Send,
     B::Error: Into<BoxError>,
 {
-    type Rejection = (StatusCode, axum::Json<Value>);
+    type Rejection = Error;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
-        match axum::Json::<T>::from_request(req).await {
-            Ok(value) => Ok(Self(value.0)),
-            Err(rejection) => {
-                let (status, err_message) = match rejection {
-                    JsonRejection::InvalidJsonBody(err) => {
-                        let msg = if let Some(inner) = err.source() {
-                            format!("Invalid JSON request: {}, {}", err, inner)
-                        } else {
-                            format!("Invalid JSON request: {}", err)
-                        };
-                        (StatusCode::BAD_REQUEST, msg)
-                    }
-                    JsonRejection::MissingJsonContentType(err) => {
-                        (StatusCode::BAD_REQUEST, err.to_string())
-                    }
-                    JsonRejection::HeadersAlreadyExtracted(err) => {
-                        (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
-                    }
-                    err => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Unknown internal error: {}", err),
-                    ),
-                };
+        if !json_content_type(req) {
+            return Err(bad_request(
+                ErrorCode::UnsupportedMediaType,
+                "Expected request with `Content-Type: application/json`".into(),
+            ));
+        }
+        let body = Bytes::from_request(req).await.map_err(|e| {
+            bad_request(
+                ErrorCode::InvalidJson,
+                format!("Failed to read the body: {}", e),
+            )
+        })?;
 
-                let body = axum::Json(json!({ "error": err_message }));
-                Err((status, body))
+        let deserializer = &mut serde_json::Deserializer::from_slice(&body);
+        let value = serde_path_to_error::deserialize(deserializer).map_err(|e| {
+            if e.inner().classify() != Category::Data {
+                let detail = format!("Invalid JSON request: {}", e.inner());
+                return bad_request(ErrorCode::InvalidJson, detail);
             }
-        }
+            let field = field_error(&e.path().to_string(), e.inner());
+            Error::ApiError(CommonError::Validation(vec![field]))
+        })?;
+        Ok(Self(value))
     }
 }
 
@@ -67,3 +67,44 @@ where
         axum::Json(self.0).into_response()
     }
 }
+
+fn bad_request(code: ErrorCode, detail: String) -> Error {
+    Error::ApiError(CommonError::Rejected { code, detail })
+}
+
+fn json_content_type<B>(req: &RequestParts<B>) -> bool {
+    let content_type = req
+        .headers()
+        .and_then(|headers| headers.get(header::CONTENT_TYPE))
+        .and_then(|value| value.to_str().ok());
+    let essence = match content_type {
+        Some(content_type) => content_type.split(';').next().unwrap_or_default().trim(),
+        None => return false,
+    };
+    essence.eq_ignore_ascii_case("application/json")
+        || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
+
+/// `serde_path_to_error` points at the object for missing fields, so take the name from the message instead
+fn field_error(path: &str, err: &serde_json::Error) -> FieldError {
+    let message = err.to_string();
+    // The position is already in the path and isn't useful to show on a form
+    let message = match message.rfind(" at line ") {
+        Some(idx) => message[..idx].to_owned(),
+        None => message,
+    };
+
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.strip_suffix('`'));
+    let field = match (path, missing) {
+        (".", Some(name)) => name.to_owned(),
+        (path, Some(name)) => format!("{}.{}", path, name),
+        (path, None) => path.to_owned(),
+    };
+
+    FieldError {
+        field,
+        messages: vec![message],
+    }
+}
@@ -1,6 +1,18 @@
-//! Various extractors that return a Json error instead of a plain string
+//! Various extractors that return a Json error instead of a plain string,
+//! or our own [`crate::Error`] for the ones used by html pages
 
+mod form;
+mod if_match;
 mod json;
+mod path;
 mod query;
+mod validated_json;
 
-pub(crate) use {json::Json, query::Query};
+pub(crate) use {
+    form::{csrf_token, CsrfToken, Form},
+    if_match::IfMatch,
+    json::Json,
+    path::Path,
+    query::Query,
+    validated_json::ValidatedJson,
+};
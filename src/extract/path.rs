@@ -0,0 +1,39 @@
+use {
+    axum::{
+        async_trait,
+        extract::{rejection::PathRejection, FromRequest, RequestParts},
+    },
+    serde::de::DeserializeOwned,
+};
+
+use crate::{error::ErrorCode, CommonError, Error};
+
+pub(crate) struct Path<T>(pub T);
+
+#[async_trait]
+impl<B, T> FromRequest<B> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    B: Send,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::<T>::from_request(req).await {
+            Ok(value) => Ok(Self(value.0)),
+            Err(rejection) => {
+                let err = match rejection {
+                    PathRejection::FailedToDeserializePathParams(e) => CommonError::Rejected {
+                        code: ErrorCode::InvalidPath,
+                        detail: e.to_string(),
+                    },
+                    err => CommonError::Rejected {
+                        code: ErrorCode::InternalError,
+                        detail: format!("Unknown internal error: {}", err),
+                    },
+                };
+                Err(Error::ApiError(err))
+            }
+        }
+    }
+}
@@ -2,13 +2,13 @@ use {
     axum::{
         async_trait,
         extract::{rejection::QueryRejection, FromRequest, RequestParts},
-        http::StatusCode,
         BoxError,
     },
     serde::de::DeserializeOwned,
-    serde_json::{json, Value},
 };
 
+use crate::{error::ErrorCode, CommonError, Error};
+
 pub(crate) struct Query<T>(pub T);
 
 #[async_trait]
@@ -20,24 +20,23 @@ where
     B::Data: Send,
     B::Error: Into<BoxError>,
 {
-    type Rejection = (StatusCode, axum::Json<Value>);
+    type Rejection = Error;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
         match axum::extract::Query::<T>::from_request(req).await {
             Ok(value) => Ok(Self(value.0)),
             Err(rejection) => {
-                let (status, err_message) = match rejection {
-                    QueryRejection::FailedToDeserializeQueryString(e) => {
-                        (StatusCode::BAD_REQUEST, e.to_string())
-                    }
-                    err => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Unknown internal error: {}", err),
-                    ),
+                let err = match rejection {
+                    QueryRejection::FailedToDeserializeQueryString(e) => CommonError::Rejected {
+                        code: ErrorCode::InvalidQuery,
+                        detail: e.to_string(),
+                    },
+                    err => CommonError::Rejected {
+                        code: ErrorCode::InternalError,
+                        detail: format!("Unknown internal error: {}", err),
+                    },
                 };
-
-                let body = axum::Json(json!({ "error": err_message }));
-                Err((status, body))
+                Err(Error::ApiError(err))
             }
         }
     }
@@ -0,0 +1,36 @@
+use {
+    axum::{
+        async_trait,
+        extract::{FromRequest, RequestParts},
+        BoxError,
+    },
+    serde::de::DeserializeOwned,
+    validator::Validate,
+};
+
+use super::Json;
+use crate::{utils::validation::field_errors, CommonError, Error};
+
+/// Like [`Json`], but the value also has to pass the `#[validate]` rules of `T`.
+///
+/// All the invalid fields are returned at once, with every message for a field grouped together.
+pub(crate) struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<B, T> FromRequest<B> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    B: axum::body::HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req).await?;
+        value
+            .validate()
+            .map_err(|e| Error::ApiError(CommonError::Validation(field_errors(&e))))?;
+        Ok(Self(value))
+    }
+}
@@ -0,0 +1,181 @@
+//! The languages the pages are available in, and formatting of money and dates for each of them.
+//!
+//! The texts of the pages are in the catalogs of `locales/`, one `key = text` per line.
+//! A text that is missing from a catalog is taken from the English one.
+
+use std::{collections::HashMap, convert::Infallible};
+
+use {
+    axum::{
+        async_trait,
+        extract::{FromRequest, RequestParts},
+    },
+    bigdecimal::BigDecimal,
+    chrono::{DateTime, NaiveDate, Utc},
+    once_cell::sync::Lazy,
+    serde::{Deserialize, Serialize},
+    strum::EnumString,
+    utoipa::ToSchema,
+};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, EnumString, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum Locale {
+    #[default]
+    En,
+    De,
+    El,
+}
+
+type Catalog = HashMap<&'static str, &'static str>;
+
+static EN: Lazy<Catalog> = Lazy::new(|| parse(include_str!("../locales/en.txt")));
+static DE: Lazy<Catalog> = Lazy::new(|| parse(include_str!("../locales/de.txt")));
+static EL: Lazy<Catalog> = Lazy::new(|| parse(include_str!("../locales/el.txt")));
+
+fn parse(catalog: &'static str) -> Catalog {
+    catalog
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, text)| (key.trim(), text.trim()))
+        .collect()
+}
+
+impl Locale {
+    pub(crate) const ALL: [Self; 3] = [Self::En, Self::De, Self::El];
+
+    /// The language tag, for `<html lang>` and the `locale` cookie
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::De => "de",
+            Self::El => "el",
+        }
+    }
+
+    /// The name of the language, in the language itself
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::En => "English",
+            Self::De => "Deutsch",
+            Self::El => "Ελληνικά",
+        }
+    }
+
+    /// The text for `key` in this language
+    pub(crate) fn t(self, key: &str) -> &'static str {
+        let catalog = match self {
+            Self::En => &EN,
+            Self::De => &DE,
+            Self::El => &EL,
+        };
+        catalog
+            .get(key)
+            .or_else(|| EN.get(key))
+            .copied()
+            .unwrap_or_else(|| {
+                tracing::warn!("Missing text for `{}`", key);
+                ""
+            })
+    }
+
+    /// The language of the user, their own choice if they made one, otherwise the first of
+    /// `Accept-Language` that we have. English if there is none.
+    pub(crate) fn negotiate(preference: Option<&str>, accept_language: Option<&str>) -> Self {
+        if let Some(locale) = preference.and_then(|p| p.parse().ok()) {
+            return locale;
+        }
+
+        let mut ranges: Vec<(&str, f32)> = accept_language
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let tag = parts.next().filter(|tag| !tag.is_empty())?;
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse().ok())?;
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // Stable, so ranges with the same quality keep the order the browser sent them in
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ranges
+            .into_iter()
+            .find_map(|(tag, _)| {
+                let language = tag.split('-').next().unwrap_or_default();
+                language.to_ascii_lowercase().parse().ok()
+            })
+            .unwrap_or_default()
+    }
+
+    /// An amount of money with 2 decimals, with the separators of the language.
+    /// The currency is put where the language puts it, it's left out if there is none.
+    pub(crate) fn money(self, amount: &BigDecimal, currency: Option<&str>) -> String {
+        let (decimal, group) = match self {
+            Self::En => ('.', ','),
+            Self::De | Self::El => (',', '.'),
+        };
+
+        let plain = amount.with_scale(2).to_string();
+        let (sign, digits) = match plain.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", plain.as_str()),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, "00"));
+
+        let mut number = String::with_capacity(plain.len() + integer.len() / 3);
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                number.push(group);
+            }
+            number.push(digit);
+        }
+        number.push(decimal);
+        number.push_str(fraction);
+
+        match (self, currency) {
+            (_, None) => format!("{}{}", sign, number),
+            (Self::En, Some(currency)) => format!("{}{}{}", sign, currency, number),
+            // With a no-break space so the currency doesn't end up on its own line
+            (Self::De | Self::El, Some(currency)) => {
+                format!("{}{}\u{a0}{}", sign, number, currency)
+            }
+        }
+    }
+
+    pub(crate) fn date(self, date: NaiveDate) -> String {
+        let format = match self {
+            Self::En => "%b %-d, %Y",
+            Self::De => "%d.%m.%Y",
+            Self::El => "%d/%m/%Y",
+        };
+        date.format(format).to_string()
+    }
+
+    pub(crate) fn date_time(self, at: DateTime<Utc>) -> String {
+        format!(
+            "{} {}",
+            self.date(at.date().naive_utc()),
+            at.format("%H:%M")
+        )
+    }
+}
+
+/// The language of the user, negotiated by the [`crate::middleware::LocaleLayer`]
+#[async_trait]
+impl<B: Send> FromRequest<B> for Locale {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let extensions = req.extensions().expect("Extensions were taken");
+        Ok(extensions.get::<Self>().copied().unwrap_or_default())
+    }
+}
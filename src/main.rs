@@ -3,20 +3,30 @@ mod crud;
 mod error;
 mod extract;
 mod html_template;
+mod i18n;
+mod middleware;
 pub(crate) mod models;
 mod requests;
+mod responses;
 mod utils;
 mod views;
 
+#[cfg(test)]
+mod test_utils;
+
 use std::{env, net::SocketAddr};
 
 use {
     anyhow::Context,
     axum::{
-        handler::Handler, http::StatusCode, response::IntoResponse, routing::get_service,
+        handler::Handler,
+        http::{header::HeaderName, HeaderValue, Response, StatusCode},
+        response::IntoResponse,
+        routing::get_service,
         AddExtensionLayer, Router,
     },
     dotenv::dotenv,
+    tower::util::MapResponseLayer,
     tower_cookies::CookieManagerLayer,
     tower_http::{services::ServeDir, trace::TraceLayer},
     tracing::dispatcher::SetGlobalDefaultError,
@@ -25,7 +35,20 @@ use {
 
 pub(crate) use error::{CommonError, Error};
 
-use crate::{html_template::HtmlTemplate, models::user::UserClaims};
+use crate::{
+    html_template::HtmlTemplate,
+    middleware::{LocaleLayer, RequestId, RequestIdLayer},
+    models::user::UserClaims,
+};
+
+/// The service worker is served from `/static` but has to handle every page to make them work offline
+fn allow_service_worker<B>(mut res: Response<B>) -> Response<B> {
+    res.headers_mut().insert(
+        HeaderName::from_static("service-worker-allowed"),
+        HeaderValue::from_static("/"),
+    );
+    res
+}
 
 fn setup_logging() -> Result<(), SetGlobalDefaultError> {
     let rust_log = env::var("RUST_LOG").unwrap_or_else(|_| String::from("DEBUG,hyper=INFO"));
@@ -52,21 +75,29 @@ async fn main() -> anyhow::Result<()> {
 
     let secret = env::var("SECRET").context("Expected `SECRET` env variable")?;
     utils::set_secret(&secret);
+    utils::oidc::set_config_from_env()?;
+    utils::cookies::set_config_from_env()?;
+    middleware::set_idempotency_config_from_env()?;
+    utils::webhooks::set_config_from_env()?;
+    utils::webhooks::spawn_worker(db.clone());
+    utils::events::spawn_listener(db.clone());
 
     let port = env::var("PORT")
         .context("Missing env variable `PORT`")?
         .parse::<u16>()
         .context("`PORT` env variable is not valid")?;
 
-    let static_files = Router::new().nest(
-        "/static",
-        get_service(ServeDir::new("static")).handle_error(|err: std::io::Error| async move {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Unhandled internal error: {}", err),
-            )
-        }),
-    );
+    let static_files = Router::new()
+        .nest(
+            "/static",
+            get_service(ServeDir::new("static")).handle_error(|err: std::io::Error| async move {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Unhandled internal error: {}", err),
+                )
+            }),
+        )
+        .layer(MapResponseLayer::new(allow_service_worker));
 
     let api = api::routes();
     let views = views::routes();
@@ -75,14 +106,16 @@ async fn main() -> anyhow::Result<()> {
         .merge(api)
         .merge(views)
         .fallback(handle_404.into_service())
-        .layer(TraceLayer::new_for_http())
+        .layer(LocaleLayer)
+        .layer(TraceLayer::new_for_http().make_span_with(RequestId::make_span))
+        .layer(RequestIdLayer)
         .layer(CookieManagerLayer::new())
         .layer(AddExtensionLayer::new(db));
 
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     tracing::info!("Listening on {}", &addr);
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr, _>())
         .with_graceful_shutdown(shutdown_signal())
         .await
         .context("Failed to run axum::Server")
@@ -95,7 +128,7 @@ async fn handle_404(user: Option<UserClaims>) -> impl IntoResponse {
         views::views_404::View404::new(None)
     };
 
-    HtmlTemplate(view)
+    (StatusCode::NOT_FOUND, HtmlTemplate(view))
 }
 
 async fn shutdown_signal() {
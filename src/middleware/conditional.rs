@@ -0,0 +1,117 @@
+//! Conditional GET requests, so clients don't download what they already have
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use {
+    axum::{
+        body::{self, Body},
+        http::{header, HeaderValue, Method, Request, StatusCode},
+        response::{IntoResponse, Response},
+    },
+    sha2::{Digest, Sha256},
+    tower::{Layer, Service},
+};
+
+use crate::utils::etag::EntityTags;
+
+/// Gives every successful GET response an `ETag` and answers with 304 Not Modified if it's in `If-None-Match`.
+///
+/// Handlers of versioned entities set the `ETag` themselves, other responses get a weak one from a hash of the body.
+#[derive(Clone, Copy)]
+pub(crate) struct ConditionalGetLayer;
+
+impl<S> Layer<S> for ConditionalGetLayer {
+    type Service = ConditionalGet<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConditionalGet { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ConditionalGet<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for ConditionalGet<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // The clone might not be ready, so keep using the one `poll_ready` was called on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            if req.method() != Method::GET {
+                return inner.call(req).await;
+            }
+            let if_none_match = req
+                .headers()
+                .get(header::IF_NONE_MATCH)
+                .and_then(|value| value.to_str().ok())
+                .map(EntityTags::parse);
+
+            let res = inner.call(req).await?;
+            // The body of an event stream never ends, so it can't be hashed
+            if res.status() != StatusCode::OK || is_event_stream(&res) {
+                return Ok(res);
+            }
+            let res = match res.headers().contains_key(header::ETAG) {
+                true => res,
+                false => with_body_etag(res).await,
+            };
+
+            let etag = res.headers().get(header::ETAG).cloned();
+            match (if_none_match, etag) {
+                (Some(tags), Some(etag))
+                    if tags.matches_weak(etag.to_str().unwrap_or_default()) =>
+                {
+                    let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+                    not_modified.headers_mut().insert(header::ETAG, etag);
+                    Ok(not_modified)
+                }
+                _ => Ok(res),
+            }
+        })
+    }
+}
+
+fn is_event_stream(res: &Response) -> bool {
+    res.headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"text/event-stream"))
+}
+
+async fn with_body_etag(res: Response) -> Response {
+    let (mut head, res_body) = res.into_parts();
+    let res_body = match hyper::body::to_bytes(res_body).await {
+        Ok(res_body) => res_body,
+        Err(e) => {
+            tracing::error!("Failed to read the response body: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let hash = Sha256::digest(&res_body);
+    let hash: String = hash[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    let etag = format!("W/\"{}\"", hash);
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        head.headers.insert(header::ETAG, etag);
+    }
+    Response::from_parts(head, body::boxed(Body::from(res_body)))
+}
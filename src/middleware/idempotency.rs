@@ -0,0 +1,280 @@
+//! Support for the `Idempotency-Key` header, so clients can safely retry POST requests
+
+use std::{
+    convert::Infallible,
+    env,
+    future::Future,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+
+use {
+    anyhow::Context,
+    axum::{
+        body::{self, Body},
+        extract::{Extension, FromRequest, RequestParts},
+        http::{header::HeaderName, HeaderMap, HeaderValue, Method, Request, StatusCode},
+        response::{IntoResponse, Response},
+    },
+    chrono::Duration,
+    once_cell::sync::OnceCell,
+    serde_json::{Map, Value},
+    sha2::{Digest, Sha256},
+    sqlx::PgPool,
+    tower::{Layer, Service},
+    uuid::Uuid,
+};
+
+use crate::{
+    crud, error::ErrorCode, models::idempotency::IdempotencyKeyRow, models::user::UserClaims,
+    CommonError, Error,
+};
+
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+/// Set on responses that were replayed instead of running the request again
+const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+const MAX_KEY_LEN: usize = 255;
+/// How often a running request refreshes its reservation of the key, well within the lease
+const HEARTBEAT: std::time::Duration =
+    std::time::Duration::from_secs(crud::idempotency::RESERVATION_LEASE_SECS as u64 / 3);
+
+static WINDOW: OnceCell<Duration> = OnceCell::new();
+
+/// Read how long keys are kept from the `IDEMPOTENCY_WINDOW_HOURS` env variable, 24 hours by default
+///
+/// # Panic
+/// Will panic if it gets called more than once
+pub(crate) fn set_config_from_env() -> anyhow::Result<()> {
+    let hours = match env::var("IDEMPOTENCY_WINDOW_HOURS") {
+        Ok(v) => v
+            .parse::<u32>()
+            .context("`IDEMPOTENCY_WINDOW_HOURS` env variable is not valid")?,
+        Err(_) => 24,
+    };
+
+    if WINDOW.set(Duration::hours(hours.into())).is_err() {
+        panic!("Idempotency config has been set before");
+    }
+    Ok(())
+}
+
+fn window() -> Duration {
+    *WINDOW.get().expect("Idempotency config has not been set")
+}
+
+/// Replays the first response to POST requests of a user that have the same `Idempotency-Key` header.
+///
+/// A retry with a different method, path, query or body than the first request is rejected,
+/// and so is one that arrives while the first is still running.
+/// Requests without the header, or from someone that isn't logged in, are passed through untouched.
+#[derive(Clone, Copy)]
+pub(crate) struct IdempotencyLayer;
+
+impl<S> Layer<S> for IdempotencyLayer {
+    type Service = Idempotency<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Idempotency { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Idempotency<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for Idempotency<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // The clone might not be ready, so keep using the one `poll_ready` was called on
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            if req.method() != Method::POST || !req.headers().contains_key(IDEMPOTENCY_KEY) {
+                let mut inner = inner;
+                return inner.call(req).await;
+            }
+            match handle(inner, req).await {
+                Ok(res) => Ok(res),
+                Err(e) => Ok(Error::ApiError(e).into_response()),
+            }
+        })
+    }
+}
+
+async fn handle<S>(mut inner: S, req: Request<Body>) -> Result<Response, CommonError>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible>,
+{
+    let key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|key| key.to_str().ok())
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| CommonError::Rejected {
+            code: ErrorCode::InvalidIdempotencyKey,
+            detail: format!(
+                "The `Idempotency-Key` header must be between 1 and {} visible characters",
+                MAX_KEY_LEN
+            ),
+        })?;
+
+    let (head, req_body) = req.into_parts();
+    let req_body = hyper::body::to_bytes(req_body).await.map_err(|e| {
+        let detail = format!("Failed to read the body: {}", e);
+        CommonError::from((StatusCode::BAD_REQUEST, detail))
+    })?;
+    let mut parts = RequestParts::new(Request::from_parts(head, Body::from(req_body.clone())));
+
+    let user = match UserClaims::from_request(&mut parts).await {
+        Ok(user) => user,
+        // Let the handler decide what to do with requests that aren't authenticated
+        Err(_) => {
+            let req = parts.try_into_request().expect("Request was extracted");
+            return Ok(inner.call(req).await.unwrap_or_else(|e| match e {}));
+        }
+    };
+    let Extension(db) = Extension::<PgPool>::from_request(&mut parts)
+        .await
+        .expect("`PgPool` extension is missing");
+
+    let mut hasher = Sha256::new();
+    hasher.update(parts.method().as_str());
+    hasher.update(b" ");
+    hasher.update(parts.uri().path());
+    if let Some(query) = parts.uri().query() {
+        hasher.update(b"?");
+        hasher.update(query);
+    }
+    hasher.update(b"\n");
+    hasher.update(&req_body);
+    let request_hash = hasher.finalize();
+
+    let reservation = Uuid::new_v4();
+    if let Some(earlier) =
+        crud::idempotency::claim_key(&db, user.id, &key, reservation, &request_hash, window())
+            .await?
+    {
+        return replay(earlier, &request_hash);
+    }
+
+    let req = parts.try_into_request().expect("Request was extracted");
+    let heartbeat = async {
+        let mut interval = tokio::time::interval(HEARTBEAT);
+        // The first tick is right away, the key was just reserved
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) =
+                crud::idempotency::refresh_reservation(&db, user.id, &key, reservation).await
+            {
+                tracing::error!("Failed to refresh the idempotency key reservation: {}", e);
+            }
+        }
+    };
+    // Polled together, so a slow refresh doesn't hold up the request
+    let res = tokio::select! {
+        res = inner.call(req) => res.unwrap_or_else(|e| match e {}),
+        _ = heartbeat => unreachable!("The heartbeat never stops"),
+    };
+    save(&db, user.id, &key, reservation, res).await
+}
+
+fn replay(earlier: IdempotencyKeyRow, request_hash: &[u8]) -> Result<Response, CommonError> {
+    if earlier.request_hash != request_hash {
+        return Err(CommonError::Rejected {
+            code: ErrorCode::IdempotencyKeyReused,
+            detail: "The idempotency key was already used for a different request".into(),
+        });
+    }
+    let status = match earlier.status {
+        Some(status) => StatusCode::from_u16(status as u16).unwrap_or(StatusCode::OK),
+        None => {
+            return Err(CommonError::Rejected {
+                code: ErrorCode::RequestInProgress,
+                detail: "A request with this idempotency key is still running".into(),
+            })
+        }
+    };
+
+    let mut res = Response::new(body::boxed(Body::from(earlier.body.unwrap_or_default())));
+    *res.status_mut() = status;
+    if let Some(Value::Object(headers)) = earlier.headers {
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes());
+            let value = value.as_str().map(HeaderValue::from_str);
+            if let (Ok(name), Some(Ok(value))) = (name, value) {
+                res.headers_mut().insert(name, value);
+            }
+        }
+    }
+    res.headers_mut()
+        .insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    Ok(res)
+}
+
+/// Store the response for retries, or release the key if it failed so it can be retried for real
+async fn save(
+    db: &PgPool,
+    user_id: Uuid,
+    key: &str,
+    reservation: Uuid,
+    res: Response,
+) -> Result<Response, CommonError> {
+    if res.status().is_server_error() {
+        crud::idempotency::release_key(db, user_id, key, reservation).await?;
+        return Ok(res);
+    }
+
+    let (head, res_body) = res.into_parts();
+    let res_body = match hyper::body::to_bytes(res_body).await {
+        Ok(res_body) => res_body,
+        Err(e) => {
+            tracing::error!("Failed to read the response body: {}", e);
+            crud::idempotency::release_key(db, user_id, key, reservation).await?;
+            return Err(CommonError::from((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read the response",
+            )));
+        }
+    };
+    crud::idempotency::save_response(
+        db,
+        user_id,
+        key,
+        reservation,
+        head.status.as_u16(),
+        headers_to_json(&head.headers),
+        res_body.to_vec(),
+    )
+    .await?;
+
+    Ok(Response::from_parts(
+        head,
+        body::boxed(Body::from(res_body)),
+    ))
+}
+
+fn headers_to_json(headers: &HeaderMap) -> Value {
+    let headers = headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let value = value.to_str().ok()?;
+            Some((name.to_string(), Value::String(value.to_owned())))
+        })
+        .collect::<Map<_, _>>();
+    Value::Object(headers)
+}
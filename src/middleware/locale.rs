@@ -0,0 +1,92 @@
+//! Figures out the language of every request once, for the pages and the error pages
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use {
+    axum::{
+        body::Body,
+        http::{header, Request},
+        response::Response,
+    },
+    tower::{Layer, Service},
+    tower_cookies::Cookies,
+};
+
+use crate::{extract, i18n::Locale, utils::cookies};
+
+tokio::task_local! {
+    static LOCALE: Locale;
+    static JAR: Option<Cookies>;
+}
+
+/// The language of the request that is being handled, English outside of a request
+pub(crate) fn current_locale() -> Locale {
+    LOCALE.try_with(|locale| *locale).unwrap_or_default()
+}
+
+/// The CSRF token of the request that is being handled, for the language menu that every page has.
+/// Empty outside of a request.
+pub(crate) fn current_csrf_token() -> String {
+    JAR.try_with(|jar| jar.as_ref().map(extract::csrf_token))
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Puts the [`Locale`] of the user in the extensions of every request.
+///
+/// It must be inside the `CookieManagerLayer` so it can read the `locale` cookie.
+#[derive(Clone, Copy)]
+pub(crate) struct LocaleLayer;
+
+impl<S> Layer<S> for LocaleLayer {
+    type Service = LocaleService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LocaleService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct LocaleService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for LocaleService<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        // The clone might not be ready, so keep using the one `poll_ready` was called on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let jar = req.extensions().get::<Cookies>().cloned();
+        let preference = jar
+            .as_ref()
+            .and_then(|jar| jar.get(cookies::LOCALE))
+            .map(|cookie| cookie.value().to_owned());
+        let accept_language = req
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok());
+        let locale = Locale::negotiate(preference.as_deref(), accept_language);
+        req.extensions_mut().insert(locale);
+
+        Box::pin(LOCALE.scope(locale, JAR.scope(jar, inner.call(req))))
+    }
+}
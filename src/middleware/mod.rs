@@ -0,0 +1,13 @@
+//! Tower layers that wrap whole groups of routes
+
+mod conditional;
+mod idempotency;
+mod locale;
+mod request_id;
+
+pub(crate) use conditional::ConditionalGetLayer;
+pub(crate) use idempotency::{
+    set_config_from_env as set_idempotency_config_from_env, IdempotencyLayer,
+};
+pub(crate) use locale::{current_csrf_token, current_locale, LocaleLayer};
+pub(crate) use request_id::{RequestId, RequestIdLayer};
@@ -0,0 +1,117 @@
+//! Gives every request an id, so what a user sees on an error page can be found in the logs
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use {
+    axum::{
+        body::Body,
+        http::{HeaderValue, Request},
+        response::Response,
+    },
+    tower::{Layer, Service},
+    tracing::Span,
+    uuid::Uuid,
+};
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Ids sent by clients longer than this are replaced with one of ours
+const MAX_LEN: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: RequestId;
+}
+
+/// The id of a request, in its extensions and in the `x-request-id` header of its response
+#[derive(Debug, Clone)]
+pub(crate) struct RequestId(pub(crate) String);
+
+impl RequestId {
+    /// The id of the request that is being handled, if there is one
+    pub(crate) fn current() -> Option<Self> {
+        REQUEST_ID.try_with(Self::clone).ok()
+    }
+
+    /// A span for the request that has its id, for the `TraceLayer`
+    pub(crate) fn make_span(req: &Request<Body>) -> Span {
+        let id = req
+            .extensions()
+            .get::<Self>()
+            .map_or("", |id| id.0.as_str());
+        tracing::info_span!(
+            "request",
+            method = %req.method(),
+            uri = %req.uri(),
+            request_id = %id,
+        )
+    }
+
+    /// Keep the id a proxy in front of us gave the request, so both logs have the same one
+    fn from_request(req: &Request<Body>) -> Self {
+        let given = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| !id.is_empty() && id.len() <= MAX_LEN);
+        match given {
+            Some(id) => Self(id.to_owned()),
+            None => Self(Uuid::new_v4().to_simple().to_string()),
+        }
+    }
+}
+
+/// Puts a [`RequestId`] in the extensions of every request and in the headers of its response.
+///
+/// It must wrap the `TraceLayer` so the span of the request can have the id.
+#[derive(Clone, Copy)]
+pub(crate) struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequestIdService<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        // The clone might not be ready, so keep using the one `poll_ready` was called on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        let id = RequestId::from_request(&req);
+        req.extensions_mut().insert(id.clone());
+        let header = HeaderValue::from_str(&id.0).ok();
+
+        Box::pin(REQUEST_ID.scope(id, async move {
+            let mut res = inner.call(req).await?;
+            if let Some(header) = header {
+                res.headers_mut().insert(REQUEST_ID_HEADER, header);
+            }
+            Ok(res)
+        }))
+    }
+}
@@ -3,6 +3,7 @@ use {
     serde::{Deserialize, Serialize},
     sqlx::types::{BigDecimal, Uuid},
     strum::EnumIter,
+    utoipa::{IntoParams, ToSchema},
 };
 
 #[derive(Iden, EnumIter)]
@@ -16,32 +17,41 @@ pub(crate) enum AccountTable {
     TotalMoney,
     UserId,
     IsAdhoc,
+    HouseholdId,
+    Version,
 }
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, ToSchema)]
 /// Can be any account
 pub(crate) struct AccountRow {
     pub(crate) id: i32,
     pub(crate) name: String,
     pub(crate) description: Option<String>,
+    #[schema(value_type = Option<String>)]
     pub(crate) available_money: Option<BigDecimal>,
+    #[schema(value_type = Option<String>)]
     pub(crate) total_money: Option<BigDecimal>,
     pub(crate) user_id: Uuid,
     pub(crate) is_adhoc: bool,
+    pub(crate) household_id: Option<i32>,
+    /// Sent as the `ETag`, bumped on every update
+    pub(crate) version: i32,
 }
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, ToSchema)]
 /// Specific for normal accounts
 pub(crate) struct NormalAccountRow {
     pub(crate) id: i32,
     pub(crate) name: String,
     pub(crate) description: Option<String>,
+    #[schema(value_type = String)]
     pub(crate) available_money: BigDecimal,
+    #[schema(value_type = String)]
     pub(crate) total_money: BigDecimal,
     pub(crate) user_id: Uuid,
 }
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, ToSchema)]
 /// Specific for adhoc accounts
 pub(crate) struct AdhocAccountRow {
     pub(crate) id: i32,
@@ -49,7 +59,7 @@ pub(crate) struct AdhocAccountRow {
     pub(crate) user_id: Uuid,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub(crate) enum AccountType {
     #[serde(alias = "any")]
     #[serde(alias = "*")]
@@ -61,9 +71,11 @@ pub(crate) enum AccountType {
     Normal,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
 #[serde(default)]
+#[into_params(parameter_in = Query)]
 pub(crate) struct AccountTypeQuery {
+    /// Also accepted as `type`
     #[serde(alias = "type")]
     pub(crate) account_type: AccountType,
 }
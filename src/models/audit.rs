@@ -0,0 +1,156 @@
+use std::net::SocketAddr;
+
+use {
+    axum::{
+        async_trait,
+        extract::{ConnectInfo, FromRequest, RequestParts, TypedHeader},
+    },
+    chrono::{DateTime, FixedOffset, Utc},
+    headers::UserAgent,
+    sea_query::{self, Iden},
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    strum::{AsRefStr, EnumString},
+    utoipa::{IntoParams, ToSchema},
+    uuid::Uuid,
+};
+
+#[derive(Iden)]
+pub(crate) enum AuditLogTable {
+    #[iden = "audit_log"]
+    Table,
+    Id,
+    CreatedAt,
+    UserId,
+    Action,
+    EntityType,
+    EntityId,
+    Before,
+    After,
+    Ip,
+    UserAgent,
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct AuditLogRow {
+    pub(crate) id: i64,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) user_id: Option<Uuid>,
+    pub(crate) action: AuditAction,
+    pub(crate) entity_type: AuditEntity,
+    pub(crate) entity_id: Option<String>,
+    #[schema(value_type = Option<Object>)]
+    pub(crate) before: Option<Value>,
+    #[schema(value_type = Option<Object>)]
+    pub(crate) after: Option<Value>,
+    pub(crate) ip: Option<String>,
+    pub(crate) user_agent: Option<String>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    LoginSuccess,
+    LoginFailure,
+}
+
+text_enum!(AuditAction);
+
+/// The kind of thing an audit log entry is about
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum AuditEntity {
+    User,
+    Account,
+    Tag,
+    Goal,
+    Household,
+    HouseholdMember,
+    HouseholdInvite,
+    Webhook,
+    Transaction,
+}
+
+text_enum!(AuditEntity);
+
+/// A change that is about to be written to the audit log
+pub(crate) struct AuditEntry<'a> {
+    pub(crate) user_id: Option<Uuid>,
+    pub(crate) action: AuditAction,
+    pub(crate) entity_type: AuditEntity,
+    pub(crate) entity_id: Option<String>,
+    pub(crate) before: Option<Value>,
+    pub(crate) after: Option<Value>,
+    pub(crate) client: &'a ClientInfo,
+}
+
+/// Where a request came from, recorded in the audit log
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ClientInfo {
+    pub(crate) ip: Option<String>,
+    pub(crate) user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for ClientInfo {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let ip = ConnectInfo::<SocketAddr>::from_request(req)
+            .await
+            .ok()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+        let user_agent = TypedHeader::<UserAgent>::from_request(req)
+            .await
+            .ok()
+            .map(|TypedHeader(agent)| agent.to_string());
+
+        Ok(Self { ip, user_agent })
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct AuditLogQuery {
+    pub(crate) action: Option<AuditAction>,
+    pub(crate) entity_type: Option<AuditEntity>,
+    pub(crate) entity_id: Option<String>,
+    /// Only used by admins, normal users can only see their own entries
+    pub(crate) user_id: Option<Uuid>,
+    pub(crate) since: Option<DateTime<FixedOffset>>,
+    pub(crate) until: Option<DateTime<FixedOffset>>,
+    /// Starts at 1
+    #[serde(default = "default_page")]
+    #[param(default = 1)]
+    pub(crate) page: u64,
+    /// At most 200
+    #[serde(default = "default_per_page")]
+    #[param(default = 50)]
+    pub(crate) per_page: u64,
+}
+
+fn default_page() -> u64 {
+    1
+}
+
+fn default_per_page() -> u64 {
+    50
+}
+
+/// A page of audit log entries
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct AuditLogPage {
+    pub(crate) entries: Vec<AuditLogRow>,
+    pub(crate) page: u64,
+    pub(crate) per_page: u64,
+    pub(crate) total: i64,
+}
@@ -0,0 +1,46 @@
+use {
+    sea_query::{self, Iden},
+    serde::Serialize,
+    sqlx::types::BigDecimal,
+    strum::EnumIter,
+    utoipa::ToSchema,
+    uuid::Uuid,
+};
+
+#[derive(Iden, EnumIter)]
+pub(crate) enum GoalTable {
+    #[iden = "goals"]
+    Table,
+    Id,
+    Name,
+    Description,
+    Target,
+    Balance,
+    AccountId,
+    UserId,
+    Version,
+}
+
+/// Money set aside in an account to save up for something, it's left out of the available money of the account
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct GoalRow {
+    pub(crate) id: i32,
+    pub(crate) name: String,
+    pub(crate) description: Option<String>,
+    #[schema(value_type = String)]
+    pub(crate) target: BigDecimal,
+    /// What was set aside so far
+    #[schema(value_type = String)]
+    pub(crate) balance: BigDecimal,
+    /// The normal account the money is set aside in
+    pub(crate) account_id: i32,
+    pub(crate) user_id: Uuid,
+    /// Sent as the `ETag`, bumped on every update
+    pub(crate) version: i32,
+}
+
+impl GoalRow {
+    pub(crate) fn is_reached(&self) -> bool {
+        self.balance >= self.target
+    }
+}
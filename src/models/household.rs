@@ -0,0 +1,113 @@
+use {
+    sea_query::{self, Iden},
+    serde::{Deserialize, Serialize},
+    strum::{AsRefStr, EnumString},
+    utoipa::ToSchema,
+    uuid::Uuid,
+};
+
+#[derive(Iden)]
+pub(crate) enum HouseholdTable {
+    #[iden = "households"]
+    Table,
+    Id,
+    Name,
+    CreatedBy,
+}
+
+#[derive(Iden)]
+pub(crate) enum HouseholdMemberTable {
+    #[iden = "household_members"]
+    Table,
+    HouseholdId,
+    UserId,
+    Role,
+}
+
+#[derive(Iden)]
+pub(crate) enum HouseholdInviteTable {
+    #[iden = "household_invites"]
+    Table,
+    Id,
+    HouseholdId,
+    UserId,
+    InvitedBy,
+    Role,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct HouseholdRow {
+    pub(crate) id: i32,
+    pub(crate) name: String,
+    pub(crate) created_by: Uuid,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct HouseholdMemberRow {
+    pub(crate) user_id: Uuid,
+    pub(crate) username: String,
+    pub(crate) role: MemberRole,
+}
+
+/// A member together with the household they are a member of
+#[derive(sqlx::FromRow, Debug)]
+pub(crate) struct HouseholdMembership {
+    pub(crate) household_id: i32,
+    pub(crate) user_id: Uuid,
+    pub(crate) username: String,
+    pub(crate) role: MemberRole,
+}
+
+impl From<HouseholdMembership> for HouseholdMemberRow {
+    fn from(membership: HouseholdMembership) -> Self {
+        Self {
+            user_id: membership.user_id,
+            username: membership.username,
+            role: membership.role,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, ToSchema)]
+pub(crate) struct HouseholdInviteRow {
+    pub(crate) id: i32,
+    pub(crate) household_id: i32,
+    pub(crate) user_id: Uuid,
+    pub(crate) invited_by: Uuid,
+    pub(crate) role: MemberRole,
+}
+
+/// A household together with its members
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct HouseholdDetails {
+    #[serde(flatten)]
+    pub(crate) household: HouseholdRow,
+    pub(crate) members: Vec<HouseholdMemberRow>,
+}
+
+/// What a member is allowed to do with the data of a household
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum MemberRole {
+    /// Can change shared data and manage the members
+    Owner,
+    /// Can change shared data
+    Editor,
+    /// Can only see shared data
+    Viewer,
+}
+
+impl MemberRole {
+    pub(crate) fn can_write(self) -> bool {
+        matches!(self, MemberRole::Owner | MemberRole::Editor)
+    }
+
+    pub(crate) fn can_manage(self) -> bool {
+        self == MemberRole::Owner
+    }
+}
+
+text_enum!(MemberRole);
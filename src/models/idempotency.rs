@@ -0,0 +1,30 @@
+use {
+    sea_query::{self, Iden},
+    serde_json::Value,
+};
+
+#[derive(Iden)]
+pub(crate) enum IdempotencyKeyTable {
+    #[iden = "idempotency_keys"]
+    Table,
+    UserId,
+    Key,
+    RequestHash,
+    Status,
+    Headers,
+    Body,
+    CreatedAt,
+    Reservation,
+    RefreshedAt,
+}
+
+/// A request that was already made with the same key
+#[derive(sqlx::FromRow, Debug)]
+pub(crate) struct IdempotencyKeyRow {
+    pub(crate) request_hash: Vec<u8>,
+    /// None while the first request hasn't finished
+    pub(crate) status: Option<i16>,
+    /// The headers of the response as a `{ name: value }` object
+    pub(crate) headers: Option<Value>,
+    pub(crate) body: Option<Vec<u8>>,
+}
@@ -1,4 +1,38 @@
 //! Database models
+
+/// Implement `sqlx::Type` and `sqlx::Decode` for an enum that is stored as text in the db.
+///
+/// The enum needs to implement `FromStr`
+macro_rules! text_enum {
+    ($name:ty) => {
+        impl sqlx::Type<sqlx::Postgres> for $name {
+            fn type_info() -> sqlx::postgres::PgTypeInfo {
+                <&str as sqlx::Type<sqlx::Postgres>>::type_info()
+            }
+
+            fn compatible(ty: &sqlx::postgres::PgTypeInfo) -> bool {
+                <&str as sqlx::Type<sqlx::Postgres>>::compatible(ty)
+            }
+        }
+
+        impl<'r> sqlx::Decode<'r, sqlx::Postgres> for $name {
+            fn decode(
+                value: sqlx::postgres::PgValueRef<'r>,
+            ) -> Result<Self, sqlx::error::BoxDynError> {
+                let value = <&str as sqlx::Decode<sqlx::Postgres>>::decode(value)?;
+                Ok(value.parse()?)
+            }
+        }
+    };
+}
+
 pub(crate) mod account;
+pub(crate) mod audit;
+pub(crate) mod goal;
+pub(crate) mod household;
+pub(crate) mod idempotency;
+pub(crate) mod settings;
 pub(crate) mod tag;
+pub(crate) mod transaction;
 pub(crate) mod user;
+pub(crate) mod webhook;
@@ -0,0 +1,152 @@
+use {
+    chrono::{Datelike, NaiveDate},
+    sea_query::{self, Iden},
+    serde::{Deserialize, Serialize},
+    strum::{AsRefStr, EnumString},
+    utoipa::ToSchema,
+};
+
+use crate::i18n::Locale;
+
+#[derive(Iden)]
+pub(crate) enum UserSettingsTable {
+    #[iden = "user_settings"]
+    Table,
+    UserId,
+    Currency,
+    Locale,
+    FirstDayOfWeek,
+    PeriodStartDay,
+    DefaultAccountId,
+    Theme,
+}
+
+/// How a user wants to see their data
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct UserSettings {
+    /// ISO 4217 code of the currency amounts are shown in, none when it's not set
+    pub(crate) currency: Option<String>,
+    /// Language of the pages, the one of the browser when it's not set
+    pub(crate) locale: Option<Locale>,
+    pub(crate) first_day_of_week: Weekday,
+    /// Budget periods start on this day of the month, e.g. payday
+    #[schema(minimum = 1, maximum = 31)]
+    pub(crate) period_start_day: i16,
+    /// Where new transactions go when no account is picked
+    pub(crate) default_account_id: Option<i32>,
+    pub(crate) theme: Theme,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            currency: None,
+            locale: None,
+            first_day_of_week: Weekday::Monday,
+            period_start_day: 1,
+            default_account_id: None,
+            theme: Theme::Light,
+        }
+    }
+}
+
+impl UserSettings {
+    /// The first day of the budget period `day` is in.
+    ///
+    /// Months that are too short for the start day start their period on their last day.
+    pub(crate) fn period_start(&self, day: NaiveDate) -> NaiveDate {
+        let this_month = period_start_in(day.year(), day.month(), self.period_start_day);
+        if this_month <= day {
+            return this_month;
+        }
+        match day.month() {
+            1 => period_start_in(day.year() - 1, 12, self.period_start_day),
+            month => period_start_in(day.year(), month - 1, self.period_start_day),
+        }
+    }
+}
+
+fn period_start_in(year: i32, month: u32, start_day: i16) -> NaiveDate {
+    // Going back from the wanted day until it's one the month has
+    (1..=start_day.clamp(1, 31) as u32)
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .expect("Every month to have a first day")
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    pub(crate) const ALL: [Self; 7] = [
+        Self::Monday,
+        Self::Tuesday,
+        Self::Wednesday,
+        Self::Thursday,
+        Self::Friday,
+        Self::Saturday,
+        Self::Sunday,
+    ];
+
+    /// The key of its name in the catalogs of `locales/`
+    pub(crate) fn text_key(self) -> &'static str {
+        match self {
+            Self::Monday => "weekday.monday",
+            Self::Tuesday => "weekday.tuesday",
+            Self::Wednesday => "weekday.wednesday",
+            Self::Thursday => "weekday.thursday",
+            Self::Friday => "weekday.friday",
+            Self::Saturday => "weekday.saturday",
+            Self::Sunday => "weekday.sunday",
+        }
+    }
+
+    /// The first day of the week `day` is in
+    pub(crate) fn week_start(self, day: NaiveDate) -> NaiveDate {
+        let first = self as i64;
+        let weekday = day.weekday().num_days_from_monday() as i64;
+        day - chrono::Duration::days((weekday - first).rem_euclid(7))
+    }
+}
+
+text_enum!(Weekday);
+
+/// The colors of the pages, `system` follows the setting of the browser
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum Theme {
+    Light,
+    Dark,
+    System,
+}
+
+impl Theme {
+    pub(crate) const ALL: [Self; 3] = [Self::Light, Self::Dark, Self::System];
+
+    /// The key of its name in the catalogs of `locales/`
+    pub(crate) fn text_key(self) -> &'static str {
+        match self {
+            Self::Light => "settings.theme_light",
+            Self::Dark => "settings.theme_dark",
+            Self::System => "settings.theme_system",
+        }
+    }
+}
+
+text_enum!(Theme);
+text_enum!(Locale);
@@ -3,6 +3,7 @@ use {
     serde::{Deserialize, Serialize},
     sqlx::types::BigDecimal,
     strum::EnumIter,
+    utoipa::ToSchema,
     uuid::Uuid,
 };
 
@@ -16,14 +17,28 @@ pub(crate) enum TagTable {
     Limit,
     Balance,
     UserId,
+    HouseholdId,
+    Version,
 }
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub(crate) struct TagRow {
     pub(crate) id: i32,
     pub(crate) name: String,
     pub(crate) description: Option<String>,
+    #[schema(value_type = Option<String>)]
     pub(crate) limit: Option<BigDecimal>,
+    #[schema(value_type = String)]
     pub(crate) balance: BigDecimal,
     pub(crate) user_id: Uuid,
+    pub(crate) household_id: Option<i32>,
+    /// Sent as the `ETag`, bumped on every update
+    pub(crate) version: i32,
+}
+
+impl TagRow {
+    /// `spent` is what was spent with the tag since the start of the budget period
+    pub(crate) fn is_over_limit(&self, spent: &BigDecimal) -> bool {
+        matches!(&self.limit, Some(limit) if spent > limit)
+    }
 }
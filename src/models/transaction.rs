@@ -0,0 +1,65 @@
+use {
+    chrono::{DateTime, NaiveDate, Utc},
+    sea_query::{self, Iden},
+    serde::Serialize,
+    sqlx::types::{BigDecimal, Uuid},
+};
+
+#[derive(Iden)]
+pub(crate) enum TransactionTable {
+    #[iden = "transactions"]
+    Table,
+    Id,
+    SourceId,
+    DestinationId,
+    Amount,
+    Description,
+    HappenedOn,
+    UserId,
+    CreatedAt,
+    ClientId,
+}
+
+#[derive(Iden)]
+pub(crate) enum TransactionTagTable {
+    #[iden = "transaction_tags"]
+    Table,
+    TransactionId,
+    TagId,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize)]
+pub(crate) struct TransactionRow {
+    pub(crate) id: i32,
+    pub(crate) source_id: i32,
+    pub(crate) destination_id: i32,
+    pub(crate) amount: BigDecimal,
+    pub(crate) description: Option<String>,
+    pub(crate) happened_on: NaiveDate,
+    /// Who entered it
+    pub(crate) user_id: Uuid,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+/// How saving an entry with a client id went
+#[derive(Debug)]
+pub(crate) enum SavedTransaction {
+    Created(TransactionRow),
+    /// It had been saved before, this is the transaction from back then
+    Duplicate(TransactionRow),
+}
+
+/// A transaction with the names of its accounts and tags
+#[derive(sqlx::FromRow, Debug, Clone, Serialize)]
+pub(crate) struct TransactionDetails {
+    pub(crate) id: i32,
+    pub(crate) source_id: i32,
+    pub(crate) source_name: String,
+    pub(crate) destination_id: i32,
+    pub(crate) destination_name: String,
+    pub(crate) amount: BigDecimal,
+    pub(crate) description: Option<String>,
+    pub(crate) happened_on: NaiveDate,
+    #[sqlx(default)]
+    pub(crate) tags: Vec<String>,
+}
@@ -1,17 +1,22 @@
+use std::marker::PhantomData;
+
 use {
     axum::{
         async_trait,
-        extract::{FromRequest, OriginalUri, RequestParts, TypedHeader},
+        extract::{Extension, FromRequest, OriginalUri, RequestParts, TypedHeader},
+        http::Method,
     },
     headers::{authorization::Bearer, Authorization},
     sea_query::{self, Iden},
     serde::{Deserialize, Serialize},
     sqlx::PgPool,
+    strum::{AsRefStr, EnumString},
     tower_cookies::Cookies,
+    utoipa::ToSchema,
     uuid::Uuid,
 };
 
-use crate::{crud, CommonError, Error};
+use crate::{crud, utils::cookies, CommonError, Error};
 
 #[derive(Iden)]
 pub(crate) enum UserTable {
@@ -20,17 +25,47 @@ pub(crate) enum UserTable {
     Id,
     Username,
     PasswordHash,
-    Admin,
+    Role,
+    OidcSubject,
 }
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize)]
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, ToSchema)]
 pub(crate) struct UserRow {
     pub(crate) id: uuid::Uuid,
     pub(crate) username: String,
+    #[serde(skip_serializing)]
+    #[schema(write_only)]
     pub(crate) password_hash: String,
-    pub(crate) admin: bool,
+    pub(crate) role: Role,
 }
 
+/// What a user is allowed to do
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum Role {
+    /// Can do everything, including managing other users
+    Admin,
+    /// Can read and change their own data
+    User,
+    /// Can only read their own data
+    Viewer,
+}
+
+impl Role {
+    pub(crate) fn can_write(self) -> bool {
+        matches!(self, Role::Admin | Role::User)
+    }
+
+    pub(crate) fn is_admin(self) -> bool {
+        self == Role::Admin
+    }
+}
+
+text_enum!(Role);
+
 fn extract_token(
     cookies: Cookies,
     header: Option<TypedHeader<Authorization<Bearer>>>,
@@ -39,26 +74,40 @@ fn extract_token(
         Ok(bearer.token().to_string())
     } else {
         let cookie = cookies
-            .get("access_token")
+            .get(cookies::ACCESS_TOKEN)
             .ok_or(CommonError::MissingCredentials("Missing access token"))?;
         Ok(cookie.value().to_string())
     }
 }
 
+/// Figure out if a failed extraction should result in an api or an html error
+async fn error_kind<B: Send>(req: &mut RequestParts<B>) -> fn(CommonError) -> Error {
+    let OriginalUri(uri) = OriginalUri::from_request(req)
+        .await
+        .expect("It's Infallible");
+    if uri.path().starts_with("/api") {
+        Error::ApiError
+    } else {
+        Error::HtmlError
+    }
+}
+
 /// Ways to identify a user
 pub(crate) enum UserIdent {
     Id(Uuid),
     Username(String),
+    /// The `sub` claim of a linked OpenID Connect identity
+    OidcSubject(String),
 }
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct UserClaims {
     pub(crate) id: Uuid,
     pub(crate) username: String,
+    pub(crate) role: Role,
 }
 
 impl UserClaims {
-    #[allow(dead_code)]
     pub(crate) async fn fetch_user(&self, db: &PgPool) -> Result<UserRow, CommonError> {
         crud::fetch_user_from(db, &UserIdent::Id(self.id))
             .await?
@@ -75,20 +124,134 @@ impl<B: Send> FromRequest<B> for UserClaims {
             .await
             .expect("`Cookies` not found");
         // Used in case there is an error. It's async so we can't /easily/ do it in map_err
-        let OriginalUri(uri) = OriginalUri::from_request(req)
-            .await
-            .expect("It's Infallible");
-        let err_type = if uri.path().starts_with("/api") {
-            Error::ApiError
-        } else {
-            Error::HtmlError
-        };
+        let err_type = error_kind(req).await;
         let header = TypedHeader::<Authorization<Bearer>>::from_request(req)
             .await
             .ok();
 
-        let token = extract_token(cookies, header).map_err(err_type)?;
+        let claims = extract_token(cookies, header)
+            .and_then(|token| crate::utils::auth::validate_jwt(&token));
+        match claims.map_err(err_type) {
+            // Pages send the user to log in, and back to where they were going after that
+            Err(Error::HtmlError(_)) => Err(Error::LoginRequired {
+                next: requested_page(req).await,
+            }),
+            claims => claims,
+        }
+    }
+}
+
+/// The page that was requested, if the user can be sent back to it with a GET
+async fn requested_page<B: Send>(req: &mut RequestParts<B>) -> Option<String> {
+    if req.method() != Method::GET {
+        return None;
+    }
+    let OriginalUri(uri) = OriginalUri::from_request(req)
+        .await
+        .expect("It's Infallible");
+    uri.path_and_query().map(|path| path.to_string())
+}
+
+/// A permission that can be required by a handler through [`RequirePermission`]
+pub(crate) trait Permission {
+    fn is_granted(role: Role) -> bool;
+}
+
+/// Permission to create, change or delete data
+pub(crate) struct Write;
 
-        crate::utils::auth::validate_jwt(&token).map_err(err_type)
+impl Permission for Write {
+    fn is_granted(role: Role) -> bool {
+        role.can_write()
+    }
+}
+
+/// Permission to manage the app and other users
+pub(crate) struct Admin;
+
+impl Permission for Admin {
+    fn is_granted(role: Role) -> bool {
+        role.is_admin()
+    }
+}
+
+/// Like [`UserClaims`] but it rejects with `403 Forbidden` if the user doesn't have the permission `P`.
+///
+/// The role the user has now is checked, not the one in their jwt, which is kept for up to 2 hours after it changed.
+pub(crate) struct RequirePermission<P> {
+    pub(crate) user: UserClaims,
+    _permission: PhantomData<P>,
+}
+
+/// A user that is an admin
+pub(crate) type AdminUser = RequirePermission<Admin>;
+
+#[async_trait]
+impl<B: Send, P: Permission> FromRequest<B> for RequirePermission<P> {
+    type Rejection = Error;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let mut user = UserClaims::from_request(req).await?;
+        let err_type = error_kind(req).await;
+        let Extension(db) = Extension::<PgPool>::from_request(req)
+            .await
+            .expect("`PgPool` extension is missing");
+        user.role = user.fetch_user(&db).await.map_err(err_type)?.role;
+        if !P::is_granted(user.role) {
+            return Err(err_type(CommonError::Forbidden));
+        }
+
+        Ok(Self {
+            user,
+            _permission: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        axum::{http::StatusCode, routing::get, AddExtensionLayer, Router},
+        tower_cookies::CookieManagerLayer,
+    };
+
+    use super::*;
+    use crate::{crud, test_utils, utils::auth};
+
+    #[tokio::test]
+    async fn permissions_are_checked_with_the_role_users_have_now() {
+        test_utils::set_secret();
+        let db = test_utils::db().await;
+        let user = test_utils::user(&db, Role::Viewer).await;
+        let mut row = crud::fetch_user_from(&db, &UserIdent::Id(user))
+            .await
+            .unwrap()
+            .unwrap();
+        // Like the jwt of an admin that was made a viewer since
+        row.role = Role::Admin;
+        let token = auth::create_jwt(row).unwrap();
+
+        let app = Router::new()
+            .route(
+                "/api/admin",
+                get(|_: AdminUser| async { StatusCode::NO_CONTENT }),
+            )
+            .route(
+                "/api/write",
+                get(|_: RequirePermission<Write>| async { StatusCode::NO_CONTENT }),
+            )
+            .layer(CookieManagerLayer::new())
+            .layer(AddExtensionLayer::new(db));
+        let addr = test_utils::serve(app);
+        let request = |path: &str| {
+            reqwest::Client::new()
+                .get(format!("http://{}{}", addr, path))
+                .bearer_auth(&token)
+                .send()
+        };
+        let admin = request("/api/admin").await.unwrap();
+        assert_eq!(admin.status(), StatusCode::FORBIDDEN);
+        let write = request("/api/write").await.unwrap();
+        assert_eq!(write.status(), StatusCode::FORBIDDEN);
     }
 }
@@ -0,0 +1,153 @@
+use {
+    chrono::{DateTime, Utc},
+    sea_query::{self, Iden},
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    sqlx::types::{BigDecimal, Json},
+    strum::{AsRefStr, EnumString},
+    utoipa::ToSchema,
+};
+
+#[derive(Iden)]
+pub(crate) enum WebhookTable {
+    #[iden = "webhooks"]
+    Table,
+    Id,
+    UserId,
+    Url,
+    Secret,
+    Events,
+    BalanceThreshold,
+    Active,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+pub(crate) enum WebhookDeliveryTable {
+    #[iden = "webhook_deliveries"]
+    Table,
+    Id,
+    WebhookId,
+    Event,
+    Payload,
+    Status,
+    Attempts,
+    ResponseStatus,
+    Error,
+    NextAttemptAt,
+    CreatedAt,
+    DeliveredAt,
+}
+
+/// A webhook as the user sees it, the secret is only shown once when it's created
+#[derive(sqlx::FromRow, Debug, Serialize, ToSchema)]
+pub(crate) struct WebhookRow {
+    pub(crate) id: i32,
+    pub(crate) url: String,
+    #[schema(value_type = Vec<WebhookEvent>)]
+    pub(crate) events: Json<Vec<WebhookEvent>>,
+    /// `account.balance_below` is sent when the available money of an account goes below this
+    #[schema(value_type = Option<String>)]
+    pub(crate) balance_threshold: Option<BigDecimal>,
+    pub(crate) active: bool,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+/// What happened, the names are also what webhooks subscribe to
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString, ToSchema,
+)]
+pub(crate) enum WebhookEvent {
+    #[serde(rename = "account.created")]
+    #[strum(serialize = "account.created")]
+    AccountCreated,
+    #[serde(rename = "account.updated")]
+    #[strum(serialize = "account.updated")]
+    AccountUpdated,
+    #[serde(rename = "account.deleted")]
+    #[strum(serialize = "account.deleted")]
+    AccountDeleted,
+    /// The available money of the account went below the `balance_threshold` of the webhook
+    #[serde(rename = "account.balance_below")]
+    #[strum(serialize = "account.balance_below")]
+    AccountBalanceBelow,
+    #[serde(rename = "tag.created")]
+    #[strum(serialize = "tag.created")]
+    TagCreated,
+    #[serde(rename = "tag.updated")]
+    #[strum(serialize = "tag.updated")]
+    TagUpdated,
+    #[serde(rename = "tag.deleted")]
+    #[strum(serialize = "tag.deleted")]
+    TagDeleted,
+    /// The balance of the tag went over its limit
+    #[serde(rename = "tag.limit_exceeded")]
+    #[strum(serialize = "tag.limit_exceeded")]
+    TagLimitExceeded,
+    #[serde(rename = "goal.created")]
+    #[strum(serialize = "goal.created")]
+    GoalCreated,
+    #[serde(rename = "goal.updated")]
+    #[strum(serialize = "goal.updated")]
+    GoalUpdated,
+    #[serde(rename = "goal.deleted")]
+    #[strum(serialize = "goal.deleted")]
+    GoalDeleted,
+    /// The balance of the goal got to its target
+    #[serde(rename = "goal.reached")]
+    #[strum(serialize = "goal.reached")]
+    GoalReached,
+    #[serde(rename = "transaction.created")]
+    #[strum(serialize = "transaction.created")]
+    TransactionCreated,
+    #[serde(rename = "transaction.deleted")]
+    #[strum(serialize = "transaction.deleted")]
+    TransactionDeleted,
+}
+
+text_enum!(WebhookEvent);
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsRefStr, EnumString, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum DeliveryStatus {
+    /// Not sent yet, or it failed and will be retried
+    Pending,
+    Delivered,
+    /// Every attempt failed, it won't be retried
+    Failed,
+}
+
+text_enum!(DeliveryStatus);
+
+/// An entry of the delivery log of a webhook
+#[derive(sqlx::FromRow, Debug, Serialize, ToSchema)]
+pub(crate) struct WebhookDeliveryRow {
+    pub(crate) id: i64,
+    pub(crate) event: WebhookEvent,
+    /// The body that is sent
+    #[schema(value_type = Object)]
+    pub(crate) payload: Value,
+    pub(crate) status: DeliveryStatus,
+    pub(crate) attempts: i32,
+    /// The status code of the last response, if there was one
+    pub(crate) response_status: Option<i16>,
+    /// Why the last attempt failed
+    pub(crate) error: Option<String>,
+    pub(crate) next_attempt_at: DateTime<Utc>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) delivered_at: Option<DateTime<Utc>>,
+}
+
+/// A delivery that is due, with what's needed to send it
+#[derive(sqlx::FromRow, Debug)]
+pub(crate) struct PendingDelivery {
+    pub(crate) id: i64,
+    pub(crate) event: WebhookEvent,
+    pub(crate) payload: Value,
+    pub(crate) attempts: i32,
+    pub(crate) url: String,
+    pub(crate) secret: String,
+}
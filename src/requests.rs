@@ -1,26 +1,823 @@
+use std::str::FromStr;
+
 use {
+    chrono::{NaiveDate, Utc},
     serde::{Deserialize, Serialize},
     sqlx::types::BigDecimal,
+    utoipa::ToSchema,
+    uuid::Uuid,
+    validator::{Validate, ValidationError, ValidationErrors},
+};
+
+use crate::{
+    i18n::Locale,
+    models::{
+        account::AccountRow,
+        household::MemberRole,
+        settings::{Theme, UserSettings, Weekday},
+        tag::TagRow,
+        user::Role,
+        webhook::WebhookEvent,
+    },
+    utils::validation::{
+        currency_code, field_error, http_url, not_blank, not_negative, not_zero, positive,
+    },
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+const MAX_NAME_LEN: u64 = 100;
+const MAX_DESCRIPTION_LEN: u64 = 2000;
+const MAX_BATCH_LEN: usize = 500;
+const MAX_SYNC_LEN: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Validate)]
 pub(crate) struct LoginRequest {
+    #[validate(length(min = 1, message = "is required"))]
     pub(crate) username: String,
+    #[validate(length(min = 1, message = "is required"))]
     pub(crate) password: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// The login form of the pages
+#[derive(Debug, Deserialize)]
+pub(crate) struct LoginForm {
+    #[serde(flatten)]
+    pub(crate) credentials: LoginRequest,
+    /// The page to go to after logging in
+    #[serde(default)]
+    pub(crate) next: String,
+}
+
+/// The query of the login pages, `next` is the page to go to after logging in
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct NextQuery {
+    pub(crate) next: String,
+}
+
+/// What the OIDC provider sends back after a login attempt
+#[derive(Debug, Deserialize)]
+pub(crate) struct OidcCallbackQuery {
+    pub(crate) state: String,
+    pub(crate) code: Option<String>,
+    pub(crate) error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
+#[validate(schema(function = "validate_account_money"))]
 pub(crate) struct AccountCreateRequest {
+    #[validate(custom = "not_blank", length(max = "MAX_NAME_LEN"))]
+    #[schema(max_length = 100)]
     pub(crate) name: String,
+    #[validate(length(max = "MAX_DESCRIPTION_LEN"))]
+    #[schema(max_length = 2000)]
     pub(crate) description: Option<String>,
+    /// Required for normal accounts
+    #[schema(value_type = Option<String>)]
     pub(crate) starting_money: Option<BigDecimal>,
     pub(crate) is_adhoc: bool,
+    pub(crate) household_id: Option<i32>,
 }
 
-#[derive(Debug, Deserialize)]
+/// An account to create in `/api/v2`, `type` says which kind it is
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum TypedAccountCreate {
+    Normal {
+        #[schema(max_length = 100)]
+        name: String,
+        #[schema(max_length = 2000)]
+        description: Option<String>,
+        /// 0 if it's left out
+        #[schema(value_type = Option<String>)]
+        starting_money: Option<BigDecimal>,
+        household_id: Option<i32>,
+    },
+    Adhoc {
+        #[schema(max_length = 100)]
+        name: String,
+        household_id: Option<i32>,
+    },
+}
+
+impl From<TypedAccountCreate> for AccountCreateRequest {
+    fn from(account: TypedAccountCreate) -> Self {
+        match account {
+            TypedAccountCreate::Normal {
+                name,
+                description,
+                starting_money,
+                household_id,
+            } => Self {
+                name,
+                description,
+                starting_money: Some(starting_money.unwrap_or_default()),
+                is_adhoc: false,
+                household_id,
+            },
+            TypedAccountCreate::Adhoc { name, household_id } => Self {
+                name,
+                description: None,
+                starting_money: None,
+                is_adhoc: true,
+                household_id,
+            },
+        }
+    }
+}
+
+// The rules are the same as for `AccountCreateRequest`, and the derive doesn't work on enums
+impl Validate for TypedAccountCreate {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        AccountCreateRequest::from(self.clone()).validate()
+    }
+}
+
+/// The account form of the html pages, every field is sent as text
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct AccountForm {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) starting_money: String,
+    /// A checkbox, it's only sent when it's checked
+    pub(crate) is_adhoc: Option<String>,
+    /// The version that is being edited
+    pub(crate) version: Option<i32>,
+}
+
+impl AccountForm {
+    pub(crate) fn is_adhoc(&self) -> bool {
+        self.is_adhoc.is_some()
+    }
+
+    pub(crate) fn to_create(&self) -> Result<AccountCreateRequest, ValidationErrors> {
+        let starting_money = form_money(&self.starting_money);
+        let account = AccountCreateRequest {
+            name: self.name.clone(),
+            description: optional_text(&self.description),
+            // A placeholder when it's not a number, so the other fields still get validated
+            starting_money: starting_money
+                .clone()
+                .unwrap_or_else(|_| Some(BigDecimal::default())),
+            is_adhoc: self.is_adhoc(),
+            household_id: None,
+        };
+        with_number_errors(account.validate(), &[("starting_money", &starting_money)])?;
+        Ok(account)
+    }
+
+    pub(crate) fn to_update(&self) -> Result<AccountUpdate, ValidationErrors> {
+        let update = AccountUpdate {
+            name: self.name.clone(),
+            description: optional_text(&self.description),
+        };
+        update.validate()?;
+        Ok(update)
+    }
+}
+
+/// The tag form of the html pages, every field is sent as text
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct TagForm {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) limit: String,
+    pub(crate) starting_money: String,
+    /// The version that is being edited
+    pub(crate) version: Option<i32>,
+}
+
+impl TagForm {
+    pub(crate) fn to_create(&self) -> Result<TagCreate, ValidationErrors> {
+        let limit = form_money(&self.limit);
+        let starting_money = form_money(&self.starting_money);
+        let tag = TagCreate {
+            name: self.name.clone(),
+            description: optional_text(&self.description),
+            limit: limit.clone().unwrap_or_default(),
+            starting_money: starting_money.clone().unwrap_or_default(),
+            household_id: None,
+        };
+        with_number_errors(
+            tag.validate(),
+            &[("limit", &limit), ("starting_money", &starting_money)],
+        )?;
+        Ok(tag)
+    }
+
+    pub(crate) fn to_update(&self) -> Result<TagUpdate, ValidationErrors> {
+        let limit = form_money(&self.limit);
+        let update = TagUpdate {
+            name: self.name.clone(),
+            description: optional_text(&self.description),
+            limit: limit.clone().unwrap_or_default(),
+        };
+        with_number_errors(update.validate(), &[("limit", &limit)])?;
+        Ok(update)
+    }
+}
+
+/// One side of a transaction that is being created
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Party {
+    Account(i32),
+    /// A third party account with this name gets created for it
+    NewPayee(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TransactionCreate {
+    pub(crate) source: Party,
+    pub(crate) destination: Party,
+    pub(crate) amount: BigDecimal,
+    pub(crate) description: Option<String>,
+    pub(crate) happened_on: NaiveDate,
+    pub(crate) tag_ids: Vec<i32>,
+    /// Made up by the browser, a transaction with the same one is only saved once
+    pub(crate) client_id: Option<Uuid>,
+}
+
+/// The quick entry form of the register of an account, every field is sent as text
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct TransactionForm {
+    /// `in` when the money goes to the account, anything else is money going out of it
+    pub(crate) direction: String,
+    /// The name of the other account, a third party account is created if there is none with that name
+    pub(crate) payee: String,
+    pub(crate) amount: String,
+    /// Names of tags, separated by commas
+    pub(crate) tags: String,
+    pub(crate) description: String,
+    /// `YYYY-MM-DD`, today if it's left empty
+    pub(crate) happened_on: String,
+    /// Made up by the page so sending the form twice only saves it once, can be left empty
+    pub(crate) client_id: String,
+}
+
+impl TransactionForm {
+    pub(crate) fn is_incoming(&self) -> bool {
+        self.direction == "in"
+    }
+
+    /// The transaction for the register of `account_id`, `accounts` and `tags` are the ones the user can see
+    pub(crate) fn to_create(
+        &self,
+        account_id: i32,
+        accounts: &[AccountRow],
+        tags: &[TagRow],
+    ) -> Result<TransactionCreate, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        // A payee that doesn't exist yet becomes the name of a new account
+        let payee = self.payee.trim();
+        if let Err(err) = not_blank(payee) {
+            errors.add("payee", err);
+        } else if payee.chars().count() > MAX_NAME_LEN as usize {
+            errors.add(
+                "payee",
+                field_error("payee", "must be at most 100 characters long"),
+            );
+        }
+        let other = match accounts.iter().find(|a| a.name.eq_ignore_ascii_case(payee)) {
+            Some(account) if account.id == account_id => {
+                errors.add("payee", field_error("payee", "must be a different account"));
+                Party::Account(account.id)
+            }
+            Some(account) => Party::Account(account.id),
+            None => Party::NewPayee(payee.to_owned()),
+        };
+
+        let amount = match form_money(&self.amount) {
+            Ok(Some(amount)) if amount > BigDecimal::from(0) => amount,
+            Ok(_) => {
+                errors.add("amount", field_error("amount", "must be more than 0"));
+                BigDecimal::default()
+            }
+            Err(err) => {
+                errors.add("amount", err);
+                BigDecimal::default()
+            }
+        };
+
+        let happened_on = match self.happened_on.trim() {
+            "" => Utc::today().naive_utc(),
+            date => NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap_or_else(|_| {
+                errors.add(
+                    "happened_on",
+                    field_error("happened_on", "must be a date like 2022-01-31"),
+                );
+                Utc::today().naive_utc()
+            }),
+        };
+
+        let mut tag_ids = Vec::new();
+        let mut unknown = Vec::new();
+        for name in self
+            .tags
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+        {
+            match tags.iter().find(|t| t.name.eq_ignore_ascii_case(name)) {
+                Some(tag) if !tag_ids.contains(&tag.id) => tag_ids.push(tag.id),
+                Some(_) => {}
+                None => unknown.push(name),
+            }
+        }
+        if !unknown.is_empty() {
+            let mut err = ValidationError::new("unknown");
+            err.message = Some(format!("There are no tags named {}", unknown.join(", ")).into());
+            errors.add("tags", err);
+        }
+
+        let description = optional_text(&self.description);
+        if description.as_ref().map_or(0, |d| d.chars().count()) > MAX_DESCRIPTION_LEN as usize {
+            errors.add(
+                "description",
+                field_error("description", "must be at most 2000 characters long"),
+            );
+        }
+
+        let client_id = match self.client_id.trim() {
+            "" => None,
+            id => id.parse().map(Some).unwrap_or_else(|_| {
+                errors.add("client_id", field_error("client_id", "must be a UUID"));
+                None
+            }),
+        };
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let (source, destination) = match self.is_incoming() {
+            true => (other, Party::Account(account_id)),
+            false => (Party::Account(account_id), other),
+        };
+        Ok(TransactionCreate {
+            source,
+            destination,
+            amount,
+            description,
+            happened_on,
+            tag_ids,
+            client_id,
+        })
+    }
+}
+
+/// The settings form of the html pages, every field is sent as text
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct SettingsForm {
+    pub(crate) currency: String,
+    /// Empty for the language of the browser
+    pub(crate) locale: String,
+    pub(crate) first_day_of_week: String,
+    pub(crate) period_start_day: String,
+    /// Empty for no default account
+    pub(crate) default_account_id: String,
+    pub(crate) theme: String,
+}
+
+impl From<&UserSettings> for SettingsForm {
+    fn from(settings: &UserSettings) -> Self {
+        Self {
+            currency: settings.currency.clone().unwrap_or_default(),
+            locale: settings
+                .locale
+                .map(|locale| locale.code().to_owned())
+                .unwrap_or_default(),
+            first_day_of_week: settings.first_day_of_week.as_ref().to_owned(),
+            period_start_day: settings.period_start_day.to_string(),
+            default_account_id: settings
+                .default_account_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            theme: settings.theme.as_ref().to_owned(),
+        }
+    }
+}
+
+impl SettingsForm {
+    pub(crate) fn to_update(&self) -> Result<SettingsUpdate, ValidationErrors> {
+        let locale = match self.locale.as_str() {
+            "" => Ok(None),
+            code => form_choice(code).map(Some),
+        };
+        let first_day_of_week = form_choice(&self.first_day_of_week);
+        let period_start_day = self.period_start_day.trim().parse::<i16>().map_err(|_| {
+            let mut err = ValidationError::new("number");
+            err.message = Some("must be a number".into());
+            err
+        });
+        let default_account_id = match self.default_account_id.as_str() {
+            "" => Ok(None),
+            id => form_choice(id).map(Some),
+        };
+        let theme = form_choice(&self.theme);
+
+        // Placeholders for the fields that couldn't be parsed, so the other fields still get validated
+        let update = SettingsUpdate {
+            currency: optional_text(&self.currency).map(|code| code.trim().to_ascii_uppercase()),
+            locale: locale.clone().unwrap_or_default(),
+            first_day_of_week: first_day_of_week.clone().unwrap_or(Weekday::Monday),
+            period_start_day: period_start_day.clone().unwrap_or(1),
+            default_account_id: default_account_id.clone().unwrap_or_default(),
+            theme: theme.clone().unwrap_or(Theme::Light),
+        };
+
+        let mut errors = update.validate().err().unwrap_or_default();
+        let parsed = [
+            ("locale", locale.err()),
+            ("first_day_of_week", first_day_of_week.err()),
+            ("period_start_day", period_start_day.err()),
+            ("default_account_id", default_account_id.err()),
+            ("theme", theme.err()),
+        ];
+        for (field, err) in parsed {
+            if let Some(err) = err {
+                errors.add(field, err);
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(update),
+            false => Err(errors),
+        }
+    }
+}
+
+/// Empty text fields of forms are left out
+fn optional_text(value: &str) -> Option<String> {
+    match value.trim() {
+        "" => None,
+        _ => Some(value.to_owned()),
+    }
+}
+
+/// One of the options of a select of a form, anything else can only have been typed by hand
+fn form_choice<T: FromStr>(value: &str) -> Result<T, ValidationError> {
+    value.parse().map_err(|_| {
+        let mut err = ValidationError::new("choice");
+        err.message = Some("is not one of the choices".into());
+        err
+    })
+}
+
+/// An amount typed in a form, empty fields are `None`
+fn form_money(value: &str) -> Result<Option<BigDecimal>, ValidationError> {
+    match value.trim() {
+        "" => Ok(None),
+        value => value.parse().map(Some).map_err(|_| {
+            let mut err = ValidationError::new("number");
+            err.message = Some("must be a number".into());
+            err
+        }),
+    }
+}
+
+/// Adds the errors of the amounts that couldn't be parsed to the result of `validate`
+fn with_number_errors(
+    result: Result<(), ValidationErrors>,
+    amounts: &[(&'static str, &Result<Option<BigDecimal>, ValidationError>)],
+) -> Result<(), ValidationErrors> {
+    let mut errors = result.err().unwrap_or_default();
+    for (field, amount) in amounts {
+        if let Err(err) = amount {
+            errors.add(field, err.clone());
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+fn validate_account_money(account: &AccountCreateRequest) -> Result<(), ValidationError> {
+    if !account.is_adhoc && account.starting_money.is_none() {
+        return Err(field_error(
+            "starting_money",
+            "Normal account needs initial money",
+        ));
+    }
+    Ok(())
+}
+
+/// Replaces the fields of an account that can be changed
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct AccountUpdate {
+    #[validate(custom = "not_blank", length(max = "MAX_NAME_LEN"))]
+    #[schema(max_length = 100)]
+    pub(crate) name: String,
+    #[validate(length(max = "MAX_DESCRIPTION_LEN"))]
+    #[schema(max_length = 2000)]
+    pub(crate) description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
 pub(crate) struct TagCreate {
+    #[validate(custom = "not_blank", length(max = "MAX_NAME_LEN"))]
+    #[schema(max_length = 100)]
     pub(crate) name: String,
+    #[validate(length(max = "MAX_DESCRIPTION_LEN"))]
+    #[schema(max_length = 2000)]
     pub(crate) description: Option<String>,
+    #[validate(custom = "not_negative")]
+    #[schema(value_type = Option<String>)]
     pub(crate) limit: Option<BigDecimal>,
+    #[schema(value_type = Option<String>)]
     pub(crate) starting_money: Option<BigDecimal>,
+    pub(crate) household_id: Option<i32>,
+}
+
+/// Replaces the fields of a tag that can be changed
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct TagUpdate {
+    #[validate(custom = "not_blank", length(max = "MAX_NAME_LEN"))]
+    #[schema(max_length = 100)]
+    pub(crate) name: String,
+    #[validate(length(max = "MAX_DESCRIPTION_LEN"))]
+    #[schema(max_length = 2000)]
+    pub(crate) description: Option<String>,
+    #[validate(custom = "not_negative")]
+    #[schema(value_type = Option<String>)]
+    pub(crate) limit: Option<BigDecimal>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct GoalCreate {
+    #[validate(custom = "not_blank", length(max = "MAX_NAME_LEN"))]
+    #[schema(max_length = 100)]
+    pub(crate) name: String,
+    #[validate(length(max = "MAX_DESCRIPTION_LEN"))]
+    #[schema(max_length = 2000)]
+    pub(crate) description: Option<String>,
+    /// The normal account the money is set aside in
+    pub(crate) account_id: i32,
+    #[validate(custom = "positive")]
+    #[schema(value_type = String, example = "2000.00")]
+    pub(crate) target: BigDecimal,
+}
+
+/// Replaces the fields of a goal that can be changed
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct GoalUpdate {
+    #[validate(custom = "not_blank", length(max = "MAX_NAME_LEN"))]
+    #[schema(max_length = 100)]
+    pub(crate) name: String,
+    #[validate(length(max = "MAX_DESCRIPTION_LEN"))]
+    #[schema(max_length = 2000)]
+    pub(crate) description: Option<String>,
+    #[validate(custom = "positive")]
+    #[schema(value_type = String, example = "2000.00")]
+    pub(crate) target: BigDecimal,
+}
+
+/// Money moved between a goal and the available money of its account
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct GoalTransfer {
+    /// Set aside for the goal when positive, given back to the account when negative
+    #[validate(custom = "not_zero")]
+    #[schema(value_type = String, example = "50.00")]
+    pub(crate) amount: BigDecimal,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct UserCreateRequest {
+    #[validate(custom = "not_blank", length(max = "MAX_NAME_LEN"))]
+    #[schema(max_length = 100)]
+    pub(crate) username: String,
+    #[validate(length(min = 8))]
+    #[schema(min_length = 8)]
+    pub(crate) password: String,
+    pub(crate) role: Option<Role>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct RoleUpdateRequest {
+    pub(crate) role: Role,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct HouseholdCreate {
+    #[validate(custom = "not_blank", length(max = "MAX_NAME_LEN"))]
+    #[schema(max_length = 100)]
+    pub(crate) name: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct InviteCreate {
+    #[validate(custom = "not_blank")]
+    pub(crate) username: String,
+    pub(crate) role: Option<MemberRole>,
+}
+
+/// Share something with a household, or stop sharing it if `household_id` is None
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct ShareRequest {
+    pub(crate) household_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+#[validate(schema(function = "validate_balance_threshold"))]
+pub(crate) struct WebhookCreate {
+    /// Where the events are POSTed to
+    #[validate(custom = "http_url")]
+    pub(crate) url: String,
+    #[validate(length(min = 1, message = "must have at least one event"))]
+    pub(crate) events: Vec<WebhookEvent>,
+    /// Needed for `account.balance_below`, it's sent when the available money of an account goes below this
+    #[schema(value_type = Option<String>, example = "100.00")]
+    pub(crate) balance_threshold: Option<BigDecimal>,
+}
+
+fn validate_balance_threshold(webhook: &WebhookCreate) -> Result<(), ValidationError> {
+    let subscribed = webhook.events.contains(&WebhookEvent::AccountBalanceBelow);
+    match (subscribed, &webhook.balance_threshold) {
+        (true, None) => Err(field_error(
+            "balance_threshold",
+            "is needed for account.balance_below",
+        )),
+        (false, Some(_)) => Err(field_error(
+            "balance_threshold",
+            "is only used by account.balance_below",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Replaces the settings of the user
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct SettingsUpdate {
+    #[validate(custom = "currency_code")]
+    #[schema(example = "EUR")]
+    pub(crate) currency: Option<String>,
+    pub(crate) locale: Option<Locale>,
+    pub(crate) first_day_of_week: Weekday,
+    #[validate(range(min = 1, max = 31, message = "must be between 1 and 31"))]
+    #[schema(minimum = 1, maximum = 31)]
+    pub(crate) period_start_day: i16,
+    pub(crate) default_account_id: Option<i32>,
+    pub(crate) theme: Theme,
+}
+
+impl From<UserSettings> for SettingsUpdate {
+    fn from(settings: UserSettings) -> Self {
+        Self {
+            currency: settings.currency,
+            locale: settings.locale,
+            first_day_of_week: settings.first_day_of_week,
+            period_start_day: settings.period_start_day,
+            default_account_id: settings.default_account_id,
+            theme: settings.theme,
+        }
+    }
+}
+
+/// A line of the quick-add box, see [`crate::utils::quick_add`] for what it can have
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub(crate) struct QuickAddRequest {
+    #[validate(length(max = "MAX_DESCRIPTION_LEN"))]
+    #[schema(max_length = 2000, example = "12.50 coffee @Cash #food yesterday")]
+    pub(crate) line: String,
+    /// Made up by the page, the same line with the same id is only saved once
+    pub(crate) client_id: Option<Uuid>,
+}
+
+/// The quick-add page, the line is previewed with a GET and saved with a POST
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct QuickAddForm {
+    pub(crate) line: String,
+    /// Made up when the preview is shown, so saving it twice only saves it once
+    pub(crate) client_id: String,
+}
+
+/// Transactions that were entered while offline, each one is saved on its own
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct TransactionSyncRequest {
+    #[schema(min_items = 1, max_items = 100)]
+    pub(crate) entries: Vec<QueuedTransaction>,
+}
+
+impl Validate for TransactionSyncRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.entries.is_empty() || self.entries.len() > MAX_SYNC_LEN {
+            let mut err = ValidationError::new("length");
+            err.add_param("min".into(), &1);
+            err.add_param("max".into(), &MAX_SYNC_LEN);
+            err.message = Some(format!("must have between 1 and {} items", MAX_SYNC_LEN).into());
+            errors.add("entries", err);
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+/// A transaction as it was typed in the entry form of the register of `account_id`
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct QueuedTransaction {
+    /// Made up by the page when the entry was made, an entry that was already saved isn't saved again
+    pub(crate) client_id: Uuid,
+    pub(crate) account_id: i32,
+    /// `in` when the money goes to the account, anything else is money going out of it
+    #[serde(default)]
+    pub(crate) direction: String,
+    #[serde(default)]
+    #[schema(max_length = 100)]
+    pub(crate) payee: String,
+    #[serde(default)]
+    pub(crate) amount: String,
+    /// Names of tags, separated by commas
+    #[serde(default)]
+    pub(crate) tags: String,
+    #[serde(default)]
+    pub(crate) description: String,
+    /// `YYYY-MM-DD`, the day the entry is saved if it's left empty
+    #[serde(default)]
+    pub(crate) happened_on: String,
+}
+
+impl From<QueuedTransaction> for TransactionForm {
+    fn from(entry: QueuedTransaction) -> Self {
+        Self {
+            direction: entry.direction,
+            payee: entry.payee,
+            amount: entry.amount,
+            tags: entry.tags,
+            description: entry.description,
+            happened_on: entry.happened_on,
+            client_id: entry.client_id.to_string(),
+        }
+    }
+}
+
+/// Operations that are applied together, if any of them fails none of them are
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct BatchRequest {
+    #[schema(min_items = 1, max_items = 500)]
+    pub(crate) operations: Vec<BatchOperation>,
+}
+
+// Written by hand because the derived rules need the operations to be `Serialize`,
+// the `data` of each operation is validated when it's applied
+impl Validate for BatchRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.operations.is_empty() || self.operations.len() > MAX_BATCH_LEN {
+            let mut err = ValidationError::new("length");
+            err.add_param("min".into(), &1);
+            err.add_param("max".into(), &MAX_BATCH_LEN);
+            err.message = Some(format!("must have between 1 and {} items", MAX_BATCH_LEN).into());
+            errors.add("operations", err);
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+}
+
+/// A single operation of a batch, `version` is what would otherwise go in `If-Match`
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum BatchOperation {
+    CreateAccount {
+        data: AccountCreateRequest,
+    },
+    UpdateAccount {
+        id: i32,
+        version: i32,
+        data: AccountUpdate,
+    },
+    DeleteAccount {
+        id: i32,
+        version: i32,
+    },
+    CreateTag {
+        data: TagCreate,
+    },
+    UpdateTag {
+        id: i32,
+        version: i32,
+        data: TagUpdate,
+    },
+    DeleteTag {
+        id: i32,
+        version: i32,
+    },
+    /// Transactions don't have versions, the tags of the transaction become `tag_ids`
+    UpdateTransactionTags {
+        id: i32,
+        tag_ids: Vec<i32>,
+    },
+    DeleteTransaction {
+        id: i32,
+    },
 }
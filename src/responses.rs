@@ -0,0 +1,311 @@
+use {
+    axum::{
+        http::{header, HeaderValue, StatusCode},
+        response::{IntoResponse, Response},
+    },
+    chrono::NaiveDate,
+    serde::Serialize,
+    sqlx::types::BigDecimal,
+    utoipa::ToSchema,
+    uuid::Uuid,
+};
+
+use crate::{
+    error::FieldError,
+    models::{
+        account::{AccountRow, AdhocAccountRow, NormalAccountRow},
+        tag::TagRow,
+    },
+    requests::TransactionForm,
+    utils::etag,
+};
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct LoginResponse {
+    pub(crate) access_token: String,
+}
+
+/// The id of something that was just created
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct Created {
+    pub(crate) id: i32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct CreatedUser {
+    pub(crate) id: Uuid,
+}
+
+/// The secret is only ever shown here, it's needed to check the signature of the deliveries
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct CreatedWebhook {
+    pub(crate) id: i32,
+    pub(crate) secret: String,
+}
+
+/// What each operation of a batch did, in the same order as the operations
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct BatchResponse {
+    pub(crate) results: Vec<BatchItemResult>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct BatchItemResult {
+    pub(crate) id: i32,
+    /// The new version, missing for deletes
+    pub(crate) version: Option<i32>,
+}
+
+/// What happened to each synced entry, in the same order as the entries
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct TransactionSyncResponse {
+    pub(crate) results: Vec<SyncedEntry>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct SyncedEntry {
+    pub(crate) client_id: Uuid,
+    pub(crate) status: SyncStatus,
+    /// The saved transaction, missing for conflicts
+    pub(crate) transaction_id: Option<i32>,
+    /// Why a conflict couldn't be saved, it can be fixed and synced again
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SyncStatus {
+    Created,
+    /// It was saved by an earlier sync
+    Duplicate,
+    /// It's invalid now, like an amount that isn't a number or an account that was deleted
+    Conflict,
+}
+
+/// What a quick-add line would save, and what has to be made clear before it can be saved
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct QuickAddPreview {
+    /// The money comes in to the account, otherwise it goes out of it
+    pub(crate) incoming: bool,
+    #[schema(value_type = Option<String>)]
+    pub(crate) amount: Option<BigDecimal>,
+    /// The one after `@`, or the default account from the settings
+    pub(crate) account: Option<NamedRef>,
+    pub(crate) payee: Option<String>,
+    /// There is no account with the name of the payee, a third party account is created for it
+    pub(crate) new_payee: bool,
+    pub(crate) tags: Vec<NamedRef>,
+    pub(crate) description: Option<String>,
+    pub(crate) happened_on: NaiveDate,
+    /// Nothing is saved until there are none
+    pub(crate) ambiguities: Vec<Ambiguity>,
+}
+
+impl QuickAddPreview {
+    /// The line as it would be typed in the register of its account, if nothing is unclear
+    pub(crate) fn to_form(&self, client_id: Option<Uuid>) -> Option<(i32, TransactionForm)> {
+        if !self.ambiguities.is_empty() {
+            return None;
+        }
+        let account = self.account.as_ref()?;
+        let form = TransactionForm {
+            direction: if self.incoming { "in" } else { "out" }.to_owned(),
+            payee: self.payee.clone().unwrap_or_default(),
+            amount: self.amount.as_ref()?.to_string(),
+            tags: self
+                .tags
+                .iter()
+                .map(|tag| tag.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            description: self.description.clone().unwrap_or_default(),
+            happened_on: self.happened_on.format("%Y-%m-%d").to_string(),
+            client_id: client_id.map(|id| id.to_string()).unwrap_or_default(),
+        };
+        Some((account.id, form))
+    }
+}
+
+/// An account or tag a name in the line was matched to
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub(crate) struct NamedRef {
+    pub(crate) id: i32,
+    pub(crate) name: String,
+}
+
+/// A part of a quick-add line that is missing or could mean more than one thing
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct Ambiguity {
+    pub(crate) field: QuickAddField,
+    pub(crate) kind: AmbiguityKind,
+    /// What was typed for it, empty if it's missing
+    pub(crate) text: String,
+    /// The names it could be, for `several`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) candidates: Vec<String>,
+    pub(crate) message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum QuickAddField {
+    Amount,
+    Account,
+    Payee,
+    Tags,
+    HappenedOn,
+}
+
+impl QuickAddField {
+    /// The key of its label in the catalogs of `locales/`
+    pub(crate) fn text_key(self) -> &'static str {
+        match self {
+            Self::Amount => "common.amount",
+            Self::Account => "quick.account",
+            Self::Payee => "register.payee",
+            Self::Tags => "common.tags",
+            Self::HappenedOn => "common.date",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AmbiguityKind {
+    Missing,
+    /// Nothing has that name
+    Unknown,
+    /// More than one thing starts with that name
+    Several,
+    /// It's in the line more than once
+    Repeated,
+    /// It can't be used, like an amount of 0
+    Invalid,
+    /// It can be read more than one way, like `1,000`
+    Unclear,
+}
+
+impl AmbiguityKind {
+    /// The key of its explanation in the catalogs of `locales/`
+    pub(crate) fn text_key(self) -> &'static str {
+        match self {
+            Self::Missing => "quick.missing",
+            Self::Unknown => "quick.unknown",
+            Self::Several => "quick.several",
+            Self::Repeated => "quick.repeated",
+            Self::Invalid => "quick.invalid",
+            Self::Unclear => "quick.unclear",
+        }
+    }
+}
+
+/// How every response of `/api/v2` wraps its resources
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    AccountEnvelope = Envelope<AccountResource>,
+    AccountListEnvelope = Envelope<Vec<AccountResource>>,
+    TagEnvelope = Envelope<TagRow>,
+    TagListEnvelope = Envelope<Vec<TagRow>>,
+)]
+pub(crate) struct Envelope<T> {
+    pub(crate) data: T,
+}
+
+/// An account in `/api/v2`, every kind has the same fields except for the ones only `normal` accounts have
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum AccountResource {
+    Normal {
+        id: i32,
+        name: String,
+        description: Option<String>,
+        #[schema(value_type = String)]
+        available_money: BigDecimal,
+        #[schema(value_type = String)]
+        total_money: BigDecimal,
+        user_id: Uuid,
+        household_id: Option<i32>,
+        version: i32,
+    },
+    Adhoc {
+        id: i32,
+        name: String,
+        user_id: Uuid,
+        household_id: Option<i32>,
+        version: i32,
+    },
+}
+
+impl AccountResource {
+    pub(crate) fn version(&self) -> i32 {
+        match self {
+            Self::Normal { version, .. } | Self::Adhoc { version, .. } => *version,
+        }
+    }
+}
+
+impl From<AccountRow> for AccountResource {
+    fn from(account: AccountRow) -> Self {
+        match account.is_adhoc {
+            true => Self::Adhoc {
+                id: account.id,
+                name: account.name,
+                user_id: account.user_id,
+                household_id: account.household_id,
+                version: account.version,
+            },
+            false => Self::Normal {
+                id: account.id,
+                name: account.name,
+                description: account.description,
+                available_money: account.available_money.unwrap_or_default(),
+                total_money: account.total_money.unwrap_or_default(),
+                user_id: account.user_id,
+                household_id: account.household_id,
+                version: account.version,
+            },
+        }
+    }
+}
+
+/// The accounts of a user, which fields they have depends on the `account_type` that was asked for
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum AccountList {
+    Any(Vec<AccountRow>),
+    Adhoc(Vec<AdhocAccountRow>),
+    Normal(Vec<NormalAccountRow>),
+}
+
+/// A response with the `ETag` of the given version of the entity
+pub(crate) struct Tagged<T> {
+    pub(crate) version: i32,
+    pub(crate) body: T,
+}
+
+/// A `201 Created` response with the `Location` of the new resource
+pub(crate) struct CreatedAt<T> {
+    pub(crate) location: String,
+    pub(crate) body: T,
+}
+
+impl<T: IntoResponse> IntoResponse for CreatedAt<T> {
+    fn into_response(self) -> Response {
+        let mut res = self.body.into_response();
+        *res.status_mut() = StatusCode::CREATED;
+        if let Ok(location) = HeaderValue::from_str(&self.location) {
+            res.headers_mut().insert(header::LOCATION, location);
+        }
+        res
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for Tagged<T> {
+    fn into_response(self) -> Response {
+        let mut res = self.body.into_response();
+        res.headers_mut()
+            .insert(header::ETAG, etag::etag(self.version));
+        res
+    }
+}
@@ -0,0 +1,48 @@
+//! What the tests share: a migrated database, users to own the data and servers to talk to
+
+use std::{env, net::SocketAddr, sync::Once};
+
+use {axum::Router, dotenv::dotenv, sqlx::PgPool, uuid::Uuid};
+
+use crate::{crud, models::user::Role, requests::UserCreateRequest, utils};
+
+/// Sign the jwts of the tests with a key of their own
+pub(crate) fn set_secret() {
+    static SECRET: Once = Once::new();
+    SECRET.call_once(|| utils::set_secret("the secret of the tests"));
+}
+
+/// The database in `TEST_DATABASE_URL`, every test adds its own users so they can share it
+pub(crate) async fn db() -> PgPool {
+    dotenv().ok();
+    let url = env::var("TEST_DATABASE_URL")
+        .expect("Expected `TEST_DATABASE_URL` env variable, with a database for the tests");
+    let pool = PgPool::connect(&url)
+        .await
+        .expect("Failed to connect to the test db");
+    sqlx::migrate!()
+        .run(&pool)
+        .await
+        .expect("Failed to run migrations");
+    pool
+}
+
+/// A new user with a random name
+pub(crate) async fn user(db: &PgPool, role: Role) -> Uuid {
+    let user = UserCreateRequest {
+        username: format!("test-{}", Uuid::new_v4().to_simple()),
+        password: "a test password".into(),
+        role: Some(role),
+    };
+    crud::create_user(db, None, user, &Default::default())
+        .await
+        .expect("Failed to create a user")
+}
+
+/// Serve `app` on a free port of localhost, until the test is over
+pub(crate) fn serve(app: Router) -> SocketAddr {
+    let server = axum::Server::bind(&([127, 0, 0, 1], 0).into()).serve(app.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
@@ -7,6 +7,7 @@ use {
         },
         Argon2,
     },
+    axum::http::Uri,
     jwt_simple::prelude::*,
 };
 
@@ -23,9 +24,7 @@ pub(crate) fn hash_password(password: &str) -> Result<String, argon2::password_h
 pub(crate) fn validate_password(hash: &str, password: &str) -> bool {
     let argon2 = Argon2::default();
     let hash = PasswordHash::new(hash).expect("Invalid argon2 encoded password");
-    argon2
-        .verify_password(password.as_bytes(), &hash)
-        .map_or(false, |_| true)
+    argon2.verify_password(password.as_bytes(), &hash).is_ok()
 }
 
 pub(crate) fn create_jwt(user: user::UserRow) -> Result<String, jwt_simple::Error> {
@@ -33,6 +32,7 @@ pub(crate) fn create_jwt(user: user::UserRow) -> Result<String, jwt_simple::Erro
     let claims = UserClaims {
         id: user.id,
         username: user.username,
+        role: user.role,
     };
     let claims = Claims::with_custom_claims(claims, Duration::from_hours(2));
     key.authenticate(claims)
@@ -43,3 +43,32 @@ pub(crate) fn validate_jwt(token: &str) -> Result<UserClaims, CommonError> {
     let claims = key.verify_token::<UserClaims>(token, None)?;
     Ok(claims.custom)
 }
+
+/// `next` if it's a page of this site the user can be sent to after logging in.
+///
+/// Only paths are accepted so a link to the login page can't send the user to another site,
+/// `//host` and `/\host` are left out because browsers treat them as other sites too.
+pub(crate) fn local_redirect(next: &str) -> Option<&str> {
+    let is_local = next.starts_with('/')
+        && !next.starts_with("//")
+        && !next.contains('\\')
+        && !next.chars().any(char::is_control)
+        && !next.starts_with("/login");
+    match is_local && next.parse::<Uri>().is_ok() {
+        true => Some(next),
+        false => None,
+    }
+}
+
+/// The login page, it sends the user to `next` after they log in
+pub(crate) fn login_uri(next: Option<&str>) -> Uri {
+    let query = next
+        .and_then(local_redirect)
+        .and_then(|next| serde_urlencoded::to_string([("next", next)]).ok());
+    match query {
+        Some(query) => format!("/login?{}", query)
+            .parse()
+            .unwrap_or_else(|_| Uri::from_static("/login")),
+        None => Uri::from_static("/login"),
+    }
+}
@@ -0,0 +1,69 @@
+//! Cookies that the app sets, along with the attributes they get from the config
+
+use std::env;
+
+use {
+    anyhow::{bail, Context},
+    cookie::SameSite,
+    once_cell::sync::OnceCell,
+    tower_cookies::Cookie,
+};
+
+/// Name of the cookie holding the jwt of a logged in user
+pub(crate) const ACCESS_TOKEN: &str = "access_token";
+/// Name of the cookie holding the CSRF token, see [`crate::extract::Form`]
+pub(crate) const CSRF_TOKEN: &str = "csrf_token";
+/// Name of the cookie holding the language the user picked, see [`crate::i18n::Locale`]
+pub(crate) const LOCALE: &str = "locale";
+/// Name of the cookie holding the theme of the pages, read by `static/js/theme.js`
+pub(crate) const THEME: &str = "theme";
+
+static CONFIG: OnceCell<CookieConfig> = OnceCell::new();
+
+struct CookieConfig {
+    secure: bool,
+    same_site: SameSite,
+}
+
+/// Read the cookie attributes from the `COOKIE_SECURE` and `COOKIE_SAME_SITE` env variables.
+/// Cookies are not `Secure` and `SameSite=Lax` by default.
+///
+/// # Panic
+/// Will panic if it gets called more than once
+pub(crate) fn set_config_from_env() -> anyhow::Result<()> {
+    let secure = match env::var("COOKIE_SECURE") {
+        Ok(v) => v
+            .parse::<bool>()
+            .context("`COOKIE_SECURE` env variable is not valid")?,
+        Err(_) => false,
+    };
+    let same_site = match env::var("COOKIE_SAME_SITE").as_deref() {
+        Ok("strict") => SameSite::Strict,
+        Ok("lax") | Err(_) => SameSite::Lax,
+        Ok("none") => SameSite::None,
+        Ok(_) => bail!("`COOKIE_SAME_SITE` must be one of `strict`, `lax` or `none`"),
+    };
+
+    if CONFIG.set(CookieConfig { secure, same_site }).is_err() {
+        panic!("Cookie config has been set before");
+    }
+    Ok(())
+}
+
+/// A cookie that is only meant for the server, with the configured attributes
+pub(crate) fn private_cookie(name: &'static str, value: String) -> Cookie<'static> {
+    let config = CONFIG.get().expect("Cookie config has not been set");
+    Cookie::build(name, value)
+        .path("/")
+        .http_only(true)
+        .secure(config.secure)
+        .same_site(config.same_site)
+        .finish()
+}
+
+/// A cookie that the scripts of the pages can read too, with the configured attributes
+pub(crate) fn script_cookie(name: &'static str, value: String) -> Cookie<'static> {
+    let mut cookie = private_cookie(name, value);
+    cookie.set_http_only(false);
+    cookie
+}
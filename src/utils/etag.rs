@@ -0,0 +1,53 @@
+//! Entity tags for optimistic concurrency, entities with a `version` column use it as their `ETag`
+
+use axum::http::HeaderValue;
+
+/// The strong `ETag` of the given version of an entity
+pub(crate) fn etag(version: i32) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{}\"", version)).expect("A number is a valid header value")
+}
+
+/// The tags of an `If-Match` or `If-None-Match` header
+#[derive(Debug, Clone)]
+pub(crate) enum EntityTags {
+    /// `*`, matches any version
+    Any,
+    Tags(Vec<String>),
+}
+
+impl EntityTags {
+    pub(crate) fn parse(header: &str) -> Self {
+        if header.trim() == "*" {
+            return Self::Any;
+        }
+        let tags = header
+            .split(',')
+            .map(|tag| tag.trim().to_owned())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        Self::Tags(tags)
+    }
+
+    /// Only matches the given version, for when it comes from somewhere other than a header
+    pub(crate) fn version(version: i32) -> Self {
+        Self::Tags(vec![format!("\"{}\"", version)])
+    }
+
+    /// Strong comparison, as used for `If-Match`
+    pub(crate) fn matches_version(&self, version: i32) -> bool {
+        let etag = etag(version);
+        match self {
+            Self::Any => true,
+            Self::Tags(tags) => tags.iter().any(|tag| tag.as_bytes() == etag.as_bytes()),
+        }
+    }
+
+    /// Weak comparison, as used for `If-None-Match`
+    pub(crate) fn matches_weak(&self, etag: &str) -> bool {
+        let opaque = |tag: &str| tag.trim_start_matches("W/").to_owned();
+        match self {
+            Self::Any => true,
+            Self::Tags(tags) => tags.iter().any(|tag| opaque(tag) == opaque(etag)),
+        }
+    }
+}
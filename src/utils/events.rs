@@ -0,0 +1,81 @@
+//! Changes to the data, as they are sent to the open event streams.
+//!
+//! Changes are sent with postgres `NOTIFY`, every instance of the server listens for them and passes them on to its
+//! own streams through a broadcast channel, so a stream gets the changes made through any instance.
+
+use std::{sync::Arc, time::Duration};
+
+use {
+    once_cell::sync::OnceCell,
+    serde::Deserialize,
+    serde_json::Value,
+    sqlx::{postgres::PgListener, PgPool},
+    tokio::sync::broadcast,
+    uuid::Uuid,
+};
+
+use crate::models::webhook::WebhookEvent;
+
+/// The postgres channel changes are sent to
+pub(crate) const CHANNEL: &str = "budgetman_events";
+
+/// How many changes a slow stream can fall behind before it misses some
+const CAPACITY: usize = 256;
+
+static SENDER: OnceCell<broadcast::Sender<Arc<Change>>> = OnceCell::new();
+
+/// A change to an entity, as published by `crud::events::publish`
+#[derive(Debug, Deserialize)]
+pub(crate) struct Change {
+    /// Who can see the entity
+    pub(crate) users: Vec<Uuid>,
+    pub(crate) event: WebhookEvent,
+    pub(crate) data: Value,
+}
+
+/// Listen for changes and pass them on to the streams, runs until the process exits
+///
+/// # Panic
+/// Will panic if it gets called more than once
+pub(crate) fn spawn_listener(db: PgPool) {
+    let (sender, _) = broadcast::channel(CAPACITY);
+    SENDER
+        .set(sender.clone())
+        .expect("The listener has been started before");
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = listen(&db, &sender).await {
+                tracing::error!("Stopped listening for changes: {:?}", e);
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    });
+}
+
+async fn listen(db: &PgPool, sender: &broadcast::Sender<Arc<Change>>) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect_with(db).await?;
+    listener.listen(CHANNEL).await?;
+
+    loop {
+        let notification = listener.recv().await?;
+        match serde_json::from_str::<Change>(notification.payload()) {
+            // It only fails when no stream is open
+            Ok(change) => {
+                let _ = sender.send(Arc::new(change));
+            }
+            Err(e) => tracing::error!("Got a change that is not valid: {:?}", e),
+        }
+    }
+}
+
+/// Get every change from now on, the ones the user can't see have to be filtered out
+///
+/// # Panic
+/// Will panic if the listener hasn't been started
+pub(crate) fn subscribe() -> broadcast::Receiver<Arc<Change>> {
+    SENDER
+        .get()
+        .expect("The listener has not been started")
+        .subscribe()
+}
@@ -1,4 +1,11 @@
 pub(crate) mod auth;
+pub(crate) mod cookies;
+pub(crate) mod etag;
+pub(crate) mod events;
+pub(crate) mod oidc;
+pub(crate) mod quick_add;
+pub(crate) mod validation;
+pub(crate) mod webhooks;
 
 use {jwt_simple::prelude::HS256Key, once_cell::sync::OnceCell};
 
@@ -21,8 +28,10 @@ pub(crate) fn get_secret() -> &'static HS256Key {
     KEY.get().expect("KEY has not been set")
 }
 
+/// If the query failed because of a unique constraint
 pub(crate) fn err_is_failed_constraint(err: &sqlx::Error) -> bool {
     err.as_database_error()
-        .map(|e| e.message().starts_with("UNIQUE"))
+        .and_then(|e| e.code())
+        .map(|code| code == "23505")
         .unwrap_or(false)
 }
@@ -0,0 +1,474 @@
+//! Login through an OpenID Connect provider using the authorization code flow
+
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration as StdDuration, Instant},
+};
+
+use {
+    anyhow::Context, axum::http::StatusCode, jwt_simple::prelude::*, once_cell::sync::OnceCell,
+    reqwest::Url,
+};
+
+use crate::CommonError;
+
+static CONFIG: OnceCell<OidcConfig> = OnceCell::new();
+
+/// How long the discovery document and the keys of the provider are used before they are fetched again
+const PROVIDER_CACHE_TTL: StdDuration = StdDuration::from_secs(60 * 60);
+
+pub(crate) struct OidcConfig {
+    /// Url of the provider, `/.well-known/openid-configuration` is appended to it for discovery
+    pub(crate) issuer: String,
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+    /// Where the provider redirects the user to after a login, must point to `/login/oidc/callback`
+    pub(crate) redirect_url: String,
+    /// Create a user the first time someone logs in through the provider
+    pub(crate) auto_provision: bool,
+    /// What was fetched from the provider last, and when
+    pub(crate) provider: Mutex<Option<(Instant, Arc<Provider>)>>,
+}
+
+/// Read the OIDC config from the env. OIDC login stays disabled if `OIDC_ISSUER` isn't set.
+///
+/// # Panic
+/// Will panic if it gets called more than once
+pub(crate) fn set_config_from_env() -> anyhow::Result<()> {
+    let issuer = match env::var("OIDC_ISSUER") {
+        Ok(issuer) => issuer,
+        Err(_) => return Ok(()),
+    };
+    let config = OidcConfig {
+        issuer: issuer.trim_end_matches('/').to_string(),
+        client_id: env::var("OIDC_CLIENT_ID").context("Expected `OIDC_CLIENT_ID` env variable")?,
+        client_secret: env::var("OIDC_CLIENT_SECRET")
+            .context("Expected `OIDC_CLIENT_SECRET` env variable")?,
+        redirect_url: env::var("OIDC_REDIRECT_URL")
+            .context("Expected `OIDC_REDIRECT_URL` env variable")?,
+        auto_provision: env::var("OIDC_AUTO_PROVISION")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false),
+        provider: Mutex::new(None),
+    };
+
+    if CONFIG.set(config).is_err() {
+        panic!("OIDC config has been set before");
+    }
+    Ok(())
+}
+
+/// Get the OIDC config, None if OIDC login is disabled
+pub(crate) fn config() -> Option<&'static OidcConfig> {
+    CONFIG.get()
+}
+
+/// The discovery document and the keys of the provider
+pub(crate) struct Provider {
+    metadata: ProviderMetadata,
+    jwks: Jwks,
+}
+
+/// The parts of the provider metadata we care about
+#[derive(Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+impl Jwks {
+    /// The RSA key with the id, or any RSA key if there's no id
+    fn key(&self, key_id: Option<&str>) -> Option<&Jwk> {
+        self.keys
+            .iter()
+            .filter(|k| k.kty == "RSA")
+            .find(|k| key_id.is_none() || k.kid.as_deref() == key_id)
+    }
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+/// Claims of the id token on top of the standard ones
+#[derive(Serialize, Deserialize)]
+struct IdTokenClaims {
+    preferred_username: Option<String>,
+    email: Option<String>,
+}
+
+/// Where to send the user to log in, along with the values we need to remember until they come back
+pub(crate) struct AuthRequest {
+    pub(crate) url: String,
+    pub(crate) state: String,
+    pub(crate) nonce: String,
+}
+
+/// A user as identified by the provider
+pub(crate) struct OidcIdentity {
+    /// The `sub` claim, stable and unique within the provider
+    pub(crate) subject: String,
+    /// A name suggested by the provider, used when creating a new user
+    pub(crate) username: Option<String>,
+}
+
+fn provider_error(context: &str, err: impl std::fmt::Display) -> CommonError {
+    tracing::error!("OIDC {}: {}", context, err);
+    (
+        StatusCode::BAD_GATEWAY,
+        "Failed to communicate with the identity provider",
+    )
+        .into()
+}
+
+/// The discovery document and keys of the provider, fetched again once they are old or when `refresh` is set,
+/// e.g. because the provider signed a token with a new key
+async fn provider(config: &OidcConfig, refresh: bool) -> Result<Arc<Provider>, CommonError> {
+    if !refresh {
+        let cached = config.provider.lock().unwrap();
+        if let Some((fetched_at, provider)) = &*cached {
+            if fetched_at.elapsed() < PROVIDER_CACHE_TTL {
+                return Ok(provider.clone());
+            }
+        }
+    }
+
+    let url = format!("{}/.well-known/openid-configuration", config.issuer);
+    let metadata: ProviderMetadata = reqwest::get(&url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| provider_error("discovery failed", e))?
+        .json()
+        .await
+        .map_err(|e| provider_error("invalid discovery document", e))?;
+    // The document has to be about the provider that was configured, otherwise its keys can't be trusted either
+    if metadata.issuer.trim_end_matches('/') != config.issuer {
+        return Err(provider_error(
+            "discovery failed",
+            format!("the document is about the issuer {}", metadata.issuer),
+        ));
+    }
+    let jwks: Jwks = reqwest::get(&metadata.jwks_uri)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| provider_error("jwks request failed", e))?
+        .json()
+        .await
+        .map_err(|e| provider_error("invalid jwks", e))?;
+
+    let provider = Arc::new(Provider { metadata, jwks });
+    *config.provider.lock().unwrap() = Some((Instant::now(), provider.clone()));
+    Ok(provider)
+}
+
+/// Build the url of the provider's login page
+pub(crate) async fn authorization_request(config: &OidcConfig) -> Result<AuthRequest, CommonError> {
+    let provider = provider(config, false).await?;
+
+    let state = uuid::Uuid::new_v4().to_simple().to_string();
+    let nonce = uuid::Uuid::new_v4().to_simple().to_string();
+    let url = Url::parse_with_params(
+        &provider.metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("scope", "openid profile email"),
+            ("client_id", &config.client_id),
+            ("redirect_uri", &config.redirect_url),
+            ("state", &state),
+            ("nonce", &nonce),
+        ],
+    )
+    .map_err(|e| provider_error("invalid authorization endpoint", e))?;
+
+    Ok(AuthRequest {
+        url: url.to_string(),
+        state,
+        nonce,
+    })
+}
+
+/// Exchange the code the provider gave us for an id token and validate it
+pub(crate) async fn exchange_code(
+    config: &OidcConfig,
+    code: &str,
+    nonce: &str,
+) -> Result<OidcIdentity, CommonError> {
+    let mut provider = provider(config, false).await?;
+
+    let tokens: TokenResponse = reqwest::Client::new()
+        .post(&provider.metadata.token_endpoint)
+        .basic_auth(&config.client_id, Some(&config.client_secret))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &config.redirect_url),
+            ("client_id", &config.client_id),
+        ])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| provider_error("token request failed", e))?
+        .json()
+        .await
+        .map_err(|e| provider_error("invalid token response", e))?;
+
+    let key_id = Token::decode_metadata(&tokens.id_token)?
+        .key_id()
+        .map(String::from);
+    if provider.jwks.key(key_id.as_deref()).is_none() {
+        // The provider may have started using a new key since the keys were fetched
+        provider = self::provider(config, true).await?;
+    }
+    let jwk = provider
+        .jwks
+        .key(key_id.as_deref())
+        .ok_or_else(|| provider_error("no usable key", "no RSA key matches the id token"))?;
+    let (n, e) = match (&jwk.n, &jwk.e) {
+        (Some(n), Some(e)) => (
+            Base64UrlSafeNoPadding::decode_to_vec(n, None)
+                .map_err(|e| provider_error("invalid key", e))?,
+            Base64UrlSafeNoPadding::decode_to_vec(e, None)
+                .map_err(|e| provider_error("invalid key", e))?,
+        ),
+        _ => {
+            return Err(provider_error(
+                "no usable key",
+                "RSA key without components",
+            ))
+        }
+    };
+    let key = RS256PublicKey::from_components(&n, &e)?;
+
+    let options = VerificationOptions {
+        required_nonce: Some(nonce.to_string()),
+        // With and without the trailing slash, the provider decides how it's written
+        allowed_issuers: Some(HashSet::from_strings(&[
+            &config.issuer,
+            &format!("{}/", config.issuer),
+        ])),
+        allowed_audiences: Some(HashSet::from_strings(&[&config.client_id])),
+        ..Default::default()
+    };
+    let claims = key.verify_token::<IdTokenClaims>(&tokens.id_token, Some(options))?;
+    let subject = claims.subject.ok_or(CommonError::MissingCredentials(
+        "Id token is missing the subject",
+    ))?;
+
+    Ok(OidcIdentity {
+        subject,
+        username: claims.custom.preferred_username.or(claims.custom.email),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use {
+        axum::{
+            extract::{Extension, Form, TypedHeader},
+            routing::{get, post},
+            AddExtensionLayer, Json, Router,
+        },
+        headers::Host,
+        serde_json::{json, Value},
+        uuid::Uuid,
+    };
+
+    use super::*;
+    use crate::{crud, models::user::Role, test_utils, utils::auth::validate_jwt};
+
+    const CLIENT_ID: &str = "budgetman-tests";
+
+    /// A provider that logs in whoever the code names, codes are `<subject> <nonce>`
+    struct MockIssuer {
+        key: RS256KeyPair,
+        /// Put in the `iss` of the id tokens instead of its own url
+        token_issuer: Option<String>,
+        discoveries: AtomicUsize,
+    }
+
+    #[derive(Deserialize)]
+    struct TokenForm {
+        code: String,
+    }
+
+    fn own_url(host: &Host) -> String {
+        format!("http://{}", host)
+    }
+
+    async fn discovery(
+        Extension(mock): Extension<Arc<MockIssuer>>,
+        TypedHeader(host): TypedHeader<Host>,
+    ) -> Json<Value> {
+        mock.discoveries.fetch_add(1, Ordering::SeqCst);
+        let issuer = own_url(&host);
+        Json(json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "jwks_uri": format!("{}/jwks", issuer),
+        }))
+    }
+
+    async fn jwks(Extension(mock): Extension<Arc<MockIssuer>>) -> Json<Value> {
+        let key = mock.key.public_key().to_components();
+        Json(json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": "test",
+                "n": Base64UrlSafeNoPadding::encode_to_string(&key.n).unwrap(),
+                "e": Base64UrlSafeNoPadding::encode_to_string(&key.e).unwrap(),
+            }],
+        }))
+    }
+
+    async fn token(
+        Extension(mock): Extension<Arc<MockIssuer>>,
+        TypedHeader(host): TypedHeader<Host>,
+        Form(form): Form<TokenForm>,
+    ) -> Json<Value> {
+        let (subject, nonce) = form.code.split_once(' ').unwrap();
+        let custom = IdTokenClaims {
+            preferred_username: Some(format!("user-{}", subject)),
+            email: None,
+        };
+        let claims = Claims::with_custom_claims(custom, Duration::from_mins(5))
+            .with_issuer(mock.token_issuer.clone().unwrap_or_else(|| own_url(&host)))
+            .with_audience(CLIENT_ID)
+            .with_subject(subject)
+            .with_nonce(nonce);
+        Json(json!({
+            "id_token": mock.key.sign(claims).unwrap(),
+            "token_type": "Bearer",
+        }))
+    }
+
+    fn mock_issuer(token_issuer: Option<&str>) -> (OidcConfig, Arc<MockIssuer>) {
+        let mock = Arc::new(MockIssuer {
+            key: RS256KeyPair::generate(2048).unwrap().with_key_id("test"),
+            token_issuer: token_issuer.map(String::from),
+            discoveries: AtomicUsize::new(0),
+        });
+        let addr = test_utils::serve(
+            Router::new()
+                .route("/.well-known/openid-configuration", get(discovery))
+                .route("/jwks", get(jwks))
+                .route("/token", post(token))
+                .layer(AddExtensionLayer::new(mock.clone())),
+        );
+        let config = OidcConfig {
+            issuer: format!("http://{}", addr),
+            client_id: CLIENT_ID.into(),
+            client_secret: "the client secret".into(),
+            redirect_url: "http://localhost/login/oidc/callback".into(),
+            auto_provision: false,
+            provider: Mutex::new(None),
+        };
+        (config, mock)
+    }
+
+    /// Log in at the provider as `subject`
+    async fn log_in_as(config: &OidcConfig, subject: &str) -> Result<OidcIdentity, CommonError> {
+        exchange_code(config, &format!("{} the-nonce", subject), "the-nonce").await
+    }
+
+    #[tokio::test]
+    async fn new_identities_get_a_user_with_auto_provisioning() {
+        test_utils::set_secret();
+        let db = test_utils::db().await;
+        let client = Default::default();
+        let (config, mock) = mock_issuer(None);
+        let subject = Uuid::new_v4().to_string();
+
+        let identity = log_in_as(&config, &subject).await.unwrap();
+        assert_eq!(identity.subject, subject);
+        let jwt = crud::login_oidc(&db, identity, None, true, &client)
+            .await
+            .unwrap();
+        let user = validate_jwt(&jwt).unwrap();
+        assert_eq!(user.username, format!("user-{}", subject));
+        assert_eq!(user.role, Role::User);
+
+        // The second time it's the same user, and the provider isn't asked for its discovery document again
+        let identity = log_in_as(&config, &subject).await.unwrap();
+        let jwt = crud::login_oidc(&db, identity, None, true, &client)
+            .await
+            .unwrap();
+        assert_eq!(validate_jwt(&jwt).unwrap().id, user.id);
+        assert_eq!(mock.discoveries.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn identities_are_linked_to_the_logged_in_user_once() {
+        test_utils::set_secret();
+        let db = test_utils::db().await;
+        let client = Default::default();
+        let (config, _) = mock_issuer(None);
+        let user_id = test_utils::user(&db, Role::User).await;
+        let subject = Uuid::new_v4().to_string();
+
+        let identity = log_in_as(&config, &subject).await.unwrap();
+        let jwt = crud::login_oidc(&db, identity, Some(user_id), false, &client)
+            .await
+            .unwrap();
+        assert_eq!(validate_jwt(&jwt).unwrap().id, user_id);
+
+        // Without being logged in, the linked identity is enough
+        let identity = log_in_as(&config, &subject).await.unwrap();
+        let jwt = crud::login_oidc(&db, identity, None, false, &client)
+            .await
+            .unwrap();
+        assert_eq!(validate_jwt(&jwt).unwrap().id, user_id);
+
+        // Another identity can't take the user over
+        let other = log_in_as(&config, &Uuid::new_v4().to_string())
+            .await
+            .unwrap();
+        let res = crud::login_oidc(&db, other, Some(user_id), false, &client).await;
+        assert!(
+            matches!(res, Err(CommonError::Other { code, .. }) if code == StatusCode::CONFLICT)
+        );
+    }
+
+    #[tokio::test]
+    async fn new_identities_are_refused_without_auto_provisioning() {
+        test_utils::set_secret();
+        let db = test_utils::db().await;
+        let (config, _) = mock_issuer(None);
+
+        let identity = log_in_as(&config, &Uuid::new_v4().to_string())
+            .await
+            .unwrap();
+        let res = crud::login_oidc(&db, identity, None, false, &Default::default()).await;
+        assert!(matches!(res, Err(CommonError::WrongCredentials)));
+    }
+
+    #[tokio::test]
+    async fn tokens_are_refused_from_another_issuer_or_for_another_login() {
+        let (config, _) = mock_issuer(Some("https://id.example.com"));
+        let res = log_in_as(&config, "someone").await;
+        assert!(matches!(res, Err(CommonError::InvalidCredentials(_))));
+
+        let (config, _) = mock_issuer(None);
+        let res = exchange_code(&config, "someone the-nonce", "another-nonce").await;
+        assert!(matches!(res, Err(CommonError::InvalidCredentials(_))));
+    }
+}
@@ -0,0 +1,202 @@
+//! Reads the lines of the quick-add box, like `12.50 coffee @Cash #food yesterday`.
+//!
+//! - A number is the amount, with a `+` in front the money comes in to the account. `1,000` and `1.000` are unclear,
+//!   they could be a thousand or one with three decimals
+//! - `@name` is the account, `#name` a tag, names with spaces go in quotes like `@"Credit card"`
+//! - `today`, `yesterday`, a weekday or `YYYY-MM-DD` is the date
+//! - Text in quotes is the description
+//! - Every other word is part of the payee
+
+use {
+    bigdecimal::BigDecimal,
+    chrono::{Datelike, Duration, NaiveDate},
+};
+
+use crate::models::settings::Weekday;
+
+/// The parts of a line, before they are matched to the accounts and tags of the user
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct QuickAddLine {
+    /// The amount had a `+` in front of it
+    pub(crate) incoming: bool,
+    /// Every amount in the line, there should be exactly one
+    pub(crate) amounts: Vec<BigDecimal>,
+    pub(crate) unclear_amounts: Vec<UnclearAmount>,
+    pub(crate) accounts: Vec<String>,
+    pub(crate) tags: Vec<String>,
+    pub(crate) dates: Vec<NaiveDate>,
+    pub(crate) description: Option<String>,
+    pub(crate) payee: String,
+}
+
+/// An amount like `1,000`, with three digits after the only separator
+#[derive(Debug, PartialEq)]
+pub(crate) struct UnclearAmount {
+    pub(crate) typed: String,
+    /// What it is if the separator groups the thousands
+    pub(crate) thousands: BigDecimal,
+    /// What it is if the separator is for the decimals
+    pub(crate) decimals: BigDecimal,
+}
+
+/// Split `line` into its parts, `today` is what the words for days are counted from
+pub(crate) fn parse(line: &str, today: NaiveDate) -> QuickAddLine {
+    let mut parsed = QuickAddLine::default();
+    let mut payee = Vec::new();
+    let mut description = Vec::new();
+
+    for word in words(line) {
+        if let Some(name) = word.strip_prefix('@') {
+            // A lone `@` or `#` is a name that hasn't been typed yet
+            parsed
+                .accounts
+                .extend(Some(unquote(name)).filter(|n| !n.is_empty()));
+        } else if let Some(name) = word.strip_prefix('#') {
+            parsed
+                .tags
+                .extend(Some(unquote(name)).filter(|n| !n.is_empty()));
+        } else if word.starts_with('"') {
+            description.push(unquote(&word));
+        } else if let Some(date) = date(&word, today) {
+            parsed.dates.push(date);
+        } else if let Some((incoming, amount)) = amount(&word) {
+            parsed.incoming |= incoming;
+            match amount {
+                Ok(amount) => parsed.amounts.push(amount),
+                Err(unclear) => parsed.unclear_amounts.push(unclear),
+            }
+        } else {
+            payee.push(word);
+        }
+    }
+
+    parsed.payee = payee.join(" ");
+    parsed.description = Some(description.join(" ")).filter(|d| !d.is_empty());
+    parsed
+}
+
+/// The words of the line, a quoted part is one word even if it has spaces
+fn words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.push(c);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn unquote(word: &str) -> String {
+    word.replace('"', "").trim().to_owned()
+}
+
+/// A number, both `12.50` and `12,50` are twelve and a half
+fn amount(word: &str) -> Option<(bool, Result<BigDecimal, UnclearAmount>)> {
+    let (incoming, number) = match word.strip_prefix('+') {
+        Some(number) => (true, number),
+        None => (false, word),
+    };
+    // Only digits and a separator, `parse` would take `1e3` too
+    let digits = number.strip_prefix('-').unwrap_or(number);
+    if !digits.contains(|c: char| c.is_ascii_digit())
+        || !digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+        || digits.matches(['.', ',']).count() > 1
+    {
+        return None;
+    }
+
+    let decimals: BigDecimal = number.replace(',', ".").parse().ok()?;
+    match digits.split_once(['.', ',']) {
+        Some((whole, fraction))
+            if (1..=3).contains(&whole.len()) && !whole.starts_with('0') && fraction.len() == 3 =>
+        {
+            let unclear = UnclearAmount {
+                typed: word.to_owned(),
+                thousands: number.replace(['.', ','], "").parse().ok()?,
+                decimals,
+            };
+            Some((incoming, Err(unclear)))
+        }
+        _ => Some((incoming, Ok(decimals))),
+    }
+}
+
+fn date(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match word.to_lowercase().as_str() {
+        "today" => Some(today),
+        "yesterday" => Some(today - Duration::days(1)),
+        word => match word.parse::<Weekday>() {
+            // The last one, today if it's that day
+            Ok(weekday) => {
+                let back = today.weekday().num_days_from_monday() as i64 - weekday as i64;
+                Some(today - Duration::days(back.rem_euclid(7)))
+            }
+            Err(_) => NaiveDate::parse_from_str(word, "%Y-%m-%d").ok(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amounts(line: &str) -> (Vec<String>, Vec<String>) {
+        let parsed = parse(line, NaiveDate::from_ymd(2022, 2, 18));
+        (
+            parsed.amounts.iter().map(ToString::to_string).collect(),
+            parsed
+                .unclear_amounts
+                .into_iter()
+                .map(|a| a.typed)
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn amounts_have_a_comma_or_a_point_for_decimals() {
+        assert_eq!(amounts("12.50 coffee"), (vec!["12.50".into()], vec![]));
+        assert_eq!(amounts("+12,5 salary"), (vec!["12.5".into()], vec![]));
+        assert_eq!(amounts("1000 rent"), (vec!["1000".into()], vec![]));
+        assert_eq!(amounts("0,125 tip"), (vec!["0.125".into()], vec![]));
+        assert_eq!(amounts("1234,567 car"), (vec!["1234.567".into()], vec![]));
+    }
+
+    #[test]
+    fn amounts_that_look_like_thousands_are_unclear() {
+        let parsed = parse("1,000 rent", NaiveDate::from_ymd(2022, 2, 18));
+        assert_eq!(
+            parsed.unclear_amounts,
+            [UnclearAmount {
+                typed: "1,000".into(),
+                thousands: 1000.into(),
+                decimals: "1.000".parse().unwrap(),
+            }]
+        );
+        assert!(parsed.amounts.is_empty());
+        assert_eq!(amounts("+12.500 bonus"), (vec![], vec!["+12.500".into()]));
+    }
+
+    #[test]
+    fn only_digits_and_a_separator_are_amounts() {
+        let parsed = parse("1e3 1,000.50 0x10 coffee", NaiveDate::from_ymd(2022, 2, 18));
+        assert!(parsed.amounts.is_empty());
+        assert!(parsed.unclear_amounts.is_empty());
+        assert_eq!(parsed.payee, "1e3 1,000.50 0x10 coffee");
+    }
+}
@@ -0,0 +1,143 @@
+//! Validation rules used by the request structs that the `validator` crate doesn't have
+
+use std::collections::BTreeMap;
+
+use {
+    reqwest::Url,
+    sqlx::types::BigDecimal,
+    validator::{ValidationError, ValidationErrors, ValidationErrorsKind},
+};
+
+use crate::error::FieldError;
+
+/// Names must have something other than whitespace
+pub(crate) fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        let mut err = ValidationError::new("blank");
+        err.message = Some("must not be empty".into());
+        return Err(err);
+    }
+    Ok(())
+}
+
+pub(crate) fn not_negative(value: &BigDecimal) -> Result<(), ValidationError> {
+    if value < &BigDecimal::from(0) {
+        let mut err = ValidationError::new("negative");
+        err.message = Some("must not be negative".into());
+        return Err(err);
+    }
+    Ok(())
+}
+
+pub(crate) fn positive(value: &BigDecimal) -> Result<(), ValidationError> {
+    if value <= &BigDecimal::from(0) {
+        let mut err = ValidationError::new("not_positive");
+        err.message = Some("must be more than 0".into());
+        return Err(err);
+    }
+    Ok(())
+}
+
+pub(crate) fn not_zero(value: &BigDecimal) -> Result<(), ValidationError> {
+    if value == &BigDecimal::from(0) {
+        let mut err = ValidationError::new("zero");
+        err.message = Some("must not be 0".into());
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// An ISO 4217 code, like `EUR`
+pub(crate) fn currency_code(value: &str) -> Result<(), ValidationError> {
+    if value.len() != 3 || !value.bytes().all(|b| b.is_ascii_uppercase()) {
+        let mut err = ValidationError::new("currency");
+        err.message = Some("must be a 3 letter currency code".into());
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// An absolute `http` or `https` url
+pub(crate) fn http_url(value: &str) -> Result<(), ValidationError> {
+    match Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => Ok(()),
+        _ => {
+            let mut err = ValidationError::new("url");
+            err.message = Some("must be an http or https url".into());
+            Err(err)
+        }
+    }
+}
+
+/// An error for rules that look at more than one field, `field` is where it gets reported
+pub(crate) fn field_error(field: &'static str, message: &'static str) -> ValidationError {
+    let mut err = ValidationError::new("invalid");
+    err.message = Some(message.into());
+    err.add_param("field".into(), &field);
+    err
+}
+
+/// All the invalid fields, with every message for a field grouped together
+pub(crate) fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut fields = BTreeMap::new();
+    collect(errors, "", &mut fields);
+    fields
+        .into_iter()
+        .map(|(field, messages)| FieldError { field, messages })
+        .collect()
+}
+
+fn collect(errors: &ValidationErrors, prefix: &str, fields: &mut BTreeMap<String, Vec<String>>) {
+    for (&field, kind) in errors.errors() {
+        match kind {
+            ValidationErrorsKind::Field(errs) => {
+                for err in errs {
+                    // Errors of rules on the whole struct say which field they are about
+                    let field = match field {
+                        "__all__" => err.params.get("field").and_then(|f| f.as_str()),
+                        field => Some(field),
+                    };
+                    fields
+                        .entry(join(prefix, field.unwrap_or_default()))
+                        .or_default()
+                        .push(message(err));
+                }
+            }
+            ValidationErrorsKind::Struct(inner) => collect(inner, &join(prefix, field), fields),
+            ValidationErrorsKind::List(items) => {
+                for (idx, inner) in items {
+                    let prefix = format!("{}[{}]", join(prefix, field), idx);
+                    collect(inner, &prefix, fields);
+                }
+            }
+        }
+    }
+}
+
+fn join(prefix: &str, field: &str) -> String {
+    match (prefix, field) {
+        ("", field) => field.to_owned(),
+        (prefix, "") => prefix.to_owned(),
+        (prefix, field) => format!("{}.{}", prefix, field),
+    }
+}
+
+/// The message of the rule, or one made from its parameters for rules that don't set one
+fn message(err: &ValidationError) -> String {
+    if let Some(message) = &err.message {
+        return message.to_string();
+    }
+
+    let (min, max) = (err.params.get("min"), err.params.get("max"));
+    match (err.code.as_ref(), min, max) {
+        ("length", Some(min), Some(max)) => {
+            format!("must be between {} and {} characters long", min, max)
+        }
+        ("length", Some(min), None) => format!("must be at least {} characters long", min),
+        ("length", None, Some(max)) => format!("must be at most {} characters long", max),
+        ("range", Some(min), Some(max)) => format!("must be between {} and {}", min, max),
+        ("range", Some(min), None) => format!("must be at least {}", min),
+        ("range", None, Some(max)) => format!("must be at most {}", max),
+        (code, _, _) => format!("is invalid ({})", code),
+    }
+}
@@ -0,0 +1,395 @@
+//! Sends the queued webhook deliveries in the background, retrying the ones that fail
+
+use std::{
+    env,
+    net::{IpAddr, SocketAddr},
+    time::Duration as StdDuration,
+};
+
+use {
+    anyhow::Context,
+    chrono::{Duration, Utc},
+    hmac::{Hmac, Mac},
+    once_cell::sync::OnceCell,
+    reqwest::{redirect, Url},
+    sha2::Sha256,
+    sqlx::PgPool,
+    url::Host,
+};
+
+use crate::{crud, models::webhook::PendingDelivery};
+
+/// How often the queue is checked for deliveries that are due
+const POLL_INTERVAL: StdDuration = StdDuration::from_secs(5);
+/// Most deliveries sent at once
+const BATCH_SIZE: u64 = 20;
+/// A delivery is given up on after this many failed attempts
+const MAX_ATTEMPTS: i32 = 8;
+/// The wait after the first failure, it doubles after every attempt
+const FIRST_RETRY_SECONDS: i64 = 30;
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+pub(crate) const SIGNATURE_HEADER: &str = "budgetman-signature";
+pub(crate) const EVENT_HEADER: &str = "budgetman-event";
+pub(crate) const DELIVERY_HEADER: &str = "budgetman-delivery";
+
+static ALLOW_PRIVATE: OnceCell<bool> = OnceCell::new();
+
+/// Read whether webhooks may go to loopback and private network addresses from the `WEBHOOK_ALLOW_PRIVATE_URLS`
+/// env variable, they may not by default
+///
+/// # Panic
+/// Will panic if it gets called more than once
+pub(crate) fn set_config_from_env() -> anyhow::Result<()> {
+    let allow = match env::var("WEBHOOK_ALLOW_PRIVATE_URLS") {
+        Ok(v) => v
+            .parse::<bool>()
+            .context("`WEBHOOK_ALLOW_PRIVATE_URLS` env variable is not valid")?,
+        Err(_) => false,
+    };
+
+    if ALLOW_PRIVATE.set(allow).is_err() {
+        panic!("Webhook config has been set before");
+    }
+    Ok(())
+}
+
+fn allow_private() -> bool {
+    ALLOW_PRIVATE.get().copied().unwrap_or(false)
+}
+
+/// The addresses the host of `url` resolves to, as long as none of them is a loopback or private network one.
+///
+/// Otherwise why the url can't be used.
+pub(crate) async fn resolve_public(url: &str) -> Result<Vec<SocketAddr>, &'static str> {
+    let url = Url::parse(url).map_err(|_| "must be an http or https url")?;
+    let port = url
+        .port_or_known_default()
+        .ok_or("must be an http or https url")?;
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|_| "has a host that can't be found")?
+            .collect(),
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        None => return Err("must be an http or https url"),
+    };
+
+    if addrs.is_empty() {
+        return Err("has a host that can't be found");
+    }
+    if !allow_private() && addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err("must not point to a loopback or private network address");
+    }
+    Ok(addrs)
+}
+
+/// Whether `ip` is on the internet, instead of being this machine, the local network or the cloud metadata service
+pub(crate) fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // 0.0.0.0/8 is this network
+                || ip.octets()[0] == 0)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    // fc00::/7 are unique local and fe80::/10 link local addresses
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Start sending deliveries, runs until the process exits
+pub(crate) fn spawn_worker(db: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let due = match crud::webhooks::claim_due(&db, BATCH_SIZE).await {
+                Ok(due) => due,
+                Err(e) => {
+                    tracing::error!("Failed to get the due webhook deliveries: {:?}", e);
+                    continue;
+                }
+            };
+
+            // They are leased for longer than the request timeout, so they won't be picked up
+            // again while they are being sent
+            for delivery in due {
+                tokio::spawn(send(db.clone(), delivery));
+            }
+        }
+    });
+}
+
+/// Send a single delivery and record how it went
+async fn send(db: PgPool, delivery: PendingDelivery) {
+    let (status, error) = match post(&delivery).await {
+        Ok(res) if res.status().is_success() => (Some(res.status().as_u16()), None),
+        Ok(res) => (
+            Some(res.status().as_u16()),
+            Some(format!("Got status {}", res.status())),
+        ),
+        Err(e) => (None, Some(e)),
+    };
+
+    let attempts = delivery.attempts + 1;
+    let retry_at = match error {
+        Some(_) => retry_delay(attempts).map(|delay| Utc::now() + delay),
+        None => None,
+    };
+    if let Err(e) = crud::webhooks::record_attempt(&db, delivery.id, status, error, retry_at).await
+    {
+        tracing::error!("Failed to save webhook delivery {}: {:?}", delivery.id, e);
+    }
+}
+
+/// How long to wait after the `attempts`th failed attempt, None once it's given up on
+fn retry_delay(attempts: i32) -> Option<Duration> {
+    (attempts < MAX_ATTEMPTS).then(|| Duration::seconds(FIRST_RETRY_SECONDS << (attempts - 1)))
+}
+
+/// POST the delivery to the address its url resolves to now, so it can't be changed to a private one after the
+/// check. Redirects aren't followed for the same reason.
+async fn post(delivery: &PendingDelivery) -> Result<reqwest::Response, String> {
+    let addrs = resolve_public(&delivery.url)
+        .await
+        .map_err(|e| format!("The url {}", e))?;
+    let mut client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(redirect::Policy::none());
+    if let Some(Host::Domain(domain)) = Url::parse(&delivery.url).ok().as_ref().and_then(Url::host)
+    {
+        client = client.resolve(domain, addrs[0]);
+    }
+    let client = client.build().map_err(|e| e.to_string())?;
+
+    let body = delivery.payload.to_string();
+    let timestamp = Utc::now().timestamp();
+    client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, delivery.event.as_ref())
+        .header(DELIVERY_HEADER, delivery.id)
+        .header(
+            SIGNATURE_HEADER,
+            signature(&delivery.secret, timestamp, &body),
+        )
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// `t=<timestamp>,v1=<hex of HMAC-SHA256 of "<timestamp>.<body>">`.
+///
+/// The timestamp is part of what's signed so receivers can refuse old deliveries that are replayed.
+pub(crate) fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("t={},v1={}", timestamp, hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use {
+        axum::{
+            extract::Extension,
+            http::{HeaderMap, StatusCode},
+            routing::post,
+            AddExtensionLayer, Router,
+        },
+        bigdecimal::BigDecimal,
+    };
+
+    use super::*;
+    use crate::{
+        models::{
+            user::Role,
+            webhook::{DeliveryStatus, WebhookEvent},
+        },
+        requests::{AccountCreateRequest, WebhookCreate},
+        test_utils,
+    };
+
+    /// What the receiver got, and the statuses it answers with in order
+    #[derive(Default)]
+    struct Receiver {
+        requests: Vec<(HeaderMap, String)>,
+        statuses: Vec<StatusCode>,
+    }
+
+    async fn receive(
+        Extension(receiver): Extension<Arc<Mutex<Receiver>>>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        let mut receiver = receiver.lock().unwrap();
+        receiver.requests.push((headers, body));
+        receiver.statuses.remove(0)
+    }
+
+    fn header<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+        headers[name].to_str().unwrap()
+    }
+
+    #[test]
+    fn only_public_addresses_are_allowed() {
+        for ip in ["93.184.216.34", "2606:2800:220:1::1"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn retries_wait_twice_as_long_every_time() {
+        assert_eq!(retry_delay(1), Some(Duration::seconds(30)));
+        assert_eq!(retry_delay(2), Some(Duration::seconds(60)));
+        assert_eq!(retry_delay(7), Some(Duration::seconds(30 * 64)));
+        assert_eq!(retry_delay(MAX_ATTEMPTS), None);
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_signed_and_retried_until_they_succeed() {
+        // The receiver is on localhost
+        let _ = ALLOW_PRIVATE.set(true);
+        let db = test_utils::db().await;
+        let user_id = test_utils::user(&db, Role::User).await;
+        let client = Default::default();
+
+        let receiver = Arc::new(Mutex::new(Receiver {
+            statuses: vec![StatusCode::INTERNAL_SERVER_ERROR, StatusCode::NO_CONTENT],
+            ..Default::default()
+        }));
+        let addr = test_utils::serve(
+            Router::new()
+                .route("/hook", post(receive))
+                .layer(AddExtensionLayer::new(receiver.clone())),
+        );
+        let url = format!("http://{}/hook", addr);
+
+        let webhook = WebhookCreate {
+            url: url.clone(),
+            events: vec![WebhookEvent::AccountCreated],
+            balance_threshold: None,
+        };
+        let (webhook, secret) = crud::webhooks::create_webhook(&db, user_id, webhook, &client)
+            .await
+            .unwrap();
+        let account = AccountCreateRequest {
+            name: "Wallet".into(),
+            description: None,
+            starting_money: Some(BigDecimal::from(10)),
+            is_adhoc: false,
+            household_id: None,
+        };
+        let account = crud::accounts::create_account(&db, user_id, account, &client)
+            .await
+            .unwrap();
+
+        // The first attempt fails
+        let due = crud::webhooks::claim_due(&db, 100).await.unwrap();
+        let delivery = due.into_iter().find(|d| d.url == url).unwrap();
+        let delivery_id = delivery.id;
+        let before = Utc::now();
+        send(db.clone(), delivery).await;
+
+        let log = crud::webhooks::fetch_deliveries(&db, user_id, webhook.id)
+            .await
+            .unwrap();
+        assert_eq!(log.len(), 1);
+        let entry = &log[0];
+        assert_eq!(entry.id, delivery_id);
+        assert_eq!(entry.event, WebhookEvent::AccountCreated);
+        assert_eq!(entry.payload["data"]["id"], account.id);
+        assert_eq!(entry.status, DeliveryStatus::Pending);
+        assert_eq!(entry.attempts, 1);
+        assert_eq!(entry.response_status, Some(500));
+        assert!(entry.error.as_deref().unwrap().contains("500"));
+        let wait = entry.next_attempt_at - before;
+        assert!(wait > Duration::seconds(29) && wait <= Duration::seconds(31));
+        assert_eq!(entry.delivered_at, None);
+
+        {
+            let receiver = receiver.lock().unwrap();
+            let (headers, body) = &receiver.requests[0];
+            assert_eq!(header(headers, EVENT_HEADER), "account.created");
+            assert_eq!(header(headers, DELIVERY_HEADER), delivery_id.to_string());
+
+            let (timestamp, hex) = header(headers, SIGNATURE_HEADER)
+                .strip_prefix("t=")
+                .and_then(|s| s.split_once(",v1="))
+                .unwrap();
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+            mac.update(format!("{}.{}", timestamp, body).as_bytes());
+            let expected: String = mac
+                .finalize()
+                .into_bytes()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            assert_eq!(hex, expected);
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(body).unwrap(),
+                entry.payload
+            );
+        }
+
+        // Not picked up again before it's due
+        let due = crud::webhooks::claim_due(&db, 100).await.unwrap();
+        assert!(due.iter().all(|d| d.id != delivery_id));
+
+        sqlx::query("UPDATE webhook_deliveries SET next_attempt_at = now() WHERE id = $1")
+            .bind(delivery_id)
+            .execute(&db)
+            .await
+            .unwrap();
+        let due = crud::webhooks::claim_due(&db, 100).await.unwrap();
+        let delivery = due.into_iter().find(|d| d.id == delivery_id).unwrap();
+        assert_eq!(delivery.attempts, 1);
+        send(db.clone(), delivery).await;
+
+        let log = crud::webhooks::fetch_deliveries(&db, user_id, webhook.id)
+            .await
+            .unwrap();
+        let entry = &log[0];
+        assert_eq!(entry.status, DeliveryStatus::Delivered);
+        assert_eq!(entry.attempts, 2);
+        assert_eq!(entry.response_status, Some(204));
+        assert_eq!(entry.error, None);
+        assert!(entry.delivered_at.is_some());
+        assert_eq!(receiver.lock().unwrap().requests.len(), 2);
+    }
+}
@@ -1,42 +1,236 @@
 use {
     askama::Template,
     axum::{
-        extract::{Extension, Form},
-        http::Uri,
-        response::Redirect,
+        extract::{Extension, Query},
+        http::{StatusCode, Uri},
+        response::{IntoResponse, Redirect, Response},
         routing::get,
         Router,
     },
+    cookie::SameSite,
     sqlx::PgPool,
     tower_cookies::{Cookie, Cookies},
 };
 
-use crate::{html_template::HtmlTemplate, requests::LoginRequest, Error};
+use super::settings;
+use crate::{
+    crud,
+    extract::{CsrfToken, Form},
+    html_template::HtmlTemplate,
+    i18n::Locale,
+    models::{audit::ClientInfo, user::UserClaims},
+    requests::{LoginForm, NextQuery, OidcCallbackQuery},
+    utils::{
+        auth::{local_redirect, validate_jwt},
+        cookies, oidc,
+    },
+    CommonError, Error,
+};
+
+/// Holds the state, nonce and next page of an OIDC login until the provider redirects back to us
+const OIDC_STATE_COOKIE: &str = "oidc_state";
 
 pub(crate) fn routes() -> Router {
-    Router::new().route(
-        "/login",
-        get(|| async { HtmlTemplate(LogIn) }).post(handle_login),
-    )
+    Router::new()
+        .route(
+            "/login",
+            get(
+                |locale, CsrfToken(csrf_token), Query(query): Query<NextQuery>| async move {
+                    HtmlTemplate(LogIn::new(locale, csrf_token, &query.next))
+                },
+            )
+            .post(handle_login),
+        )
+        .route("/login/oidc", get(start_oidc_login))
+        .route("/login/oidc/callback", get(handle_oidc_callback))
 }
 
 // Post /login
 pub(crate) async fn handle_login(
     Extension(db): Extension<PgPool>,
-    Form(req): Form<LoginRequest>,
+    locale: Locale,
+    CsrfToken(csrf_token): CsrfToken,
+    Form(form): Form<LoginForm>,
+    cookies: Cookies,
+    client: ClientInfo,
+) -> Result<Response, Error> {
+    let username = form.credentials.username.clone();
+    match crud::login(form.credentials, &db, &client).await {
+        Ok(jwt) => {
+            log_in(&db, &cookies, jwt).await?;
+            Ok(redirect_to_next(&form.next))
+        }
+        Err(CommonError::WrongCredentials) => {
+            let page = LogIn {
+                username,
+                ..LogIn::failed(locale, csrf_token, &form.next, "login.wrong_credentials")
+            };
+            Ok((StatusCode::UNAUTHORIZED, HtmlTemplate(page)).into_response())
+        }
+        Err(e) => Err(Error::HtmlError(e)),
+    }
+}
+
+// Get /login/oidc
+pub(crate) async fn start_oidc_login(
+    Query(query): Query<NextQuery>,
     cookies: Cookies,
 ) -> Result<Redirect, Error> {
-    let jwt = crate::crud::login(req, &db)
+    let config = oidc::config().ok_or(Error::HtmlError(CommonError::NotFound))?;
+    let req = oidc::authorization_request(config)
         .await
         .map_err(Error::HtmlError)?;
 
-    let mut cookie = Cookie::new("access_token", jwt);
-    cookie.set_http_only(true);
+    let next = local_redirect(&query.next).unwrap_or_default();
+    let state = format!("{}.{}.{}", req.state, req.nonce, next);
+    let mut cookie = cookies::private_cookie(OIDC_STATE_COOKIE, state);
+    cookie.set_path("/login/oidc");
+    // The provider redirects back to us so the cookie must be sent with top level navigations
+    cookie.set_same_site(SameSite::Lax);
     cookies.add(cookie);
 
-    Ok(Redirect::to(Uri::from_static("/")))
+    let uri = req
+        .url
+        .parse::<Uri>()
+        .map_err(|_| Error::HtmlError(CommonError::NotFound))?;
+    Ok(Redirect::to(uri))
+}
+
+// Get /login/oidc/callback
+pub(crate) async fn handle_oidc_callback(
+    Extension(db): Extension<PgPool>,
+    Query(callback): Query<OidcCallbackQuery>,
+    locale: Locale,
+    CsrfToken(csrf_token): CsrfToken,
+    cookies: Cookies,
+    user: Option<UserClaims>,
+    client: ClientInfo,
+) -> Result<Response, Error> {
+    let config = oidc::config().ok_or(Error::HtmlError(CommonError::NotFound))?;
+
+    match oidc_login(&db, config, callback, &cookies, user, &client).await {
+        Ok((jwt, next)) => {
+            log_in(&db, &cookies, jwt).await?;
+            Ok(redirect_to_next(&next))
+        }
+        Err(
+            CommonError::MissingCredentials(_)
+            | CommonError::WrongCredentials
+            | CommonError::InvalidCredentials(_),
+        ) => {
+            let page = LogIn::failed(locale, csrf_token, "", "login.sso_failed");
+            Ok((StatusCode::UNAUTHORIZED, HtmlTemplate(page)).into_response())
+        }
+        Err(e) => Err(Error::HtmlError(e)),
+    }
+}
+
+/// Check what the OIDC provider sent back and log the user in.
+///
+/// Returns the jwt and the page to go to next.
+async fn oidc_login(
+    db: &PgPool,
+    config: &oidc::OidcConfig,
+    callback: OidcCallbackQuery,
+    cookies: &Cookies,
+    user: Option<UserClaims>,
+    client: &ClientInfo,
+) -> Result<(String, String), CommonError> {
+    let saved = cookies
+        .get(OIDC_STATE_COOKIE)
+        .map(|c| c.value().to_string())
+        .ok_or(CommonError::MissingCredentials("Missing OIDC state"))?;
+    cookies.remove(
+        Cookie::build(OIDC_STATE_COOKIE, "")
+            .path("/login/oidc")
+            .finish(),
+    );
+
+    let mut parts = saved.splitn(3, '.');
+    let (state, nonce, next) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(state), Some(nonce), next) => (state, nonce, next.unwrap_or_default()),
+        _ => return Err(CommonError::WrongCredentials),
+    };
+    if callback.state != state {
+        return Err(CommonError::WrongCredentials);
+    }
+    let code = match (callback.code, callback.error) {
+        (Some(code), None) => code,
+        (_, error) => {
+            tracing::warn!("OIDC login failed: {:?}", error);
+            return Err(CommonError::WrongCredentials);
+        }
+    };
+
+    let identity = oidc::exchange_code(config, &code, nonce).await?;
+    let jwt = crud::login_oidc(
+        db,
+        identity,
+        user.map(|u| u.id),
+        config.auto_provision,
+        client,
+    )
+    .await?;
+    Ok((jwt, next.to_owned()))
+}
+
+/// Keep the jwt in a cookie, along with the settings the pages need before they know who the user is
+async fn log_in(db: &PgPool, jar: &Cookies, jwt: String) -> Result<(), Error> {
+    let user = validate_jwt(&jwt).map_err(Error::HtmlError)?;
+    let settings = crud::settings::fetch_settings(db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    settings::remember(jar, &settings);
+    jar.add(cookies::private_cookie(cookies::ACCESS_TOKEN, jwt));
+    Ok(())
+}
+
+/// Go to `next` after logging in, or to the home page if it isn't a page of this site
+fn redirect_to_next(next: &str) -> Response {
+    let uri = local_redirect(next)
+        .and_then(|next| next.parse::<Uri>().ok())
+        .unwrap_or_else(|| Uri::from_static("/"));
+    Redirect::to(uri).into_response()
 }
 
 #[derive(Template)]
 #[template(path = "account/login.html")]
-pub(crate) struct LogIn;
+pub(crate) struct LogIn {
+    locale: Locale,
+    oidc_enabled: bool,
+    csrf_token: String,
+    /// What was typed in the form the last time, so it doesn't have to be typed again
+    username: String,
+    /// Why the last attempt to log in failed
+    error: Option<&'static str>,
+    /// The page to go to after logging in, empty for the home page
+    next: String,
+    oidc_url: String,
+}
+
+impl LogIn {
+    fn new(locale: Locale, csrf_token: String, next: &str) -> Self {
+        let next = local_redirect(next).unwrap_or_default().to_owned();
+        let oidc_url = match serde_urlencoded::to_string([("next", &next)]) {
+            Ok(query) if !next.is_empty() => format!("/login/oidc?{}", query),
+            _ => "/login/oidc".to_owned(),
+        };
+        Self {
+            locale,
+            oidc_enabled: oidc::config().is_some(),
+            csrf_token,
+            username: String::new(),
+            error: None,
+            next,
+            oidc_url,
+        }
+    }
+
+    /// The form again after a failed login, `error` is the key of the text that says why
+    fn failed(locale: Locale, csrf_token: String, next: &str, error: &str) -> Self {
+        Self {
+            error: Some(locale.t(error)),
+            ..Self::new(locale, csrf_token, next)
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use {
+    askama::Template,
+    axum::{
+        extract::{Extension, Path},
+        http::{StatusCode, Uri},
+        response::{IntoResponse, Redirect, Response},
+        routing::get,
+        Router,
+    },
+    bigdecimal::BigDecimal,
+    sqlx::PgPool,
+};
+
+use super::{recent_activity, Activity, FormErrors};
+use crate::{
+    crud,
+    extract::{CsrfToken, Form},
+    html_template::HtmlTemplate,
+    i18n::Locale,
+    models::{
+        account::AccountRow,
+        audit::{AuditEntity, ClientInfo},
+        user::{RequirePermission, UserClaims, Write},
+    },
+    requests::AccountForm,
+    utils::etag::EntityTags,
+    Error,
+};
+
+pub(crate) fn routes() -> Router {
+    Router::new()
+        .route("/accounts", get(list_accounts))
+        .route("/accounts/new", get(new_account).post(create_account))
+        .route("/accounts/:id", get(show_account))
+        .route("/accounts/:id/edit", get(edit_account).post(update_account))
+}
+
+// Get /accounts
+pub(crate) async fn list_accounts(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    locale: Locale,
+) -> Result<HtmlTemplate<AccountList>, Error> {
+    let accounts = crud::accounts::fetch_accounts(&db, &user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let settings = crud::settings::fetch_settings(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let (adhoc, normal): (Vec<_>, Vec<_>) = accounts
+        .into_iter()
+        .map(|account| AccountItem::new(account, &user, locale, settings.currency.as_deref()))
+        .partition(|account| account.is_adhoc);
+
+    Ok(HtmlTemplate(AccountList {
+        username: user.username,
+        locale,
+        normal,
+        adhoc,
+    }))
+}
+
+// Get /accounts/:id
+pub(crate) async fn show_account(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    locale: Locale,
+    Path(id): Path<i32>,
+) -> Result<HtmlTemplate<AccountDetails>, Error> {
+    let account = crud::accounts::fetch_account(&db, &user.id, id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let activity = recent_activity(&db, &user, locale, AuditEntity::Account, id).await?;
+    let settings = crud::settings::fetch_settings(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+
+    Ok(HtmlTemplate(AccountDetails {
+        account: AccountItem::new(account, &user, locale, settings.currency.as_deref()),
+        activity,
+        username: user.username,
+        locale,
+    }))
+}
+
+// Get /accounts/new
+pub(crate) async fn new_account(
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    CsrfToken(csrf_token): CsrfToken,
+) -> HtmlTemplate<AccountFormPage> {
+    HtmlTemplate(AccountFormPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        id: None,
+        form: AccountForm::default(),
+        errors: FormErrors::default(),
+    })
+}
+
+// Post /accounts/new
+pub(crate) async fn create_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    client: ClientInfo,
+    CsrfToken(csrf_token): CsrfToken,
+    Form(form): Form<AccountForm>,
+) -> Result<Response, Error> {
+    let errors = match form.to_create() {
+        Ok(account) => match crud::accounts::create_account(&db, user.id, account, &client).await {
+            Ok(account) => return Ok(redirect_to(account.id)),
+            Err(e) => FormErrors::from_error(e, locale)?,
+        },
+        Err(e) => e.into(),
+    };
+
+    let page = AccountFormPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        id: None,
+        form,
+        errors,
+    };
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(page)).into_response())
+}
+
+// Get /accounts/:id/edit
+pub(crate) async fn edit_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(id): Path<i32>,
+) -> Result<HtmlTemplate<AccountFormPage>, Error> {
+    let account = crud::accounts::fetch_account(&db, &user.id, id)
+        .await
+        .map_err(Error::HtmlError)?;
+
+    Ok(HtmlTemplate(AccountFormPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        id: Some(id),
+        form: AccountForm {
+            name: account.name,
+            description: account.description.unwrap_or_default(),
+            is_adhoc: account.is_adhoc.then(|| "on".to_owned()),
+            version: Some(account.version),
+            ..AccountForm::default()
+        },
+        errors: FormErrors::default(),
+    }))
+}
+
+// Post /accounts/:id/edit
+pub(crate) async fn update_account(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    client: ClientInfo,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(id): Path<i32>,
+    Form(form): Form<AccountForm>,
+) -> Result<Response, Error> {
+    // Without a version the form can only have been changed by hand, so it's treated as a conflict
+    let if_match = EntityTags::version(form.version.unwrap_or_default());
+    let errors = match form.to_update() {
+        Ok(update) => {
+            match crud::accounts::update_account(&db, user.id, id, update, &if_match, &client).await
+            {
+                Ok(_) => return Ok(redirect_to(id)),
+                Err(e) => FormErrors::from_error(e, locale)?,
+            }
+        }
+        Err(e) => e.into(),
+    };
+
+    let page = AccountFormPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        id: Some(id),
+        form,
+        errors,
+    };
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(page)).into_response())
+}
+
+fn redirect_to(id: i32) -> Response {
+    let uri = format!("/accounts/{}", id)
+        .parse::<Uri>()
+        .expect("The path of an account to be a valid uri");
+    Redirect::to(uri).into_response()
+}
+
+#[derive(Template)]
+#[template(path = "accounts/list.html")]
+pub(crate) struct AccountList {
+    username: String,
+    locale: Locale,
+    normal: Vec<AccountItem>,
+    adhoc: Vec<AccountItem>,
+}
+
+#[derive(Template)]
+#[template(path = "accounts/details.html")]
+pub(crate) struct AccountDetails {
+    username: String,
+    locale: Locale,
+    account: AccountItem,
+    /// Newest first
+    activity: Vec<Activity>,
+}
+
+#[derive(Template)]
+#[template(path = "accounts/form.html")]
+pub(crate) struct AccountFormPage {
+    username: String,
+    locale: Locale,
+    csrf_token: String,
+    /// The account that is being edited, `None` when a new one is being created
+    id: Option<i32>,
+    form: AccountForm,
+    errors: FormErrors,
+}
+
+/// An account, with its money formatted to be shown
+struct AccountItem {
+    id: i32,
+    name: String,
+    description: String,
+    available_money: String,
+    total_money: String,
+    is_adhoc: bool,
+    /// Owned by someone else and shared with the user through a household
+    shared_with_user: bool,
+}
+
+impl AccountItem {
+    fn new(account: AccountRow, user: &UserClaims, locale: Locale, currency: Option<&str>) -> Self {
+        let money = |amount: Option<BigDecimal>| {
+            amount
+                .map(|amount| locale.money(&amount, currency))
+                .unwrap_or_default()
+        };
+        Self {
+            id: account.id,
+            available_money: money(account.available_money),
+            total_money: money(account.total_money),
+            description: account.description.unwrap_or_default(),
+            is_adhoc: account.is_adhoc,
+            shared_with_user: account.user_id != user.id,
+            name: account.name,
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use {
+    askama::Template,
+    axum::{extract::Extension, routing::get, Router},
+    bigdecimal::{BigDecimal, Zero},
+    chrono::Utc,
+    sqlx::PgPool,
+};
+
+use super::{percent, Budget};
+use crate::{
+    crud,
+    html_template::HtmlTemplate,
+    i18n::Locale,
+    models::{account::NormalAccountRow, goal::GoalRow, user::UserClaims},
+    Error,
+};
+
+pub(crate) fn routes() -> Router {
+    Router::new().route("/", get(dashboard))
+}
+
+// Get /
+pub(crate) async fn dashboard(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    locale: Locale,
+) -> Result<HtmlTemplate<Index>, Error> {
+    let accounts = crud::accounts::fetch_normal_accounts(&db, &user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let tags = crud::tags::fetch_tags(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let goals = crud::goals::fetch_goals(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let settings = crud::settings::fetch_settings(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let currency = settings.currency.as_deref();
+
+    let today = Utc::today().naive_utc();
+    let ids: Vec<i32> = accounts.iter().map(|account| account.id).collect();
+    let week_start = settings.first_day_of_week.week_start(today);
+    let spent_this_week = crud::transactions::fetch_spent_since(&db, &ids, week_start)
+        .await
+        .map_err(Error::HtmlError)?;
+    let period_start = settings.period_start(today);
+    let spent_this_period = crud::transactions::fetch_spent_since(&db, &ids, period_start)
+        .await
+        .map_err(Error::HtmlError)?;
+
+    // Accounts shared with the user by someone else are shown, but they are not part of their net worth
+    let net_worth = accounts
+        .iter()
+        .filter(|account| account.user_id == user.id)
+        .fold(BigDecimal::zero(), |sum, account| {
+            sum + &account.total_money
+        });
+
+    let goals = goals
+        .into_iter()
+        .map(|goal| {
+            let account = accounts
+                .iter()
+                .find(|account| account.id == goal.account_id)
+                .map(|account| account.name.clone())
+                .unwrap_or_default();
+            GoalProgress::new(goal, account, locale, currency)
+        })
+        .collect();
+    let accounts = accounts
+        .into_iter()
+        .map(|account| AccountBalance::new(account, &net_worth, locale, currency))
+        .collect();
+    let tags: Vec<_> = tags.into_iter().filter(|tag| tag.limit.is_some()).collect();
+    let tag_ids: Vec<i32> = tags.iter().map(|tag| tag.id).collect();
+    let spent = crud::transactions::fetch_tag_spent_since(&db, &tag_ids, period_start)
+        .await
+        .map_err(Error::HtmlError)?;
+    let budgets = tags
+        .into_iter()
+        .map(|tag| {
+            let spent = spent.get(&tag.id).cloned().unwrap_or_default();
+            Budget::new(tag, &spent, locale, currency)
+        })
+        .collect();
+
+    Ok(HtmlTemplate(Index {
+        username: user.username,
+        locale,
+        net_worth: locale.money(&net_worth, currency),
+        spent_this_week: locale.money(&spent_this_week, currency),
+        spent_this_period: locale.money(&spent_this_period, currency),
+        period_start: locale.date(period_start),
+        accounts,
+        budgets,
+        goals,
+    }))
+}
+
+#[derive(Template)]
+#[template(path = "index.html")]
+pub(crate) struct Index {
+    username: String,
+    locale: Locale,
+    net_worth: String,
+    /// What went out of the accounts to third parties and accounts of others
+    spent_this_week: String,
+    spent_this_period: String,
+    /// The first day of the budget period
+    period_start: String,
+    accounts: Vec<AccountBalance>,
+    /// Only the tags that have a limit
+    budgets: Vec<Budget>,
+    goals: Vec<GoalProgress>,
+}
+
+struct AccountBalance {
+    name: String,
+    available_money: String,
+    total_money: String,
+    /// How much of the net worth is in this account, from 0 to 100
+    share: u8,
+}
+
+impl AccountBalance {
+    fn new(
+        account: NormalAccountRow,
+        net_worth: &BigDecimal,
+        locale: Locale,
+        currency: Option<&str>,
+    ) -> Self {
+        Self {
+            share: percent(&account.total_money, net_worth),
+            available_money: locale.money(&account.available_money, currency),
+            total_money: locale.money(&account.total_money, currency),
+            name: account.name,
+        }
+    }
+}
+
+struct GoalProgress {
+    name: String,
+    /// The name of the account the money is set aside in
+    account: String,
+    balance: String,
+    target: String,
+    /// How much of the target was set aside, from 0 to 100
+    progress: u8,
+    reached: bool,
+}
+
+impl GoalProgress {
+    fn new(goal: GoalRow, account: String, locale: Locale, currency: Option<&str>) -> Self {
+        Self {
+            progress: percent(&goal.balance, &goal.target),
+            reached: goal.is_reached(),
+            balance: locale.money(&goal.balance, currency),
+            target: locale.money(&goal.target, currency),
+            name: goal.name,
+            account,
+        }
+    }
+}
@@ -1,38 +1,295 @@
 mod account;
+mod accounts;
+mod dashboard;
+mod quick_add;
+mod settings;
+mod tags;
+mod transactions;
 pub(crate) mod views_404;
 pub(crate) mod views_500;
 
 use {
-    askama::Template,
-    axum::{routing::get, Router},
+    axum::{
+        extract::{Extension, Path},
+        http::{header, HeaderMap, Uri},
+        response::Redirect,
+        routing::{get, post},
+        Router,
+    },
+    bigdecimal::{BigDecimal, ToPrimitive, Zero},
+    serde::de::IgnoredAny,
+    sqlx::PgPool,
+    tower_cookies::Cookies,
+    validator::ValidationErrors,
 };
 
-use crate::{html_template::HtmlTemplate, models::user::UserClaims};
+use crate::{
+    crud,
+    error::FieldError,
+    extract::Form,
+    html_template::HtmlTemplate,
+    i18n::Locale,
+    models::{
+        audit::{AuditAction, AuditEntity, AuditLogQuery, AuditLogRow, ClientInfo},
+        tag::TagRow,
+        user::UserClaims,
+    },
+    requests::SettingsUpdate,
+    utils::{auth::local_redirect, cookies, err_is_failed_constraint, validation::field_errors},
+    CommonError, Error,
+};
 
 pub(crate) fn routes() -> Router {
     let views = Router::new()
-        .route(
-            "/",
-            get(|user: UserClaims| async {
-                HtmlTemplate(Index {
-                    username: user.username,
-                })
-            }),
-        )
         .route(
             "/500",
             get(|user: Option<UserClaims>| async {
                 HtmlTemplate(views_500::View500::new(user.map(|u| u.username).as_deref()))
             }),
-        );
+        )
+        .route("/locale/:code", post(set_locale));
 
     let account_pages = account::routes();
 
-    views.merge(account_pages)
+    views
+        .merge(dashboard::routes())
+        .merge(account_pages)
+        .merge(accounts::routes())
+        .merge(tags::routes())
+        .merge(transactions::routes())
+        .merge(quick_add::routes())
+        .merge(settings::routes())
+}
+
+/// How many audit log entries the page of an entity shows
+const RECENT_ACTIVITY: u64 = 10;
+
+// Post /locale/:code
+/// Remember the language the user picked and go back to the page they picked it on.
+/// It's saved in the settings of the user too if they are logged in.
+async fn set_locale(
+    Extension(db): Extension<PgPool>,
+    Path(code): Path<String>,
+    user: Option<UserClaims>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    jar: Cookies,
+    Form(_): Form<IgnoredAny>,
+) -> Redirect {
+    if let Ok(locale) = code.parse::<Locale>() {
+        let mut cookie = cookies::private_cookie(cookies::LOCALE, locale.code().to_owned());
+        cookie.make_permanent();
+        jar.add(cookie);
+
+        if let Some(user) = user {
+            if let Err(e) = save_locale(&db, user, locale, &client).await {
+                tracing::warn!("Failed to save the language in the settings: {}", e);
+            }
+        }
+    }
+
+    let back = headers
+        .get(header::REFERER)
+        .and_then(|referer| referer.to_str().ok())
+        .and_then(|referer| referer.parse::<Uri>().ok())
+        .and_then(|referer| {
+            let page = referer.path_and_query()?.as_str();
+            local_redirect(page)?.parse::<Uri>().ok()
+        });
+    Redirect::to(back.unwrap_or_else(|| Uri::from_static("/")))
+}
+
+async fn save_locale(
+    db: &PgPool,
+    user: UserClaims,
+    locale: Locale,
+    client: &ClientInfo,
+) -> Result<(), CommonError> {
+    let settings = crud::settings::fetch_settings(db, user.id).await?;
+    if settings.locale == Some(locale) {
+        return Ok(());
+    }
+    let update = SettingsUpdate {
+        locale: Some(locale),
+        ..settings.into()
+    };
+    crud::settings::update_settings(db, user.id, update, client).await?;
+    Ok(())
+}
+
+/// `part` as a percentage of `whole`, clamped to 0..=100 so it can be used as the width of a progress bar
+pub(crate) fn percent(part: &BigDecimal, whole: &BigDecimal) -> u8 {
+    if whole <= &BigDecimal::zero() {
+        return 0;
+    }
+    let ratio = (part / whole).to_f64().unwrap_or_default();
+    (ratio * 100.0).round().clamp(0.0, 100.0) as u8
+}
+
+/// A tag and how much of its limit is used this budget period, with the money formatted to be shown
+pub(crate) struct Budget {
+    id: i32,
+    name: String,
+    description: String,
+    /// Spent since the start of the budget period
+    spent: String,
+    limit: Option<String>,
+    /// Negative when the tag is over its limit
+    remaining: Option<String>,
+    /// How much of the limit is used, from 0 to 100
+    used: u8,
+    over_limit: bool,
+}
+
+impl Budget {
+    /// `spent` is what was spent with the tag since the start of the budget period
+    pub(crate) fn new(
+        tag: TagRow,
+        spent: &BigDecimal,
+        locale: Locale,
+        currency: Option<&str>,
+    ) -> Self {
+        let used = match &tag.limit {
+            Some(limit) => percent(spent, limit),
+            None => 0,
+        };
+        Self {
+            id: tag.id,
+            used,
+            over_limit: tag.is_over_limit(spent),
+            remaining: tag
+                .limit
+                .as_ref()
+                .map(|limit| locale.money(&(limit - spent), currency)),
+            limit: tag
+                .limit
+                .as_ref()
+                .map(|limit| locale.money(limit, currency)),
+            spent: locale.money(spent, currency),
+            description: tag.description.unwrap_or_default(),
+            name: tag.name,
+        }
+    }
+
+    /// The adminLTE color of its progress bar and badge
+    pub(crate) fn status(&self) -> &'static str {
+        match self.used {
+            _ if self.over_limit => "danger",
+            _ if self.limit.is_none() => "secondary",
+            90..=100 => "warning",
+            _ => "success",
+        }
+    }
+}
+
+/// Why a form that was sent couldn't be saved, shown on the form instead of going to the error page
+#[derive(Debug, Default)]
+pub(crate) struct FormErrors {
+    fields: Vec<FieldError>,
+    /// An error that isn't about a single field
+    general: Option<String>,
+}
+
+impl FormErrors {
+    /// The errors the user can fix by changing the form, any other error is given back
+    pub(crate) fn from_error(err: CommonError, locale: Locale) -> Result<Self, Error> {
+        let general = |msg: &str| Self {
+            general: Some(msg.to_owned()),
+            ..Self::default()
+        };
+        match err {
+            CommonError::Validation(fields) => Ok(Self {
+                fields,
+                general: None,
+            }),
+            CommonError::Db { source, .. } if err_is_failed_constraint(&source) => Ok(Self {
+                fields: vec![FieldError {
+                    field: "name".into(),
+                    messages: vec![locale.t("form.already_used").into()],
+                }],
+                general: None,
+            }),
+            CommonError::VersionMismatch => Ok(general(locale.t("form.version_mismatch"))),
+            CommonError::Forbidden => Ok(general(locale.t("form.forbidden"))),
+            err => Err(Error::HtmlError(err)),
+        }
+    }
+
+    pub(crate) fn general(&self) -> Option<&str> {
+        self.general.as_deref()
+    }
+
+    /// Every message, for forms that show them in one place
+    pub(crate) fn messages(&self) -> Vec<String> {
+        self.general
+            .iter()
+            .cloned()
+            .chain(self.fields.iter().flat_map(|e| e.messages.iter().cloned()))
+            .collect()
+    }
+
+    /// The messages for `field`, joined together
+    pub(crate) fn field(&self, field: &str) -> Option<String> {
+        self.fields
+            .iter()
+            .find(|e| e.field == field)
+            .map(|e| e.messages.join(", "))
+    }
+}
+
+impl From<ValidationErrors> for FormErrors {
+    fn from(errors: ValidationErrors) -> Self {
+        Self {
+            fields: field_errors(&errors),
+            general: None,
+        }
+    }
+}
+
+/// A change to an entity, from the audit log
+pub(crate) struct Activity {
+    at: String,
+    action: String,
+}
+
+impl Activity {
+    fn new(entry: AuditLogRow, locale: Locale) -> Self {
+        Self {
+            at: locale.date_time(entry.created_at),
+            action: match entry.action {
+                AuditAction::Create => locale.t("activity.created").to_owned(),
+                AuditAction::Update => locale.t("activity.updated").to_owned(),
+                AuditAction::Delete => locale.t("activity.deleted").to_owned(),
+                action => action.as_ref().to_owned(),
+            },
+        }
+    }
 }
 
-#[derive(Template)]
-#[template(path = "index.html")]
-struct Index {
-    username: String,
+/// The latest changes the user made to an entity, newest first
+pub(crate) async fn recent_activity(
+    db: &PgPool,
+    user: &UserClaims,
+    locale: Locale,
+    entity: AuditEntity,
+    id: i32,
+) -> Result<Vec<Activity>, Error> {
+    let q = AuditLogQuery {
+        action: None,
+        entity_type: Some(entity),
+        entity_id: Some(id.to_string()),
+        user_id: None,
+        since: None,
+        until: None,
+        page: 1,
+        per_page: RECENT_ACTIVITY,
+    };
+    let page = crud::audit::fetch_audit_log(db, user, q)
+        .await
+        .map_err(Error::HtmlError)?;
+    Ok(page
+        .entries
+        .into_iter()
+        .map(|entry| Activity::new(entry, locale))
+        .collect())
 }
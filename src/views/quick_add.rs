@@ -0,0 +1,113 @@
+use {
+    askama::Template,
+    axum::{
+        extract::{Extension, Query},
+        http::{StatusCode, Uri},
+        response::{IntoResponse, Redirect, Response},
+        routing::get,
+        Router,
+    },
+    sqlx::PgPool,
+    uuid::Uuid,
+};
+
+use super::FormErrors;
+use crate::{
+    crud,
+    extract::{CsrfToken, Form},
+    html_template::HtmlTemplate,
+    i18n::Locale,
+    models::{
+        audit::ClientInfo,
+        user::{RequirePermission, UserClaims, Write},
+    },
+    requests::QuickAddForm,
+    responses::QuickAddPreview,
+    Error,
+};
+
+pub(crate) fn routes() -> Router {
+    Router::new().route("/quick-add", get(show_quick_add).post(save_quick_add))
+}
+
+// Get /quick-add
+/// The preview of a line, the quick-add box of every page comes here when scripts are off
+pub(crate) async fn show_quick_add(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    locale: Locale,
+    CsrfToken(csrf_token): CsrfToken,
+    Query(mut form): Query<QuickAddForm>,
+) -> Result<HtmlTemplate<QuickAddPage>, Error> {
+    form.client_id = Uuid::new_v4().to_string();
+    let page = quick_add_page(&db, user, locale, csrf_token, form).await?;
+    Ok(HtmlTemplate(page))
+}
+
+// Post /quick-add
+pub(crate) async fn save_quick_add(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    client: ClientInfo,
+    CsrfToken(csrf_token): CsrfToken,
+    Form(form): Form<QuickAddForm>,
+) -> Result<Response, Error> {
+    let client_id = form.client_id.parse().ok();
+    let errors = match crud::quick_add::save(&db, user.id, &form.line, client_id, &client).await {
+        Ok((account_id, _)) => {
+            let uri = format!("/accounts/{}/register", account_id)
+                .parse::<Uri>()
+                .expect("The path of a register to be a valid uri");
+            return Ok(Redirect::to(uri).into_response());
+        }
+        Err(e) => FormErrors::from_error(e, locale)?,
+    };
+
+    let mut page = quick_add_page(&db, user, locale, csrf_token, form).await?;
+    page.errors = errors;
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(page)).into_response())
+}
+
+async fn quick_add_page(
+    db: &PgPool,
+    user: UserClaims,
+    locale: Locale,
+    csrf_token: String,
+    form: QuickAddForm,
+) -> Result<QuickAddPage, Error> {
+    let preview = match form.line.trim() {
+        "" => None,
+        line => Some(
+            crud::quick_add::preview(db, user.id, line)
+                .await
+                .map_err(Error::HtmlError)?,
+        ),
+    };
+    let settings = crud::settings::fetch_settings(db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+
+    Ok(QuickAddPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        currency: settings.currency,
+        form,
+        preview,
+        errors: FormErrors::default(),
+    })
+}
+
+#[derive(Template)]
+#[template(path = "quick_add.html")]
+pub(crate) struct QuickAddPage {
+    username: String,
+    locale: Locale,
+    csrf_token: String,
+    currency: Option<String>,
+    form: QuickAddForm,
+    /// None until a line is typed
+    preview: Option<QuickAddPreview>,
+    errors: FormErrors,
+}
@@ -0,0 +1,124 @@
+use {
+    askama::Template,
+    axum::{
+        extract::Extension,
+        http::{StatusCode, Uri},
+        response::{IntoResponse, Redirect, Response},
+        routing::get,
+        Router,
+    },
+    sqlx::PgPool,
+    tower_cookies::{Cookie, Cookies},
+};
+
+use super::FormErrors;
+use crate::{
+    crud,
+    extract::{CsrfToken, Form},
+    html_template::HtmlTemplate,
+    i18n::Locale,
+    models::{
+        account::NormalAccountRow, audit::ClientInfo, settings::UserSettings, user::UserClaims,
+    },
+    requests::SettingsForm,
+    utils::cookies,
+    Error,
+};
+
+pub(crate) fn routes() -> Router {
+    Router::new().route("/settings", get(show_settings).post(save_settings))
+}
+
+// Get /settings
+pub(crate) async fn show_settings(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    locale: Locale,
+    CsrfToken(csrf_token): CsrfToken,
+) -> Result<HtmlTemplate<SettingsPage>, Error> {
+    let settings = crud::settings::fetch_settings(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let form = SettingsForm::from(&settings);
+    let page = settings_page(&db, user, locale, csrf_token, form).await?;
+    Ok(HtmlTemplate(page))
+}
+
+// Post /settings
+pub(crate) async fn save_settings(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    locale: Locale,
+    client: ClientInfo,
+    CsrfToken(csrf_token): CsrfToken,
+    jar: Cookies,
+    Form(form): Form<SettingsForm>,
+) -> Result<Response, Error> {
+    let errors = match form.to_update() {
+        Ok(update) => match crud::settings::update_settings(&db, user.id, update, &client).await {
+            Ok(settings) => {
+                if settings.locale.is_none() {
+                    jar.remove(Cookie::build(cookies::LOCALE, "").path("/").finish());
+                }
+                remember(&jar, &settings);
+                return Ok(Redirect::to(Uri::from_static("/settings")).into_response());
+            }
+            Err(e) => FormErrors::from_error(e, locale)?,
+        },
+        Err(e) => e.into(),
+    };
+
+    let mut page = settings_page(&db, user, locale, csrf_token, form).await?;
+    page.errors = errors;
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(page)).into_response())
+}
+
+/// Keep the settings that are needed before the user is known in cookies,
+/// so the language and theme are right on every page, including the login page.
+///
+/// Without a language in the settings the one picked from the menu, if any, is kept.
+pub(crate) fn remember(jar: &Cookies, settings: &UserSettings) {
+    if let Some(locale) = settings.locale {
+        let mut cookie = cookies::private_cookie(cookies::LOCALE, locale.code().to_owned());
+        cookie.make_permanent();
+        jar.add(cookie);
+    }
+
+    let mut cookie = cookies::script_cookie(cookies::THEME, settings.theme.as_ref().to_owned());
+    cookie.make_permanent();
+    jar.add(cookie);
+}
+
+/// The settings page with `form` in it
+async fn settings_page(
+    db: &PgPool,
+    user: UserClaims,
+    locale: Locale,
+    csrf_token: String,
+    form: SettingsForm,
+) -> Result<SettingsPage, Error> {
+    let accounts = crud::accounts::fetch_normal_accounts(db, &user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+
+    Ok(SettingsPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        accounts,
+        form,
+        errors: FormErrors::default(),
+    })
+}
+
+#[derive(Template)]
+#[template(path = "settings.html")]
+pub(crate) struct SettingsPage {
+    username: String,
+    locale: Locale,
+    csrf_token: String,
+    /// The choices for the default account
+    accounts: Vec<NormalAccountRow>,
+    form: SettingsForm,
+    errors: FormErrors,
+}
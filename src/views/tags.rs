@@ -0,0 +1,275 @@
+use {
+    askama::Template,
+    axum::{
+        extract::{Extension, Path},
+        http::{StatusCode, Uri},
+        response::{IntoResponse, Redirect, Response},
+        routing::get,
+        Router,
+    },
+    bigdecimal::BigDecimal,
+    chrono::Utc,
+    sqlx::PgPool,
+};
+
+use super::{recent_activity, Activity, Budget, FormErrors};
+use crate::{
+    crud,
+    extract::{CsrfToken, Form},
+    html_template::HtmlTemplate,
+    i18n::Locale,
+    models::{
+        audit::{AuditEntity, ClientInfo},
+        transaction::TransactionDetails,
+        user::{RequirePermission, UserClaims, Write},
+    },
+    requests::TagForm,
+    utils::etag::EntityTags,
+    Error,
+};
+
+pub(crate) fn routes() -> Router {
+    Router::new()
+        .route("/tags", get(list_tags))
+        .route("/tags/new", get(new_tag).post(create_tag))
+        .route("/tags/:id", get(show_tag))
+        .route("/tags/:id/edit", get(edit_tag).post(update_tag))
+}
+
+// Get /tags
+pub(crate) async fn list_tags(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    locale: Locale,
+) -> Result<HtmlTemplate<TagList>, Error> {
+    let settings = crud::settings::fetch_settings(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let tags = crud::tags::fetch_tags(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let tag_ids: Vec<i32> = tags.iter().map(|tag| tag.id).collect();
+    let period_start = settings.period_start(Utc::today().naive_utc());
+    let spent = crud::transactions::fetch_tag_spent_since(&db, &tag_ids, period_start)
+        .await
+        .map_err(Error::HtmlError)?;
+    let tags = tags
+        .into_iter()
+        .map(|tag| {
+            let spent = spent.get(&tag.id).cloned().unwrap_or_default();
+            Budget::new(tag, &spent, locale, settings.currency.as_deref())
+        })
+        .collect();
+
+    Ok(HtmlTemplate(TagList {
+        username: user.username,
+        locale,
+        tags,
+    }))
+}
+
+// Get /tags/:id
+pub(crate) async fn show_tag(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    locale: Locale,
+    Path(id): Path<i32>,
+) -> Result<HtmlTemplate<TagDetails>, Error> {
+    let tag = crud::tags::fetch_tag(&db, user.id, id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let activity = recent_activity(&db, &user, locale, AuditEntity::Tag, id).await?;
+    let settings = crud::settings::fetch_settings(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let currency = settings.currency.as_deref();
+    let period_start = settings.period_start(Utc::today().naive_utc());
+    let transactions = crud::transactions::fetch_tag_transactions(&db, user.id, id, period_start)
+        .await
+        .map_err(Error::HtmlError)?;
+    let spent: BigDecimal = transactions.iter().map(|t| &t.amount).sum();
+    let transactions = transactions
+        .into_iter()
+        .map(|transaction| TagTransaction::new(transaction, locale, currency))
+        .collect();
+
+    Ok(HtmlTemplate(TagDetails {
+        tag: Budget::new(tag, &spent, locale, currency),
+        period_start: locale.date(period_start),
+        activity,
+        transactions,
+        username: user.username,
+        locale,
+    }))
+}
+
+// Get /tags/new
+pub(crate) async fn new_tag(
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    CsrfToken(csrf_token): CsrfToken,
+) -> HtmlTemplate<TagFormPage> {
+    HtmlTemplate(TagFormPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        id: None,
+        form: TagForm::default(),
+        errors: FormErrors::default(),
+    })
+}
+
+// Post /tags/new
+pub(crate) async fn create_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    client: ClientInfo,
+    CsrfToken(csrf_token): CsrfToken,
+    Form(form): Form<TagForm>,
+) -> Result<Response, Error> {
+    let errors = match form.to_create() {
+        Ok(tag) => match crud::tags::create_tag(&db, user.id, tag, &client).await {
+            Ok(tag) => return Ok(redirect_to(tag.id)),
+            Err(e) => FormErrors::from_error(e, locale)?,
+        },
+        Err(e) => e.into(),
+    };
+
+    let page = TagFormPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        id: None,
+        form,
+        errors,
+    };
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(page)).into_response())
+}
+
+// Get /tags/:id/edit
+pub(crate) async fn edit_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(id): Path<i32>,
+) -> Result<HtmlTemplate<TagFormPage>, Error> {
+    let tag = crud::tags::fetch_tag(&db, user.id, id)
+        .await
+        .map_err(Error::HtmlError)?;
+
+    Ok(HtmlTemplate(TagFormPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        id: Some(id),
+        form: TagForm {
+            name: tag.name,
+            description: tag.description.unwrap_or_default(),
+            limit: tag.limit.map(|limit| limit.to_string()).unwrap_or_default(),
+            version: Some(tag.version),
+            ..TagForm::default()
+        },
+        errors: FormErrors::default(),
+    }))
+}
+
+// Post /tags/:id/edit
+pub(crate) async fn update_tag(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    client: ClientInfo,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(id): Path<i32>,
+    Form(form): Form<TagForm>,
+) -> Result<Response, Error> {
+    // Without a version the form can only have been changed by hand, so it's treated as a conflict
+    let if_match = EntityTags::version(form.version.unwrap_or_default());
+    let errors = match form.to_update() {
+        Ok(update) => {
+            match crud::tags::update_tag(&db, user.id, id, update, &if_match, &client).await {
+                Ok(_) => return Ok(redirect_to(id)),
+                Err(e) => FormErrors::from_error(e, locale)?,
+            }
+        }
+        Err(e) => e.into(),
+    };
+
+    let page = TagFormPage {
+        username: user.username,
+        locale,
+        csrf_token,
+        id: Some(id),
+        form,
+        errors,
+    };
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(page)).into_response())
+}
+
+fn redirect_to(id: i32) -> Response {
+    let uri = format!("/tags/{}", id)
+        .parse::<Uri>()
+        .expect("The path of a tag to be a valid uri");
+    Redirect::to(uri).into_response()
+}
+
+#[derive(Template)]
+#[template(path = "tags/list.html")]
+pub(crate) struct TagList {
+    username: String,
+    locale: Locale,
+    tags: Vec<Budget>,
+}
+
+#[derive(Template)]
+#[template(path = "tags/details.html")]
+pub(crate) struct TagDetails {
+    username: String,
+    locale: Locale,
+    tag: Budget,
+    /// Newest first
+    activity: Vec<Activity>,
+    /// The first day of the budget period
+    period_start: String,
+    /// The ones of this budget period, newest first
+    transactions: Vec<TagTransaction>,
+}
+
+#[derive(Template)]
+#[template(path = "tags/form.html")]
+pub(crate) struct TagFormPage {
+    username: String,
+    locale: Locale,
+    csrf_token: String,
+    /// The tag that is being edited, `None` when a new one is being created
+    id: Option<i32>,
+    form: TagForm,
+    errors: FormErrors,
+}
+
+/// A transaction with a tag, shown on the page of the tag
+struct TagTransaction {
+    happened_on: String,
+    source_id: i32,
+    source: String,
+    destination_id: i32,
+    destination: String,
+    description: String,
+    amount: String,
+}
+
+impl TagTransaction {
+    fn new(transaction: TransactionDetails, locale: Locale, currency: Option<&str>) -> Self {
+        Self {
+            happened_on: locale.date(transaction.happened_on),
+            source_id: transaction.source_id,
+            source: transaction.source_name,
+            destination_id: transaction.destination_id,
+            destination: transaction.destination_name,
+            description: transaction.description.unwrap_or_default(),
+            amount: locale.money(&transaction.amount, currency),
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use {
+    askama::Template,
+    axum::{
+        extract::{Extension, Path},
+        http::{StatusCode, Uri},
+        response::{IntoResponse, Redirect, Response},
+        routing::{get, post},
+        Router,
+    },
+    bigdecimal::BigDecimal,
+    serde::de::IgnoredAny,
+    sqlx::PgPool,
+};
+
+use super::FormErrors;
+use crate::{
+    crud,
+    extract::{CsrfToken, Form},
+    html_template::HtmlTemplate,
+    i18n::Locale,
+    models::{
+        audit::ClientInfo,
+        transaction::TransactionDetails,
+        user::{RequirePermission, UserClaims, Write},
+    },
+    requests::TransactionForm,
+    Error,
+};
+
+/// How many transactions the register of an account shows
+const REGISTER_LENGTH: u64 = 50;
+
+pub(crate) fn routes() -> Router {
+    Router::new()
+        .route("/transactions/new", get(new_transaction))
+        .route(
+            "/accounts/:id/register",
+            get(show_register).post(create_transaction),
+        )
+        .route(
+            "/accounts/:id/register/:transaction_id/delete",
+            post(delete_transaction),
+        )
+}
+
+// Get /transactions/new
+/// The register of the default account from the settings, or the accounts to pick one from
+pub(crate) async fn new_transaction(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+) -> Result<Redirect, Error> {
+    let settings = crud::settings::fetch_settings(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let uri = match settings.default_account_id {
+        Some(id) => format!("/accounts/{}/register", id)
+            .parse::<Uri>()
+            .expect("The path of a register to be a valid uri"),
+        None => Uri::from_static("/accounts"),
+    };
+    Ok(Redirect::to(uri))
+}
+
+// Get /accounts/:id/register
+pub(crate) async fn show_register(
+    Extension(db): Extension<PgPool>,
+    user: UserClaims,
+    locale: Locale,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(id): Path<i32>,
+) -> Result<HtmlTemplate<Register>, Error> {
+    let form = TransactionForm::default();
+    let page = register(&db, user, locale, csrf_token, id, form).await?;
+    Ok(HtmlTemplate(page))
+}
+
+// Post /accounts/:id/register
+pub(crate) async fn create_transaction(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    locale: Locale,
+    client: ClientInfo,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(id): Path<i32>,
+    Form(form): Form<TransactionForm>,
+) -> Result<Response, Error> {
+    let accounts = crud::accounts::fetch_accounts(&db, &user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let tags = crud::tags::fetch_tags(&db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+
+    let errors = match form.to_create(id, &accounts, &tags) {
+        // Sent twice is fine, the second one finds the transaction of the first one
+        Ok(new) => match crud::transactions::save_entry(&db, user.id, new, &client).await {
+            Ok(_) => return Ok(redirect_to(id)),
+            Err(e) => FormErrors::from_error(e, locale)?,
+        },
+        Err(e) => e.into(),
+    };
+
+    let mut page = register(&db, user, locale, csrf_token, id, form).await?;
+    page.errors = errors;
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, HtmlTemplate(page)).into_response())
+}
+
+// Post /accounts/:id/register/:transaction_id/delete
+pub(crate) async fn delete_transaction(
+    Extension(db): Extension<PgPool>,
+    RequirePermission { user, .. }: RequirePermission<Write>,
+    client: ClientInfo,
+    Path((id, transaction_id)): Path<(i32, i32)>,
+    Form(_): Form<IgnoredAny>,
+) -> Result<Response, Error> {
+    crud::transactions::delete_transaction(&db, user.id, transaction_id, &client)
+        .await
+        .map_err(Error::HtmlError)?;
+    Ok(redirect_to(id))
+}
+
+/// The register of an account with `form` in its entry form
+async fn register(
+    db: &PgPool,
+    user: UserClaims,
+    locale: Locale,
+    csrf_token: String,
+    id: i32,
+    form: TransactionForm,
+) -> Result<Register, Error> {
+    let account = crud::accounts::fetch_account(db, &user.id, id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let transactions =
+        crud::transactions::fetch_account_transactions(db, user.id, id, REGISTER_LENGTH)
+            .await
+            .map_err(Error::HtmlError)?;
+    let payees = crud::accounts::fetch_adhoc_accounts(db, &user.id)
+        .await
+        .map_err(Error::HtmlError)?
+        .into_iter()
+        .map(|account| account.name)
+        .collect();
+    let tags = crud::tags::fetch_tags(db, user.id)
+        .await
+        .map_err(Error::HtmlError)?
+        .into_iter()
+        .map(|tag| tag.name)
+        .collect();
+    let settings = crud::settings::fetch_settings(db, user.id)
+        .await
+        .map_err(Error::HtmlError)?;
+    let currency = settings.currency.as_deref();
+
+    // Walking back from the current money, the balance before a transaction is the one after it minus its amount
+    let mut balance = account.total_money.clone();
+    let entries = transactions
+        .into_iter()
+        .map(|transaction| {
+            let entry = RegisterEntry::new(transaction, id, balance.as_ref(), locale, currency);
+            if let Some(balance) = balance.as_mut() {
+                *balance -= &entry.delta;
+            }
+            entry
+        })
+        .collect();
+
+    Ok(Register {
+        username: user.username,
+        locale,
+        csrf_token,
+        id,
+        name: account.name,
+        is_adhoc: account.is_adhoc,
+        total_money: account
+            .total_money
+            .as_ref()
+            .map(|money| locale.money(money, currency))
+            .unwrap_or_default(),
+        entries,
+        payees,
+        tags,
+        form,
+        errors: FormErrors::default(),
+    })
+}
+
+fn redirect_to(id: i32) -> Response {
+    let uri = format!("/accounts/{}/register", id)
+        .parse::<Uri>()
+        .expect("The path of a register to be a valid uri");
+    Redirect::to(uri).into_response()
+}
+
+#[derive(Template)]
+#[template(path = "accounts/register.html")]
+pub(crate) struct Register {
+    username: String,
+    locale: Locale,
+    csrf_token: String,
+    id: i32,
+    name: String,
+    is_adhoc: bool,
+    total_money: String,
+    /// Newest first
+    entries: Vec<RegisterEntry>,
+    /// Names of the third party accounts, suggested for the payee
+    payees: Vec<String>,
+    /// Names of the tags, suggested for the tags
+    tags: Vec<String>,
+    form: TransactionForm,
+    errors: FormErrors,
+}
+
+/// A line of the register of an account
+struct RegisterEntry {
+    transaction_id: i32,
+    happened_on: String,
+    /// The account on the other side of the transaction
+    payee_id: i32,
+    payee: String,
+    description: String,
+    tags: String,
+    /// How much the money of the account changed, negative when it went out of it
+    delta: BigDecimal,
+    amount: String,
+    /// The money of the account after the transaction, third party accounts don't have one
+    balance: Option<String>,
+}
+
+impl RegisterEntry {
+    fn new(
+        transaction: TransactionDetails,
+        account_id: i32,
+        balance: Option<&BigDecimal>,
+        locale: Locale,
+        currency: Option<&str>,
+    ) -> Self {
+        let (payee_id, payee, delta) = match transaction.destination_id == account_id {
+            true => (
+                transaction.source_id,
+                transaction.source_name,
+                transaction.amount,
+            ),
+            false => (
+                transaction.destination_id,
+                transaction.destination_name,
+                -transaction.amount,
+            ),
+        };
+        Self {
+            transaction_id: transaction.id,
+            happened_on: locale.date(transaction.happened_on),
+            payee_id,
+            payee,
+            description: transaction.description.unwrap_or_default(),
+            tags: transaction.tags.join(", "),
+            amount: locale.money(&delta, currency),
+            delta,
+            balance: balance.map(|balance| locale.money(balance, currency)),
+        }
+    }
+}
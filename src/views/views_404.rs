@@ -1,14 +1,20 @@
 use askama::Template;
 
+use crate::{i18n::Locale, middleware::current_locale};
+
 #[derive(Template)]
 #[template(path = "404.html")]
 pub(crate) struct View404 {
     pub(crate) username: String,
+    pub(crate) locale: Locale,
 }
 
 impl View404 {
     pub(crate) fn new(username: Option<&str>) -> Self {
         let username = username.map_or_else(String::new, String::from);
-        Self { username }
+        Self {
+            username,
+            locale: current_locale(),
+        }
     }
 }
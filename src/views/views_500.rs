@@ -1,14 +1,40 @@
-use askama::Template;
+use {askama::Template, axum::http::StatusCode};
+
+use crate::{
+    i18n::Locale,
+    middleware::{current_locale, RequestId},
+};
 
 #[derive(Template)]
 #[template(path = "500.html")]
 pub(crate) struct View500 {
     pub(crate) username: String,
+    pub(crate) locale: Locale,
+    pub(crate) status: u16,
+    /// What went wrong, only shown for errors the user caused
+    pub(crate) message: Option<String>,
+    /// The id of the request in the logs
+    pub(crate) request_id: Option<String>,
 }
 
 impl View500 {
     pub(crate) fn new(username: Option<&str>) -> Self {
+        Self::error(
+            username,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            String::default(),
+        )
+    }
+
+    /// The page for an error with `status`, the message is left out for server errors
+    pub(crate) fn error(username: Option<&str>, status: StatusCode, message: String) -> Self {
         let username = username.map_or_else(String::new, String::from);
-        Self { username }
+        Self {
+            username,
+            locale: current_locale(),
+            status: status.as_u16(),
+            message: (!status.is_server_error() && !message.is_empty()).then_some(message),
+            request_id: RequestId::current().map(|id| id.0),
+        }
     }
 }
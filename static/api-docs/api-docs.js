@@ -0,0 +1,131 @@
+// Renders the OpenAPI document of the api, grouped by tag
+(function () {
+  'use strict';
+
+  const METHOD_CLASSES = {
+    get: 'badge-primary',
+    post: 'badge-success',
+    put: 'badge-warning',
+    patch: 'badge-warning',
+    delete: 'badge-danger',
+  };
+
+  function escape(text) {
+    return $('<div>').text(String(text)).html();
+  }
+
+  // Turn a schema into a short, readable description of its shape
+  function describe(schema, doc, depth) {
+    if (!schema) {
+      return 'any';
+    }
+    if (depth > 6) {
+      return '...';
+    }
+    if (schema.$ref) {
+      const name = schema.$ref.split('/').pop();
+      return describe(doc.components.schemas[name], doc, depth + 1);
+    }
+    const variants = schema.oneOf || schema.anyOf || schema.allOf;
+    if (variants) {
+      return variants.map(s => describe(s, doc, depth + 1)).join(schema.allOf ? ' & ' : ' | ');
+    }
+    if (schema.enum) {
+      return schema.enum.map(v => JSON.stringify(v)).join(' | ');
+    }
+    if (schema.type === 'array' || (Array.isArray(schema.type) && schema.type.includes('array'))) {
+      return '[' + describe(schema.items, doc, depth + 1) + ']';
+    }
+    if (schema.properties) {
+      const required = schema.required || [];
+      const indent = '  '.repeat(depth + 1);
+      const fields = Object.entries(schema.properties).map(([name, field]) => {
+        const optional = required.includes(name) ? '' : '?';
+        return indent + name + optional + ': ' + describe(field, doc, depth + 1);
+      });
+      return '{\n' + fields.join(',\n') + '\n' + '  '.repeat(depth) + '}';
+    }
+    const type = Array.isArray(schema.type) ? schema.type.join(' | ') : schema.type || 'object';
+    return schema.format ? type + ' (' + schema.format + ')' : type;
+  }
+
+  function renderParameters(operation, doc) {
+    if (!operation.parameters || operation.parameters.length === 0) {
+      return '';
+    }
+    const rows = operation.parameters.map(p => `
+      <tr>
+        <td><code>${escape(p.name)}</code>${p.required ? ' *' : ''}</td>
+        <td>${escape(p.in)}</td>
+        <td>${escape(describe(p.schema, doc, 0))}</td>
+        <td>${escape(p.description || '')}</td>
+      </tr>`);
+    return `
+      <h6>Parameters</h6>
+      <table class="table table-sm">
+        <thead><tr><th>Name</th><th>In</th><th>Type</th><th>Description</th></tr></thead>
+        <tbody>${rows.join('')}</tbody>
+      </table>`;
+  }
+
+  function renderBody(content, doc) {
+    if (!content) {
+      return '';
+    }
+    return Object.entries(content).map(([type, media]) => `
+      <small class="text-muted">${escape(type)}</small>
+      <pre class="schema">${escape(describe(media.schema, doc, 0))}</pre>`).join('');
+  }
+
+  function renderOperation(path, method, operation, doc) {
+    const id = 'op-' + operation.operationId;
+    const isPublic = operation.security && operation.security.every(s => Object.keys(s).length === 0);
+    const responses = Object.entries(operation.responses || {}).map(([status, response]) => `
+      <li>
+        <strong>${escape(status)}</strong> ${escape(response.description || '')}
+        ${renderBody(response.content, doc)}
+      </li>`);
+    const requestBody = operation.requestBody
+      ? '<h6>Request body</h6>' + renderBody(operation.requestBody.content, doc)
+      : '';
+
+    return `
+      <div class="card operation">
+        <div class="card-header" data-toggle="collapse" data-target="#${id}">
+          <span class="badge method ${METHOD_CLASSES[method] || 'badge-secondary'}">${escape(method)}</span>
+          <code>${escape(path)}</code>
+          ${isPublic ? '<span class="badge badge-light float-right">no login needed</span>' : ''}
+        </div>
+        <div id="${id}" class="collapse">
+          <div class="card-body">
+            ${operation.description ? `<p>${escape(operation.description)}</p>` : ''}
+            ${renderParameters(operation, doc)}
+            ${requestBody}
+            <h6>Responses</h6>
+            <ul class="list-unstyled">${responses.join('')}</ul>
+          </div>
+        </div>
+      </div>`;
+  }
+
+  function render(doc) {
+    $('#api-version').text(doc.info.version);
+
+    const byTag = {};
+    Object.entries(doc.paths).forEach(([path, item]) => {
+      Object.entries(item).forEach(([method, operation]) => {
+        const tag = (operation.tags && operation.tags[0]) || 'other';
+        (byTag[tag] = byTag[tag] || []).push(renderOperation(path, method, operation, doc));
+      });
+    });
+
+    const sections = Object.entries(byTag).map(([tag, operations]) => `
+      <h4 class="mt-4 text-capitalize">${escape(tag)}</h4>
+      ${operations.join('')}`);
+    $('#operations').html(sections.join(''));
+  }
+
+  $.getJSON('/api/v1/openapi.json')
+    .done(render)
+    .fail(() => $('#operations').html('<p class="text-danger">Failed to load the OpenAPI document</p>'));
+})();
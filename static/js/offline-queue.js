@@ -0,0 +1,167 @@
+// Keeps the transactions that were entered without a connection on this device and sends them
+// to `/api/v1/transactions/sync` once the connection is back. The ones the server can't save
+// anymore are kept as conflicts and listed on every page, to be fixed in their register or discarded.
+(function () {
+  'use strict';
+
+  var status = document.getElementById('offline-queue');
+  // Every user of the browser has their own queue, so entries are only ever sent as the one who made them
+  var user = encodeURIComponent(status.dataset.user);
+  var QUEUE = 'budgetman.offline-queue.' + user;
+  var CONFLICTS = 'budgetman.offline-conflicts.' + user;
+  // The most entries the server takes in one sync
+  var SYNC_LENGTH = 100;
+
+  if ('serviceWorker' in navigator) {
+    navigator.serviceWorker.register('/static/sw.js', { scope: '/' });
+  }
+
+  function load(key) {
+    try {
+      return JSON.parse(localStorage.getItem(key)) || [];
+    } catch (e) {
+      return [];
+    }
+  }
+
+  function store(key, entries) {
+    localStorage.setItem(key, JSON.stringify(entries));
+  }
+
+  function without(entries, clientId) {
+    return entries.filter(function (entry) { return entry.client_id !== clientId; });
+  }
+
+  var syncing = false;
+
+  function sync() {
+    var queue = load(QUEUE);
+    if (syncing || !queue.length || !navigator.onLine) {
+      return;
+    }
+    syncing = true;
+
+    var entries = queue.slice(0, SYNC_LENGTH);
+    fetch('/api/v1/transactions/sync', {
+      method: 'POST',
+      credentials: 'same-origin',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ entries: entries })
+    })
+      .then(function (response) {
+        // Not logged in anymore or a server error, the entries wait for the next try
+        if (!response.ok) {
+          throw new Error('Sync failed with ' + response.status);
+        }
+        return response.json();
+      })
+      .then(function (body) {
+        // Read again, more entries could have been queued in the meantime
+        var queue = load(QUEUE);
+        var conflicts = load(CONFLICTS);
+        body.results.forEach(function (result) {
+          var entry = entries.filter(function (e) { return e.client_id === result.client_id; })[0];
+          queue = without(queue, result.client_id);
+          if (result.status === 'conflict' && entry) {
+            entry.errors = result.errors || [];
+            conflicts = without(conflicts, entry.client_id).concat([entry]);
+          }
+        });
+        store(QUEUE, queue);
+        store(CONFLICTS, conflicts);
+        syncing = false;
+        render();
+        // The rest of a long queue
+        if (queue.length && body.results.length) {
+          sync();
+        }
+      })
+      .catch(function () {
+        syncing = false;
+        render();
+      });
+  }
+
+  function text(tag, className, content) {
+    var element = document.createElement(tag);
+    element.className = className;
+    element.textContent = content;
+    return element;
+  }
+
+  // What is waiting to be sent and what couldn't be saved, above the content of the page
+  function render() {
+    var queue = load(QUEUE);
+    var conflicts = load(CONFLICTS);
+    status.innerHTML = '';
+
+    if (queue.length) {
+      var waiting = text('div', 'alert alert-info', status.dataset.waiting + ' ' + queue.length);
+      if (!navigator.onLine) {
+        waiting.appendChild(text('span', 'd-block', status.dataset.offline));
+      }
+      status.appendChild(waiting);
+    }
+
+    if (conflicts.length) {
+      var report = text('div', 'alert alert-warning', '');
+      report.appendChild(text('p', 'mb-2', status.dataset.conflicts));
+      var list = text('ul', 'list-unstyled mb-0', '');
+      conflicts.forEach(function (entry) {
+        var item = text('li', 'mb-2', '');
+        item.appendChild(text('strong', '', [entry.happened_on, entry.payee, entry.amount].join(' · ')));
+        (entry.errors || []).forEach(function (error) {
+          item.appendChild(text('span', 'd-block', error.field + ': ' + error.messages.join(', ')));
+        });
+
+        var fix = text('a', 'btn btn-sm btn-light mr-1', status.dataset.fix);
+        fix.href = '/accounts/' + entry.account_id + '/register?retry=' + encodeURIComponent(entry.client_id);
+        var discard = text('button', 'btn btn-sm btn-outline-dark', status.dataset.discard);
+        discard.type = 'button';
+        discard.addEventListener('click', function () {
+          store(CONFLICTS, without(load(CONFLICTS), entry.client_id));
+          render();
+        });
+        item.appendChild(fix);
+        item.appendChild(discard);
+        list.appendChild(item);
+      });
+      report.appendChild(list);
+      status.appendChild(report);
+    }
+  }
+
+  // The ids that make sending an entry twice save it only once
+  function newId() {
+    if (window.crypto && crypto.randomUUID) {
+      return crypto.randomUUID();
+    }
+    var bytes = crypto.getRandomValues(new Uint8Array(16));
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    var hex = Array.prototype.map.call(bytes, function (b) { return (b + 0x100).toString(16).slice(1); }).join('');
+    return [hex.slice(0, 8), hex.slice(8, 12), hex.slice(12, 16), hex.slice(16, 20), hex.slice(20)].join('-');
+  }
+
+  // Used by the entry form of the registers and the quick-add box
+  window.offlineQueue = {
+    newId: newId,
+    add: function (entry) {
+      store(QUEUE, without(load(QUEUE), entry.client_id).concat([entry]));
+      render();
+    },
+    conflict: function (clientId) {
+      return load(CONFLICTS).filter(function (entry) { return entry.client_id === clientId; })[0];
+    },
+    resolve: function (clientId) {
+      store(CONFLICTS, without(load(CONFLICTS), clientId));
+    }
+  };
+
+  window.addEventListener('online', sync);
+  window.addEventListener('offline', render);
+  // Another tab could have queued or synced something
+  window.addEventListener('storage', render);
+  render();
+  sync();
+})();
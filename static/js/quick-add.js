@@ -0,0 +1,180 @@
+// The quick-add box of every page: Ctrl+K opens it, every change of the line is previewed by the server
+// and Enter saves it once nothing is unclear. Without scripts the button goes to the `/quick-add` page instead.
+(function () {
+  'use strict';
+
+  var form = document.getElementById('quick-add-form');
+  if (!form) {
+    return;
+  }
+
+  var modal = $('#quick-add');
+  var input = document.getElementById('quick-add-line');
+  var output = document.getElementById('quick-add-preview');
+  var texts = form.dataset;
+  var clientId = window.offlineQueue.newId();
+  var preview = null;
+  // Only the answer to the latest line is shown, older ones can arrive after it
+  var asked = 0;
+  var timer;
+
+  document.addEventListener('keydown', function (event) {
+    if ((event.ctrlKey || event.metaKey) && event.key.toLowerCase() === 'k') {
+      event.preventDefault();
+      modal.modal('show');
+    }
+  });
+
+  modal.on('shown.bs.modal', function () {
+    input.focus();
+    input.select();
+  });
+
+  function element(tag, className, content) {
+    var created = document.createElement(tag);
+    created.className = className;
+    created.textContent = content || '';
+    return created;
+  }
+
+  function show(className, message) {
+    output.innerHTML = '';
+    output.appendChild(element('div', className, message));
+  }
+
+  // A name with spaces has to be in quotes to be read as one
+  function named(sigil, name) {
+    return sigil + (/\s/.test(name) ? '"' + name + '"' : name);
+  }
+
+  // Puts the picked name in place of the one that was typed, and previews the line again
+  function pick(ambiguity, candidate) {
+    var sigil = ambiguity.field === 'account' ? '@' : '#';
+    var typed = ambiguity.text.replace(/[.*+?^${}()|[\]\\]/g, '\\$&');
+    var pattern = new RegExp(sigil + '"?' + typed + '"?(?=\\s|$)', 'i');
+    input.value = input.value.replace(pattern, named(sigil, candidate));
+    input.focus();
+    ask();
+  }
+
+  function describe(ambiguity) {
+    var item = element('li', '', texts[ambiguity.field] + (ambiguity.text ? ' "' + ambiguity.text + '"' : '') + ' ' + texts[ambiguity.kind]);
+    if (ambiguity.kind === 'several') {
+      ambiguity.candidates.forEach(function (candidate) {
+        var button = element('button', 'btn btn-xs btn-outline-dark ml-1', candidate);
+        button.type = 'button';
+        button.addEventListener('click', function () { pick(ambiguity, candidate); });
+        item.appendChild(button);
+      });
+    } else if (ambiguity.candidates) {
+      item.appendChild(document.createTextNode(': ' + ambiguity.candidates.join(', ')));
+    }
+    return item;
+  }
+
+  function render() {
+    output.innerHTML = '';
+    if (!preview) {
+      return;
+    }
+
+    var rows = [
+      [texts.money, preview.incoming ? texts.in : texts.out],
+      [texts.amount, preview.amount],
+      [texts.account, preview.account && preview.account.name],
+      [texts.payee, preview.payee && (preview.payee + (preview.new_payee ? ' (' + texts.new_payee + ')' : ''))],
+      [texts.tags, preview.tags.map(function (tag) { return tag.name; }).join(', ')],
+      [texts.happened_on, preview.happened_on],
+      [texts.description, preview.description]
+    ];
+    var list = element('dl', 'row mb-0 small');
+    rows.filter(function (row) { return row[1]; }).forEach(function (row) {
+      list.appendChild(element('dt', 'col-4', row[0]));
+      list.appendChild(element('dd', 'col-8 mb-1', row[1]));
+    });
+    output.appendChild(list);
+
+    if (preview.ambiguities.length) {
+      var problems = element('ul', 'alert alert-warning mb-0 mt-2 pl-4');
+      preview.ambiguities.forEach(function (ambiguity) { problems.appendChild(describe(ambiguity)); });
+      output.appendChild(problems);
+    } else {
+      output.appendChild(element('p', 'text-muted mb-0 mt-2', texts.ready));
+    }
+  }
+
+  function ask() {
+    clearTimeout(timer);
+    var line = input.value.trim();
+    preview = null;
+    if (!line) {
+      render();
+      return;
+    }
+    if (!navigator.onLine) {
+      show('text-muted', texts.offline);
+      return;
+    }
+
+    var number = ++asked;
+    fetch('/api/v1/transactions/parse', {
+      method: 'POST',
+      credentials: 'same-origin',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ line: line })
+    })
+      .then(function (response) { return response.ok ? response.json() : null; })
+      .then(function (body) {
+        if (number === asked) {
+          preview = body;
+          render();
+        }
+      })
+      .catch(function () { show('text-muted', texts.offline); });
+  }
+
+  input.addEventListener('input', function () {
+    clearTimeout(timer);
+    timer = setTimeout(ask, 250);
+  });
+
+  form.addEventListener('submit', function (event) {
+    event.preventDefault();
+    if (!preview || preview.ambiguities.length || !input.value.trim()) {
+      // The first choice to make, or the line to finish
+      var next = output.querySelector('button') || input;
+      next.focus();
+      return;
+    }
+
+    var account = preview.account.id;
+    fetch('/api/v1/transactions/quick-add', {
+      method: 'POST',
+      credentials: 'same-origin',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ line: input.value, client_id: clientId })
+    })
+      .then(function (response) {
+        return response.json().then(function (body) {
+          if (!response.ok) {
+            var reasons = (body.errors || []).map(function (error) { return error.messages.join(', '); });
+            throw new Error(reasons.join('; ') || body.detail);
+          }
+        });
+      })
+      .then(function () {
+        // Ready for the next one
+        clientId = window.offlineQueue.newId();
+        input.value = '';
+        preview = null;
+        show('alert alert-success mb-0', texts.saved);
+        input.focus();
+        if (window.location.pathname === '/accounts/' + account + '/register') {
+          window.location.reload();
+        }
+      })
+      .catch(function (error) {
+        show('alert alert-danger mb-0', texts.failed + ' ' + error.message);
+      });
+  });
+})();
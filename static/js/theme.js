@@ -0,0 +1,19 @@
+// Applies the theme from the settings of the user before the page is drawn, so it doesn't flash.
+// It has to be loaded right after `<body>` without `defer`.
+(function () {
+  'use strict';
+
+  var match = document.cookie.match(/(?:^|;\s*)theme=(\w+)/);
+  var theme = match ? match[1] : 'light';
+  var system = window.matchMedia('(prefers-color-scheme: dark)');
+
+  function apply() {
+    var dark = theme === 'dark' || (theme === 'system' && system.matches);
+    document.body.classList.toggle('dark-mode', dark);
+  }
+
+  apply();
+  if (theme === 'system' && system.addEventListener) {
+    system.addEventListener('change', apply);
+  }
+})();
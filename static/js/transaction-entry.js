@@ -0,0 +1,104 @@
+// Makes the quick entry form of a register faster to use, the form works the same without it.
+(function () {
+  'use strict';
+
+  var form = document.getElementById('transaction-entry');
+  if (!form) {
+    return;
+  }
+
+  var payee = form.elements.payee;
+  var tags = form.elements.tags;
+  var tagNames = Array.prototype.map.call(
+    document.querySelectorAll('#tag-names option'),
+    function (option) { return option.value; }
+  );
+  var suggestions = document.getElementById('tag-names');
+
+  // A datalist only completes the whole value of an input, so the suggestions for the tags
+  // are rewritten to complete the tag after the last comma and keep the ones before it.
+  function suggestTags() {
+    var parts = tags.value.split(',');
+    var current = parts.pop().trim().toLowerCase();
+    var chosen = parts.map(function (part) { return part.trim().toLowerCase(); });
+    var prefix = parts.map(function (part) { return part.trim(); }).join(', ');
+    if (prefix) {
+      prefix += ', ';
+    }
+
+    suggestions.innerHTML = '';
+    tagNames
+      .filter(function (name) {
+        var lower = name.toLowerCase();
+        return chosen.indexOf(lower) === -1 && lower.indexOf(current) === 0;
+      })
+      .forEach(function (name) {
+        var option = document.createElement('option');
+        option.value = prefix + name;
+        suggestions.appendChild(option);
+      });
+  }
+
+  tags.addEventListener('input', suggestTags);
+  suggestTags();
+
+  // The payee is the first thing to type, unless a field needs fixing
+  var invalid = form.querySelector('.is-invalid');
+  (invalid || payee).focus();
+
+  var clientId = form.elements.client_id;
+  var notice = document.getElementById('entry-notice');
+
+  function today() {
+    var now = new Date();
+    return [
+      now.getFullYear(),
+      ('0' + (now.getMonth() + 1)).slice(-2),
+      ('0' + now.getDate()).slice(-2)
+    ].join('-');
+  }
+
+  // A form that was sent back to be fixed keeps its id, so it's still saved only once
+  if (!clientId.value) {
+    clientId.value = window.offlineQueue.newId();
+  }
+
+  // An entry that couldn't be synced is put back in the form to be fixed
+  var retry = new URLSearchParams(window.location.search).get('retry');
+  var conflict = retry && window.offlineQueue && window.offlineQueue.conflict(retry);
+  if (conflict) {
+    ['direction', 'payee', 'amount', 'tags', 'description', 'happened_on', 'client_id'].forEach(function (name) {
+      form.elements[name].value = conflict[name];
+    });
+  }
+
+  form.addEventListener('submit', function (event) {
+    if (window.offlineQueue) {
+      window.offlineQueue.resolve(clientId.value);
+    }
+
+    // Without a connection the entry is kept on this device until it can be synced
+    if (!navigator.onLine && window.offlineQueue) {
+      event.preventDefault();
+      window.offlineQueue.add({
+        client_id: clientId.value,
+        account_id: Number(form.dataset.account),
+        direction: form.elements.direction.value,
+        payee: payee.value,
+        amount: form.elements.amount.value,
+        tags: tags.value,
+        description: form.elements.description.value,
+        // An empty date is the day it was entered, not the day it's synced
+        happened_on: form.elements.happened_on.value || today()
+      });
+      form.reset();
+      clientId.value = window.offlineQueue.newId();
+      notice.classList.remove('d-none');
+      payee.focus();
+      return;
+    }
+
+    // Entering several transactions in a row shouldn't send the same one twice
+    form.querySelector('button[type="submit"]').disabled = true;
+  });
+})();
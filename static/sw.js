@@ -0,0 +1,88 @@
+// Keeps the pages that were visited and the files they need, so they still open without a connection.
+// Registered with the scope `/`, which the server allows with the `Service-Worker-Allowed` header.
+'use strict';
+
+// Changing it drops everything that was kept by the older versions
+var CACHE = 'budgetman-v3';
+
+// What every page needs, kept as soon as the worker is installed
+var SHELL = [
+  '/static/adminLTE/plugins/fontawesome-free/css/all.min.css',
+  '/static/adminLTE/css/adminlte.min.css',
+  '/static/adminLTE/plugins/jquery/jquery.min.js',
+  '/static/adminLTE/plugins/bootstrap/js/bootstrap.bundle.min.js',
+  '/static/adminLTE/js/adminlte.min.js',
+  '/static/adminLTE/img/AdminLTELogo.png',
+  '/static/js/theme.js',
+  '/static/js/offline-queue.js',
+  '/static/js/transaction-entry.js',
+  '/static/js/quick-add.js',
+  '/static/manifest.webmanifest'
+];
+
+self.addEventListener('install', function (event) {
+  event.waitUntil(
+    caches.open(CACHE)
+      .then(function (cache) { return cache.addAll(SHELL); })
+      .then(function () { return self.skipWaiting(); })
+  );
+});
+
+self.addEventListener('activate', function (event) {
+  event.waitUntil(
+    caches.keys()
+      .then(function (keys) {
+        return Promise.all(keys
+          .filter(function (key) { return key !== CACHE; })
+          .map(function (key) { return caches.delete(key); }));
+      })
+      .then(function () { return self.clients.claim(); })
+  );
+});
+
+// Pages are always asked from the server first so they are up to date, the kept copy is only used offline
+function networkFirst(request) {
+  return fetch(request)
+    .then(function (response) {
+      // Pages that sent the user to log in belong to nobody, they aren't kept
+      if (response.ok && !response.redirected) {
+        var copy = response.clone();
+        caches.open(CACHE).then(function (cache) { cache.put(request, copy); });
+      }
+      return response;
+    })
+    .catch(function () {
+      // A page that was never visited gets the dashboard, which links to the ones that were
+      return caches.match(request)
+        .then(function (kept) { return kept || caches.match('/'); })
+        .then(function (kept) { return kept || Response.error(); });
+    });
+}
+
+// The files under `/static` rarely change, they are kept after the first time
+function cacheFirst(request) {
+  return caches.match(request).then(function (kept) {
+    return kept || fetch(request).then(function (response) {
+      if (response.ok) {
+        var copy = response.clone();
+        caches.open(CACHE).then(function (cache) { cache.put(request, copy); });
+      }
+      return response;
+    });
+  });
+}
+
+self.addEventListener('fetch', function (event) {
+  var request = event.request;
+  var url = new URL(request.url);
+  // The api and forms always go to the server, entries made offline are queued by the pages instead
+  if (request.method !== 'GET' || url.origin !== self.location.origin || url.pathname.indexOf('/api/') === 0) {
+    return;
+  }
+
+  if (request.mode === 'navigate') {
+    event.respondWith(networkFirst(request));
+  } else if (url.pathname.indexOf('/static/') === 0) {
+    event.respondWith(cacheFirst(request));
+  }
+});